@@ -0,0 +1,36 @@
+#![feature(test)]
+
+extern crate bencode;
+extern crate test;
+
+use test::Bencher;
+
+/// DHT messages: small dicts that mostly hold integers and bytestrings.
+const MESSAGES: &[&[u8]] = &[
+    b"d1:ad2:id20:abcdefghij0123456789e1:q4:ping1:t2:aa1:y1:qe",
+    b"d1:rd2:id20:mnopqrstuvwxyz123456e1:t2:aa1:y1:re",
+    b"d1:ad2:id20:abcdefghij01234567896:target20:mnopqrstuvwxyz123456e\
+      1:q9:find_node1:t2:aa1:y1:qe",
+    b"d1:eli201e23:A Generic Error Ocurrede1:t2:aa1:y1:ee",
+    b"d4:porti6881e5:tokeni12345e4:name8:abcdefghe",
+];
+
+#[bench]
+fn decode_small_dicts(b: &mut Bencher) {
+    b.iter(|| {
+        for s in MESSAGES {
+            bencode::decode(s).unwrap();
+        }
+    });
+}
+
+/// The same messages through `decode_with_callback`, which never takes the
+/// fast path for scalars, for comparison with `decode_small_dicts`.
+#[bench]
+fn decode_small_dicts_general(b: &mut Bencher) {
+    b.iter(|| {
+        for s in MESSAGES {
+            bencode::decode_with_callback(s, |_, _| {}).unwrap();
+        }
+    });
+}
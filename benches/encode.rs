@@ -0,0 +1,42 @@
+#![feature(test)]
+
+extern crate bencode;
+extern crate test;
+
+use std::collections::HashMap;
+
+use bencode::Bencoded::*;
+use test::Bencher;
+
+/// A dict with thousands of short keys, where most of the work is writing
+/// length prefixes.
+fn many_keys() -> bencode::Bencoded {
+    let mut m = HashMap::new();
+    for i in 0..5000 {
+        m.insert(format!("k{}", i).into_bytes(), Integer(i));
+    }
+    Dict(m)
+}
+
+#[bench]
+fn to_bytes_many_keys(b: &mut Bencher) {
+    let d = many_keys();
+    b.iter(|| d.to_bytes());
+}
+
+#[bench]
+fn encode_to_many_keys(b: &mut Bencher) {
+    let d = many_keys();
+    let mut v = Vec::new();
+    b.iter(|| {
+        v.clear();
+        d.encode_to(&mut v).unwrap();
+    });
+}
+
+#[bench]
+fn to_bytes_cached_many_keys(b: &mut Bencher) {
+    let d = many_keys();
+    let mut cache = bencode::EncodeCache::new();
+    b.iter(|| d.to_bytes_cached(&mut cache));
+}
@@ -0,0 +1,92 @@
+//! # bencode-derive
+//!
+//! `#[derive(FromBencoded)]` for the `bencode` crate. Use it through the
+//! `derive` feature of `bencode` rather than depending on this crate directly.
+
+extern crate proc_macro;
+extern crate proc_macro2;
+#[macro_use]
+extern crate quote;
+extern crate syn;
+
+use proc_macro::TokenStream;
+use proc_macro2::Literal;
+use syn::{Data, DeriveInput, Fields, LitStr};
+
+/// Derives `FromBencoded` for a struct with named fields, read from a dict
+/// with one key per field. The key defaults to the field name and can be
+/// changed with `#[bencode(rename = "...")]`.
+#[proc_macro_derive(FromBencoded, attributes(bencode))]
+pub fn derive_from_bencoded(input: TokenStream) -> TokenStream {
+    let input = syn::parse_macro_input!(input as DeriveInput);
+    match expand(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+fn expand(input: &DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let fields = match input.data {
+        Data::Struct(ref data) => match data.fields {
+            Fields::Named(ref fields) => &fields.named,
+            _ => {
+                let msg = "FromBencoded needs a struct with named fields";
+                return Err(syn::Error::new_spanned(input, msg));
+            }
+        },
+        _ => {
+            let msg = "FromBencoded can only be derived for structs";
+            return Err(syn::Error::new_spanned(input, msg));
+        }
+    };
+
+    let mut inits = Vec::new();
+    for field in fields {
+        let ident = field.ident.as_ref().unwrap();
+        let mut key = ident.to_string();
+        for attr in &field.attrs {
+            if !attr.path().is_ident("bencode") {
+                continue;
+            }
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("rename") {
+                    key = meta.value()?.parse::<LitStr>()?.value();
+                    Ok(())
+                } else {
+                    Err(meta.error("unknown bencode attribute"))
+                }
+            })?;
+        }
+
+        let key = Literal::byte_string(key.as_bytes());
+        inits.push(quote! {
+            #ident: ::bencode::FromBencoded::from_field(b.get(#key), #key)?
+        });
+    }
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) =
+        input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::bencode::FromBencoded for #name #ty_generics
+            #where_clause
+        {
+            fn from_bencoded(b: &::bencode::Bencoded)
+                -> ::std::result::Result<Self, ::bencode::ConvertError>
+            {
+                match *b {
+                    ::bencode::Bencoded::Dict(_) |
+                    ::bencode::Bencoded::OrderedDict(_) => {}
+                    _ => {
+                        return Err(::bencode::ConvertError::WrongType {
+                            expected: "dict",
+                        })
+                    }
+                }
+                Ok(#name {
+                    #(#inits,)*
+                })
+            }
+        }
+    })
+}
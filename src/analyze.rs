@@ -0,0 +1,242 @@
+//! Explaining in plain words why input fails to decode.
+
+use std::fmt;
+use std::str;
+
+use {decode, is_canonical, uint_digits, DecodeOptions, ParseError, Parser};
+
+/// The outcome of `analyze`: the error decoding failed with, if any, and a
+/// description of its most likely cause, meant for end users looking at a
+/// corrupt download rather than for programs.
+#[derive(Debug, Eq, PartialEq)]
+pub struct AnalysisReport {
+    /// The error that `decode` fails with, or `None` if the input is valid.
+    pub error: Option<ParseError>,
+
+    /// What is most likely wrong with the input, e.g. `bytestring at offset
+    /// 40 declares 100 bytes but only 12 remain (file truncated?)`.
+    pub summary: String,
+}
+
+impl fmt::Display for AnalysisReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.summary)
+    }
+}
+
+/// Decodes `s` as `decode` would, and describes what went wrong in terms of
+/// its likely cause, such as a truncated file or an HTML error page saved in
+/// place of a torrent. The wording is not stable, and should only be shown
+/// to people.
+pub fn analyze(s: &[u8]) -> AnalysisReport {
+    let mut parser = Parser::new(s, DecodeOptions::default());
+    let res = parser.parse_bencoded(0).and_then(|(_, idx)| {
+        if idx != s.len() {
+            return Err(ParseError::TrailingData { pos: idx });
+        }
+        Ok(())
+    });
+    let err = match res {
+        Ok(()) if is_canonical(s) => {
+            return AnalysisReport { error: None, summary: "valid bencode".to_string() }
+        }
+        Ok(()) => {
+            return AnalysisReport {
+                error: None,
+                summary: "valid bencode, but not in canonical form, so re-encoding it \
+                          will change it".to_string(),
+            }
+        }
+        Err(err) => err,
+    };
+
+    let summary = match err {
+        _ if s.is_empty() => "input is empty".to_string(),
+        ParseError::Incomplete { needed: Some(needed) } => {
+            let (pos, len) = parser.truncated.expect("a bytestring ran past the end");
+            format!("bytestring at offset {} declares {} bytes but only {} remain \
+                     (file truncated?)", pos, len, len - needed)
+        }
+        ParseError::Incomplete { needed: None } => {
+            format!("input ends in the middle of a value after {} bytes (file truncated?)",
+                    s.len())
+        }
+        ParseError::UnexpectedByte { pos: 0, byte } if byte == b'<' || byte == b'{' => {
+            format!("input starts with `{}`, so it looks like HTML or JSON rather than \
+                     bencode (an error page saved in place of the file?)", byte as char)
+        }
+        ParseError::TrailingData { pos } => {
+            format!("a complete value ends at offset {}, but {} more bytes follow \
+                     (several files joined together?)", pos, s.len() - pos)
+        }
+        ParseError::LengthOverflow { pos } => {
+            format!("bytestring at offset {} declares an impossibly large length \
+                     (file corrupt?)", pos)
+        }
+        ref err => format!("{} (file corrupt?)", err),
+    };
+    AnalysisReport { error: Some(err), summary }
+}
+
+/// Tries to fix input in which some bytestrings declare a length one more
+/// than they have, as a buggy encoder is known to write, so that each runs
+/// into the token after it. Returns the corrected input if it then decodes
+/// and is in canonical form, or `None` if not.
+///
+/// This is a best-effort recovery tool rather than a decoder: a length is
+/// taken as declared if the token after the bytestring could come next, and
+/// otherwise one less is tried, so input that is broken in other ways, or
+/// where a wrong length happens to look right, can't be repaired.
+pub fn try_repair(s: &[u8]) -> Option<Vec<u8>> {
+    let mut res = Vec::with_capacity(s.len());
+    if repair_value(s, 0, Follow::End, &mut res)? != s.len() {
+        return None;
+    }
+    if decode(&res).is_err() || !is_canonical(&res) {
+        return None;
+    }
+    Some(res)
+}
+
+/// What may come after a value, for `try_repair` to tell whether a length
+/// is right.
+#[derive(Clone, Copy)]
+enum Follow {
+    /// The value of a dict entry, after its key.
+    Value,
+
+    /// Another element, or the end of the list.
+    ValueOrEnd,
+
+    /// Another dict key, or the end of the dict.
+    KeyOrEnd,
+
+    /// The end of the input.
+    End,
+}
+
+impl Follow {
+    /// Returns whether the token at `idx` looks like it could come next.
+    fn allows(self, s: &[u8], idx: usize) -> bool {
+        let c = match s.get(idx) {
+            Some(&c) => c,
+            None => {
+                return match self {
+                    Follow::End => idx == s.len(),
+                    _ => false,
+                }
+            }
+        };
+        let allowed = match self {
+            Follow::Value => c != b'e',
+            Follow::ValueOrEnd => true,
+            Follow::KeyOrEnd => c == b'e' || c.is_ascii_digit(),
+            Follow::End => false,
+        };
+        allowed && token_start(s, idx)
+    }
+}
+
+/// Returns whether `idx` starts a plausible token: a container's start or
+/// end, an integer up to its `e`, or a length up to its `:`.
+fn token_start(s: &[u8], idx: usize) -> bool {
+    let (digits, end) = match s[idx] {
+        b'l' | b'd' | b'e' => return true,
+        b'i' if s.get(idx + 1) == Some(&b'-') => (idx + 2, b'e'),
+        b'i' => (idx + 1, b'e'),
+        c if c.is_ascii_digit() => (idx, b':'),
+        _ => return false,
+    };
+    match s[digits..].iter().position(|c| !c.is_ascii_digit()) {
+        Some(n) => n > 0 && s[digits + n] == end,
+        None => false,
+    }
+}
+
+/// Copies the value at `idx` to `res`, correcting bytestring lengths as
+/// `try_repair` does, and returns the index just past it.
+fn repair_value(s: &[u8], idx: usize, follow: Follow, res: &mut Vec<u8>) -> Option<usize> {
+    match *s.get(idx)? {
+        b'i' => {
+            let end = idx + s[idx..].iter().position(|&c| c == b'e')? + 1;
+            res.push_all(&s[idx..end]);
+            Some(end)
+        }
+        c @ b'l' | c @ b'd' => {
+            res.push(c);
+            let mut idx = idx + 1;
+            while *s.get(idx)? != b'e' {
+                idx = if c == b'l' {
+                    repair_value(s, idx, Follow::ValueOrEnd, res)?
+                } else {
+                    let idx = repair_value(s, idx, Follow::Value, res)?;
+                    repair_value(s, idx, Follow::KeyOrEnd, res)?
+                };
+            }
+            res.push(b'e');
+            Some(idx + 1)
+        }
+        _ => {
+            let colon = idx + s[idx..].iter().position(|&c| c == b':')?;
+            let len: usize = str::from_utf8(&s[idx..colon]).ok()?.parse().ok()?;
+            let start = colon + 1;
+            let len = if follow.allows(s, start.checked_add(len)?) {
+                len
+            } else if len > 0 && follow.allows(s, start + len - 1) {
+                len - 1
+            } else {
+                return None;
+            };
+            res.push_all(uint_digits(len as u64, &mut [0; 20]));
+            res.push(b':');
+            res.push_all(s.get(start..start + len)?);
+            Some(start + len)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{analyze, try_repair};
+    use ParseError;
+
+    #[test]
+    fn analyze_truncated() {
+        let mut s = b"d4:infod6:pieces100:".to_vec();
+        s.extend(vec!(0; 12));
+        let report = analyze(&s);
+        assert_eq!(report.error, Some(ParseError::Incomplete { needed: Some(88) }));
+        assert_eq!(report.summary, "bytestring at offset 16 declares 100 bytes but only 12 \
+                                    remain (file truncated?)");
+
+        assert_eq!(analyze(b"d4:infod").summary,
+                   "input ends in the middle of a value after 8 bytes (file truncated?)");
+    }
+
+    #[test]
+    fn analyze_other() {
+        assert_eq!(analyze(b"d1:ai1ee").error, None);
+        assert!(analyze(b"d1:bi1e1:ai2ee").summary.contains("not in canonical form"));
+        assert!(analyze(b"<html>").summary.contains("HTML"));
+        assert_eq!(analyze(b"i1ei2e").summary,
+                   "a complete value ends at offset 3, but 3 more bytes follow \
+                    (several files joined together?)");
+        assert_eq!(analyze(b"").summary, "input is empty");
+        assert_eq!(analyze(b"i1x").summary, "unexpected byte 0x78 at offset 2 (file corrupt?)");
+    }
+
+    #[test]
+    fn try_repair_lengths() {
+        assert_eq!(try_repair(b"d6:lengthi1e4:name4:abce"),
+                   Some(b"d6:lengthi1e4:name3:abce".to_vec()));
+        assert_eq!(try_repair(b"l4:abc4:defi1e5:ghiji1ee"),
+                   Some(b"l3:abc3:defi1e4:ghiji1ee".to_vec()));
+        assert_eq!(try_repair(b"4:abc"), Some(b"3:abc".to_vec()));
+        assert_eq!(try_repair(b"d1:ai1ee"), Some(b"d1:ai1ee".to_vec()));
+
+        // off by more than one, or broken some other way
+        assert_eq!(try_repair(b"l5:abce"), None);
+        assert_eq!(try_repair(b"d1:ai1xe"), None);
+        assert_eq!(try_repair(b"d1:bi1e1:ai2ee"), None);
+    }
+}
@@ -0,0 +1,159 @@
+//! A decoded representation allocated in an arena.
+
+use bumpalo::collections::Vec as BumpVec;
+use bumpalo::Bump;
+
+use {Bencoded, DecodeOptions, Int, ParseError, ParseResult, Parser};
+
+/// Like `BencodedRef`, but lists and dicts are slices allocated in a `Bump`
+/// arena rather than `Vec`s and maps of their own, so decoding makes no heap
+/// allocations beyond the arena's chunks, and everything is freed together
+/// when the arena is dropped or reset. Bytestrings and dict keys borrow from
+/// the input, which must live as long as the arena.
+///
+/// Dict entries are kept in the order they appear, duplicates included. Use
+/// `to_owned` to keep a value after the arena is gone.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum BencodedArena<'a> {
+    Integer(Int),
+    Bytestring(&'a [u8]),
+    List(&'a [BencodedArena<'a>]),
+    Dict(&'a [(&'a [u8], BencodedArena<'a>)]),
+}
+
+impl<'a> BencodedArena<'a> {
+    /// Looks up `key` in a dict. If the key is repeated, the last value is
+    /// returned, as `decode` would keep.
+    pub fn get(&self, key: &[u8]) -> Option<&BencodedArena<'a>> {
+        match *self {
+            BencodedArena::Dict(pairs) => {
+                pairs.iter().rev().find(|&&(k, _)| k == key).map(|&(_, ref v)| v)
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the contents of a bytestring, or `None` if this is not one.
+    pub fn as_bytes(&self) -> Option<&'a [u8]> {
+        match *self {
+            BencodedArena::Bytestring(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// Copies the value into a `Bencoded`, which owns all of its data.
+    pub fn to_owned(&self) -> Bencoded {
+        match *self {
+            BencodedArena::Integer(n) => Bencoded::Integer(n),
+            BencodedArena::Bytestring(v) => Bencoded::Bytestring(v.to_vec()),
+            BencodedArena::List(v) => {
+                Bencoded::List(v.iter().map(BencodedArena::to_owned).collect())
+            }
+            BencodedArena::Dict(pairs) => {
+                let map = pairs.iter().map(|&(k, ref v)| (k.to_vec(), v.to_owned()));
+                Bencoded::Dict(map.collect())
+            }
+        }
+    }
+}
+
+impl<'a> Parser<'a> {
+    fn parse_arena(&mut self, idx: usize, arena: &'a Bump)
+                   -> ParseResult<BencodedArena<'a>> {
+        match self.peek(idx)? {
+            b'i' => {
+                let (n, idx) = self.parse_int(idx + 1)?;
+                Ok((BencodedArena::Integer(n), idx))
+            }
+            b'l' => {
+                let mut v = BumpVec::new_in(arena);
+                let mut idx = idx + 1;
+                loop {
+                    match self.peek(idx)? {
+                        b'e' => {
+                            return Ok((BencodedArena::List(v.into_bump_slice()), idx + 1))
+                        }
+                        _ => {
+                            self.add_element(idx)?;
+                            let (elem, idx_) = self.parse_arena(idx, arena)?;
+                            idx = idx_;
+                            v.push(elem);
+                        }
+                    }
+                }
+            }
+            b'd' => {
+                let mut pairs = BumpVec::new_in(arena);
+                let mut idx = idx + 1;
+                loop {
+                    match self.peek(idx)? {
+                        b'e' => {
+                            let pairs = pairs.into_bump_slice();
+                            return Ok((BencodedArena::Dict(pairs), idx + 1));
+                        }
+                        _ => {
+                            self.add_element(idx)?;
+                            let (key, idx_) = self.parse_key(idx)?;
+                            let (val, idx_) = self.parse_arena(idx_, arena)?;
+                            pairs.push((key, val));
+                            idx = idx_;
+                        }
+                    }
+                }
+            }
+            _ => {
+                let (v, idx) = self.parse_slice(idx)?;
+                Ok((BencodedArena::Bytestring(v), idx))
+            }
+        }
+    }
+}
+
+/// Like `decode`, but allocates the lists and dicts of the result in `arena`
+/// and borrows bytestrings and dict keys from `s`. This suits decoding many
+/// values in a batch, resetting the arena in between.
+pub fn decode_in<'a>(s: &'a [u8], arena: &'a Bump)
+                     -> Result<&'a BencodedArena<'a>, ParseError> {
+    let mut parser = Parser::new(s, DecodeOptions::default());
+    let (val, idx) = parser.parse_arena(0, arena)?;
+    if idx != s.len() {
+        return Err(ParseError::TrailingData { pos: idx });
+    }
+    Ok(arena.alloc(val))
+}
+
+#[cfg(test)]
+mod tests {
+    use bumpalo::Bump;
+
+    use super::{decode_in, BencodedArena};
+    use {decode, ParseError};
+
+    #[test]
+    fn decode_in_arena() {
+        let arena = Bump::new();
+        let s = b"d3:fool3:bari42ee1:ai1e1:ai2ee";
+        let val = decode_in(s, &arena).unwrap();
+        match *val.get(b"foo").unwrap() {
+            BencodedArena::List(v) => {
+                assert_eq!(v, &[BencodedArena::Bytestring(b"bar"),
+                                BencodedArena::Integer(42)]);
+                // bytestrings point into the input
+                assert_eq!(v[0].as_bytes().unwrap().as_ptr(), s[9..].as_ptr());
+            }
+            _ => panic!("expected a list"),
+        }
+        assert_eq!(val.get(b"a"), Some(&BencodedArena::Integer(2)));
+        assert_eq!(decode_in(b"i1ei2e", &arena), Err(ParseError::TrailingData { pos: 3 }));
+    }
+
+    #[test]
+    fn to_owned() {
+        let arena = Bump::new();
+        let inputs: Vec<&[u8]> = vec!(b"i-1e", b"3:foo", b"le",
+                                      b"d3:fool3:bari42ee4:spamd0:0:ee");
+        for s in inputs {
+            assert_eq!(decode_in(s, &arena).unwrap().to_owned(), decode(s).unwrap());
+        }
+    }
+}
@@ -0,0 +1,326 @@
+//! Decoded representations that borrow from their input.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use {Bencoded, DecodeOptions, Int, ParseError, ParseResult, Parser};
+
+/// Like `Bencoded`, but bytestrings and dict keys borrow from the input they
+/// were decoded from instead of being copied.
+///
+/// Each bytestring is a `Cow`, so individual values can still be replaced by
+/// owned data, e.g. after editing them. Use `into_owned` to detach the whole
+/// tree from the input.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum BencodedRef<'a> {
+    Integer(Int),
+    Bytestring(Cow<'a, [u8]>),
+    List(Vec<BencodedRef<'a>>),
+    Dict(HashMap<Cow<'a, [u8]>, BencodedRef<'a>>),
+}
+
+impl<'a> BencodedRef<'a> {
+    pub fn get(&self, key: &[u8]) -> Option<&BencodedRef<'a>> {
+        if let BencodedRef::Dict(ref map) = *self {
+            map.get(key)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the contents of a bytestring, or `None` if this is not one.
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match *self {
+            BencodedRef::Bytestring(ref v) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// Returns the contents of a bytestring as a `Vec`, or `None` if this is
+    /// not one. They are only copied if they are still borrowed.
+    pub fn into_owned_bytestring(self) -> Option<Vec<u8>> {
+        match self {
+            BencodedRef::Bytestring(v) => Some(v.into_owned()),
+            _ => None,
+        }
+    }
+
+    /// Copies everything that is still borrowed, so the result no longer
+    /// refers to the input.
+    pub fn into_owned(self) -> BencodedRef<'static> {
+        match self {
+            BencodedRef::Integer(n) => BencodedRef::Integer(n),
+            BencodedRef::Bytestring(v) => {
+                BencodedRef::Bytestring(Cow::Owned(v.into_owned()))
+            }
+            BencodedRef::List(v) => {
+                let v = v.into_iter().map(BencodedRef::into_owned);
+                BencodedRef::List(v.collect())
+            }
+            BencodedRef::Dict(map) => {
+                let map = map.into_iter().map(|(k, v)| {
+                    (Cow::Owned(k.into_owned()), v.into_owned())
+                });
+                BencodedRef::Dict(map.collect())
+            }
+        }
+    }
+
+    /// Copies the value into a `Bencoded`, which owns all of its data.
+    ///
+    /// Note that this shadows `ToOwned::to_owned`, which would only give
+    /// another `BencodedRef`.
+    pub fn to_owned(&self) -> Bencoded {
+        match *self {
+            BencodedRef::Integer(n) => Bencoded::Integer(n),
+            BencodedRef::Bytestring(ref v) => Bencoded::Bytestring(v.to_vec()),
+            BencodedRef::List(ref v) => {
+                Bencoded::List(v.iter().map(BencodedRef::to_owned).collect())
+            }
+            BencodedRef::Dict(ref map) => {
+                let map = map.iter().map(|(k, v)| (k.to_vec(), v.to_owned()));
+                Bencoded::Dict(map.collect())
+            }
+        }
+    }
+}
+
+/// Halfway between `Bencoded` and `BencodedRef`: dict keys borrow from the
+/// input, while bytestring values are copied. Keys are usually short field
+/// names repeated in every dict, so this saves most of the allocations of
+/// `decode` when there are many dicts, and values can still outlive the
+/// input once taken out with `to_owned`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum BencodedBk<'a> {
+    Integer(Int),
+    Bytestring(Vec<u8>),
+    List(Vec<BencodedBk<'a>>),
+    Dict(HashMap<&'a [u8], BencodedBk<'a>>),
+}
+
+impl<'a> BencodedBk<'a> {
+    pub fn get(&self, key: &[u8]) -> Option<&BencodedBk<'a>> {
+        if let BencodedBk::Dict(ref map) = *self {
+            map.get(key)
+        } else {
+            None
+        }
+    }
+
+    /// Copies the value into a `Bencoded`, which owns all of its data.
+    pub fn to_owned(&self) -> Bencoded {
+        match *self {
+            BencodedBk::Integer(n) => Bencoded::Integer(n),
+            BencodedBk::Bytestring(ref v) => Bencoded::Bytestring(v.clone()),
+            BencodedBk::List(ref v) => {
+                Bencoded::List(v.iter().map(BencodedBk::to_owned).collect())
+            }
+            BencodedBk::Dict(ref map) => {
+                let map = map.iter().map(|(k, v)| (k.to_vec(), v.to_owned()));
+                Bencoded::Dict(map.collect())
+            }
+        }
+    }
+}
+
+impl<'a> Parser<'a> {
+    fn parse_borrowed_keys(&mut self, idx: usize) -> ParseResult<BencodedBk<'a>> {
+        let idx = self.skip_whitespace(idx);
+        match self.peek(idx)? {
+            b'i' => {
+                let (n, idx) = self.parse_int(idx + 1)?;
+                Ok((BencodedBk::Integer(n), idx))
+            }
+            b'l' => {
+                let mut v = Vec::new();
+                let mut idx = idx + 1;
+                loop {
+                    idx = self.skip_whitespace(idx);
+                    match self.peek(idx)? {
+                        b'e' => return Ok((BencodedBk::List(v), idx + 1)),
+                        _ => {
+                            self.add_element(idx)?;
+                            let (elem, idx_) = self.parse_borrowed_keys(idx)?;
+                            idx = idx_;
+                            v.push(elem);
+                        }
+                    }
+                }
+            }
+            b'd' => {
+                let mut map = HashMap::new();
+                let mut idx = idx + 1;
+                loop {
+                    idx = self.skip_whitespace(idx);
+                    match self.peek(idx)? {
+                        b'e' => return Ok((BencodedBk::Dict(map), idx + 1)),
+                        _ => {
+                            self.add_element(idx)?;
+                            let (key, idx_) = self.parse_key(idx)?;
+                            let (val, idx_) = self.parse_borrowed_keys(idx_)?;
+                            map.insert(key, val);
+                            idx = idx_;
+                        }
+                    }
+                }
+            }
+            _ => {
+                let (v, end) = self.parse_slice(idx)?;
+                self.allocate(idx, v.len())?;
+                Ok((BencodedBk::Bytestring(v.to_vec()), end))
+            }
+        }
+    }
+
+    fn parse_borrowed(&mut self, idx: usize) -> ParseResult<BencodedRef<'a>> {
+        let idx = self.skip_whitespace(idx);
+        match self.peek(idx)? {
+            b'i' => {
+                let (n, idx) = self.parse_int(idx + 1)?;
+                Ok((BencodedRef::Integer(n), idx))
+            }
+            b'l' => {
+                let mut v = Vec::new();
+                let mut idx = idx + 1;
+                loop {
+                    idx = self.skip_whitespace(idx);
+                    match self.peek(idx)? {
+                        b'e' => return Ok((BencodedRef::List(v), idx + 1)),
+                        _ => {
+                            self.add_element(idx)?;
+                            let (elem, idx_) = self.parse_borrowed(idx)?;
+                            idx = idx_;
+                            v.push(elem);
+                        }
+                    }
+                }
+            }
+            b'd' => {
+                let mut map = HashMap::new();
+                let mut idx = idx + 1;
+                loop {
+                    idx = self.skip_whitespace(idx);
+                    match self.peek(idx)? {
+                        b'e' => return Ok((BencodedRef::Dict(map), idx + 1)),
+                        _ => {
+                            self.add_element(idx)?;
+                            let (key, idx_) = self.parse_key(idx)?;
+                            let (val, idx_) = self.parse_borrowed(idx_)?;
+                            map.insert(Cow::Borrowed(key), val);
+                            idx = idx_;
+                        }
+                    }
+                }
+            }
+            _ => {
+                let (v, idx) = self.parse_slice(idx)?;
+                Ok((BencodedRef::Bytestring(Cow::Borrowed(v)), idx))
+            }
+        }
+    }
+}
+
+/// Like `decode`, but borrows bytestrings and dict keys from `s` instead of
+/// copying them.
+pub fn decode_borrowed<'a>(s: &'a [u8]) -> Result<BencodedRef<'a>, ParseError> {
+    let mut parser = Parser::new(s, DecodeOptions::default());
+    let (val, idx) = parser.parse_borrowed(0)?;
+    if idx != s.len() {
+        return Err(ParseError::TrailingData { pos: idx });
+    }
+    Ok(val)
+}
+
+/// Like `decode`, but borrows dict keys from `s` instead of copying them.
+/// Everything else is copied, as `decode` would.
+pub fn decode_borrowed_keys<'a>(s: &'a [u8]) -> Result<BencodedBk<'a>, ParseError> {
+    let mut parser = Parser::new(s, DecodeOptions::default());
+    let (val, idx) = parser.parse_borrowed_keys(0)?;
+    if idx != s.len() {
+        return Err(ParseError::TrailingData { pos: idx });
+    }
+    Ok(val)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use super::{decode_borrowed, decode_borrowed_keys, BencodedBk, BencodedRef};
+    use decode;
+
+    #[test]
+    fn borrows_from_input() {
+        let s = b"d3:fool3:bari42eee".to_vec();
+        let val = decode_borrowed(&s).unwrap();
+        match *val.get(b"foo").unwrap() {
+            BencodedRef::List(ref v) => {
+                match v[0] {
+                    BencodedRef::Bytestring(Cow::Borrowed(b)) => assert_eq!(b, b"bar"),
+                    _ => panic!("expected a borrowed bytestring"),
+                }
+                assert_eq!(v[1], BencodedRef::Integer(42));
+            }
+            _ => panic!("expected a list"),
+        }
+        assert!(decode_borrowed(b"i1ei2e").is_err());
+    }
+
+    #[test]
+    fn into_owned() {
+        let owned = {
+            let s = b"d1:a1:be".to_vec();
+            let mut val = decode_borrowed(&s).unwrap();
+            if let BencodedRef::Dict(ref mut map) = val {
+                map.insert(Cow::Owned(b"c".to_vec()),
+                           BencodedRef::Bytestring(Cow::Owned(b"d".to_vec())));
+            }
+            val.into_owned()
+        };
+        assert_eq!(owned.get(b"a"), Some(&BencodedRef::Bytestring(Cow::Borrowed(b"b"))));
+        assert_eq!(owned.get(b"c"), Some(&BencodedRef::Bytestring(Cow::Borrowed(b"d"))));
+    }
+
+    #[test]
+    fn to_owned() {
+        let inputs: Vec<&[u8]> = vec!(b"i-1e", b"3:foo", b"le",
+                                      b"d3:fool3:bari42ee4:spamd0:0:ee");
+        for s in inputs {
+            assert_eq!(decode_borrowed(s).unwrap().to_owned(), decode(s).unwrap());
+        }
+    }
+
+    #[test]
+    fn borrowed_keys() {
+        let s = b"d3:fool3:bari42ee4:spamd0:0:ee".to_vec();
+        let val = decode_borrowed_keys(&s).unwrap();
+        match val {
+            BencodedBk::Dict(ref map) => {
+                let key = map.keys().find(|k| **k == b"foo").unwrap();
+                assert_eq!(key.as_ptr(), s[3..].as_ptr());
+            }
+            _ => panic!("expected a dict"),
+        }
+        assert_eq!(val.get(b"foo"),
+                   Some(&BencodedBk::List(vec!(BencodedBk::Bytestring(b"bar".to_vec()),
+                                               BencodedBk::Integer(42)))));
+        assert_eq!(val.to_owned(), decode(&s).unwrap());
+        assert!(decode_borrowed_keys(b"i1ei2e").is_err());
+    }
+
+    #[test]
+    fn bytestrings() {
+        let s = b"3:foo";
+        let val = decode_borrowed(s).unwrap();
+        assert_eq!(val.as_bytes(), Some(&b"foo"[..]));
+        assert_eq!(val.into_owned_bytestring(), Some(b"foo".to_vec()));
+
+        let v = b"bar".to_vec();
+        let ptr = v.as_ptr();
+        let val = BencodedRef::Bytestring(Cow::Owned(v));
+        // owned data is moved out, not copied
+        let v = val.into_owned_bytestring().unwrap();
+        assert_eq!(v.as_ptr(), ptr);
+        assert_eq!(BencodedRef::Integer(1).into_owned_bytestring(), None);
+    }
+}
@@ -0,0 +1,349 @@
+//! Conversion between bencoded values and Rust types.
+
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::num::TryFromIntError;
+use std::error;
+use std::fmt;
+
+use {Bencoded, Int};
+use Bencoded::*;
+
+/// An error encountered while converting a bencoded value into a Rust type.
+#[derive(Debug, Eq, PartialEq)]
+pub enum ConvertError {
+    /// The value was not of the expected type, e.g. a bytestring where an
+    /// integer was expected.
+    WrongType { expected: &'static str },
+
+    /// A dict did not contain a required key.
+    MissingKey(Vec<u8>),
+
+    /// A bytestring that should hold text was not valid UTF-8.
+    InvalidUtf8,
+
+    /// An integer did not fit in the target type.
+    OutOfRange(Int),
+
+    /// A floating-point number was given where bencode can only store
+    /// integers. It is rejected rather than truncated, which would silently
+    /// lose data.
+    UnsupportedFloat,
+}
+
+impl fmt::Display for ConvertError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ConvertError::WrongType { expected } => {
+                write!(f, "expected {}", expected)
+            }
+            ConvertError::MissingKey(ref key) => {
+                write!(f, "missing key {:?}", String::from_utf8_lossy(key))
+            }
+            ConvertError::InvalidUtf8 => {
+                write!(f, "bytestring is not valid UTF-8")
+            }
+            ConvertError::OutOfRange(n) => {
+                write!(f, "integer {} is out of range", n)
+            }
+            ConvertError::UnsupportedFloat => {
+                write!(f, "bencode has no floating-point numbers")
+            }
+        }
+    }
+}
+
+impl error::Error for ConvertError {}
+
+/// Types that can be read from a bencoded value.
+///
+/// Implement it by hand, or with `#[derive(FromBencoded)]` (behind the
+/// `derive` feature) for structs that are stored as dicts with one key per
+/// field. Derived fields can be renamed with `#[bencode(rename = "...")]`,
+/// and fields of type `Option<T>` may be missing from the dict. A field of
+/// type `Vec<T>` is read from a list, so e.g. the `files` of a torrent can be
+/// read into a `Vec` of a derived struct.
+pub trait FromBencoded: Sized {
+    fn from_bencoded(b: &Bencoded) -> Result<Self, ConvertError>;
+
+    /// Reads the value stored under `key` in a dict, where `b` is the result
+    /// of looking it up. Only `Option` treats a missing key as a value.
+    #[doc(hidden)]
+    fn from_field(b: Option<&Bencoded>, key: &[u8]) -> Result<Self, ConvertError> {
+        match b {
+            Some(b) => Self::from_bencoded(b),
+            None => Err(ConvertError::MissingKey(key.to_vec())),
+        }
+    }
+}
+
+macro_rules! from_bencoded_int {
+    ($($t:ty),*) => {
+        $(
+            impl FromBencoded for $t {
+                fn from_bencoded(b: &Bencoded) -> Result<$t, ConvertError> {
+                    match *b {
+                        Integer(n) => {
+                            <$t>::try_from(n).map_err(|_| ConvertError::OutOfRange(n))
+                        }
+                        _ => Err(ConvertError::WrongType { expected: "integer" }),
+                    }
+                }
+            }
+        )*
+    }
+}
+
+// `u8` is left out so that `Vec<u8>` can mean a bytestring.
+from_bencoded_int!(i8, i16, i32, i64, isize, u16, u32, u64, usize);
+
+/// Types that can be written as a bencoded value, the other direction of
+/// `FromBencoded`. Implementations for a type's fields compose, e.g.
+///
+/// ```
+/// use bencode::{Bencoded, ToBencoded};
+///
+/// struct Peer {
+///     ip: String,
+///     port: u16,
+/// }
+///
+/// impl ToBencoded for Peer {
+///     fn to_bencoded(&self) -> Bencoded {
+///         let mut dict = Bencoded::empty_dict();
+///         dict.set_path(&[b"ip"], self.ip.to_bencoded()).unwrap();
+///         dict.set_path(&[b"port"], self.port.to_bencoded()).unwrap();
+///         dict
+///     }
+/// }
+///
+/// let peers = vec!(Peer { ip: "10.0.0.1".to_string(), port: 6881 });
+/// assert_eq!(peers.to_bencoded().to_bytes(), b"ld2:ip8:10.0.0.14:porti6881eee".to_vec());
+/// ```
+///
+/// `u64`, `usize` and other integers that may not fit in an `Int` have no
+/// implementation, since this can't fail; convert them with `TryFrom`.
+pub trait ToBencoded {
+    fn to_bencoded(&self) -> Bencoded;
+}
+
+macro_rules! to_bencoded_int {
+    ($($t:ty),*) => {
+        $(
+            impl ToBencoded for $t {
+                fn to_bencoded(&self) -> Bencoded {
+                    Integer(Int::from(*self))
+                }
+            }
+        )*
+    }
+}
+
+// `u8` is left out so that `Vec<u8>` can mean a bytestring, as for
+// `FromBencoded`.
+to_bencoded_int!(i8, i16, i32, i64, u16, u32);
+
+impl ToBencoded for bool {
+    fn to_bencoded(&self) -> Bencoded {
+        Bencoded::from(*self)
+    }
+}
+
+impl ToBencoded for str {
+    fn to_bencoded(&self) -> Bencoded {
+        Bytestring(self.as_bytes().to_vec())
+    }
+}
+
+impl ToBencoded for String {
+    fn to_bencoded(&self) -> Bencoded {
+        Bytestring(self.as_bytes().to_vec())
+    }
+}
+
+impl ToBencoded for [u8] {
+    fn to_bencoded(&self) -> Bencoded {
+        Bytestring(self.to_vec())
+    }
+}
+
+impl ToBencoded for Vec<u8> {
+    fn to_bencoded(&self) -> Bencoded {
+        Bytestring(self.clone())
+    }
+}
+
+impl<T: ToBencoded> ToBencoded for Vec<T> {
+    fn to_bencoded(&self) -> Bencoded {
+        List(self.iter().map(T::to_bencoded).collect())
+    }
+}
+
+impl<K: AsRef<[u8]>, V: ToBencoded> ToBencoded for HashMap<K, V> {
+    fn to_bencoded(&self) -> Bencoded {
+        Dict(self.iter().map(|(k, v)| (k.as_ref().to_vec(), v.to_bencoded())).collect())
+    }
+}
+
+impl ToBencoded for Bencoded {
+    fn to_bencoded(&self) -> Bencoded {
+        self.clone()
+    }
+}
+
+impl<T: ToBencoded + ?Sized> ToBencoded for &T {
+    fn to_bencoded(&self) -> Bencoded {
+        (**self).to_bencoded()
+    }
+}
+
+macro_rules! from_int {
+    ($($t:ty),*) => {
+        $(
+            impl From<$t> for Bencoded {
+                fn from(n: $t) -> Bencoded {
+                    Integer(Int::from(n))
+                }
+            }
+        )*
+    }
+}
+
+macro_rules! try_from_int {
+    ($($t:ty),*) => {
+        $(
+            /// Fails if the value doesn't fit in an `Int`, rather than
+            /// wrapping around as an `as` cast would.
+            impl TryFrom<$t> for Bencoded {
+                type Error = TryFromIntError;
+
+                fn try_from(n: $t) -> Result<Bencoded, TryFromIntError> {
+                    Int::try_from(n).map(Integer)
+                }
+            }
+        )*
+    }
+}
+
+// Integers that always fit in an `Int` convert with `From`, and the others
+// with `TryFrom`, so that e.g. a `usize` length can't silently overflow.
+from_int!(i8, i16, i32, i64, u8, u16, u32);
+try_from_int!(i128, isize, u64, u128, usize);
+
+impl FromBencoded for bool {
+    fn from_bencoded(b: &Bencoded) -> Result<bool, ConvertError> {
+        match *b {
+            Integer(n) => b.as_bool().ok_or(ConvertError::OutOfRange(n)),
+            _ => Err(ConvertError::WrongType { expected: "integer" }),
+        }
+    }
+}
+
+impl FromBencoded for String {
+    fn from_bencoded(b: &Bencoded) -> Result<String, ConvertError> {
+        match *b {
+            Bytestring(ref v) => {
+                String::from_utf8(v.clone()).map_err(|_| ConvertError::InvalidUtf8)
+            }
+            _ => Err(ConvertError::WrongType { expected: "bytestring" }),
+        }
+    }
+}
+
+impl FromBencoded for Vec<u8> {
+    fn from_bencoded(b: &Bencoded) -> Result<Vec<u8>, ConvertError> {
+        match *b {
+            Bytestring(ref v) => Ok(v.clone()),
+            _ => Err(ConvertError::WrongType { expected: "bytestring" }),
+        }
+    }
+}
+
+impl<T: FromBencoded> FromBencoded for Vec<T> {
+    fn from_bencoded(b: &Bencoded) -> Result<Vec<T>, ConvertError> {
+        match *b {
+            List(ref v) => v.iter().map(T::from_bencoded).collect(),
+            _ => Err(ConvertError::WrongType { expected: "list" }),
+        }
+    }
+}
+
+impl<T: FromBencoded> FromBencoded for Option<T> {
+    fn from_bencoded(b: &Bencoded) -> Result<Option<T>, ConvertError> {
+        T::from_bencoded(b).map(Some)
+    }
+
+    fn from_field(b: Option<&Bencoded>, _: &[u8]) -> Result<Option<T>, ConvertError> {
+        match b {
+            Some(b) => Self::from_bencoded(b),
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ConvertError, FromBencoded, ToBencoded};
+    use Bencoded::*;
+
+    #[test]
+    fn integers() {
+        assert_eq!(i64::from_bencoded(&Integer(-1)), Ok(-1));
+        assert_eq!(u32::from_bencoded(&Integer(-1)), Err(ConvertError::OutOfRange(-1)));
+        assert_eq!(i8::from_bencoded(&Integer(128)), Err(ConvertError::OutOfRange(128)));
+        assert_eq!(u64::from_bencoded(&Integer(1 << 40)), Ok(1 << 40));
+        assert_eq!(bool::from_bencoded(&Integer(1)), Ok(true));
+        assert_eq!(usize::from_bencoded(&Bytestring(vec!())),
+                   Err(ConvertError::WrongType { expected: "integer" }));
+    }
+
+    #[test]
+    fn to_bencoded() {
+        use std::collections::HashMap;
+
+        assert_eq!((-1i32).to_bencoded(), Integer(-1));
+        assert_eq!(true.to_bencoded(), Integer(1));
+        assert_eq!("hi".to_bencoded(), Bytestring(b"hi".to_vec()));
+        assert_eq!(b"hi".to_vec().to_bencoded(), Bytestring(b"hi".to_vec()));
+        assert_eq!(vec!(1i64, 2).to_bencoded(), List(vec!(Integer(1), Integer(2))));
+        assert_eq!(vec!("a", "b").to_bencoded(),
+                   List(vec!(Bytestring(b"a".to_vec()), Bytestring(b"b".to_vec()))));
+
+        let mut map = HashMap::new();
+        map.insert("length".to_string(), vec!(1u32));
+        assert_eq!(map.to_bencoded(), ::parse(b"d6:lengthli1eee"));
+
+        // and back again
+        assert_eq!(Vec::<i64>::from_bencoded(&vec!(3i64).to_bencoded()), Ok(vec!(3)));
+    }
+
+    #[test]
+    fn from_integers() {
+        use std::convert::TryFrom;
+
+        use Bencoded;
+
+        assert_eq!(Bencoded::from(-1i32), Integer(-1));
+        assert_eq!(Bencoded::from(u32::MAX), Integer(u32::MAX as i64));
+        assert_eq!(Bencoded::try_from(1usize), Ok(Integer(1)));
+        assert_eq!(Bencoded::try_from(i64::MAX as u64),
+                   Ok(Integer(i64::MAX)));
+        assert!(Bencoded::try_from(i64::MAX as u64 + 1).is_err());
+        assert!(Bencoded::try_from(-1i128 << 64).is_err());
+    }
+
+    #[test]
+    fn strings_and_lists() {
+        assert_eq!(String::from_bencoded(&Bytestring(b"hi".to_vec())),
+                   Ok("hi".to_string()));
+        assert_eq!(String::from_bencoded(&Bytestring(vec!(0xff))),
+                   Err(ConvertError::InvalidUtf8));
+        assert_eq!(Vec::<u8>::from_bencoded(&Bytestring(b"hi".to_vec())),
+                   Ok(b"hi".to_vec()));
+        assert_eq!(Vec::<i64>::from_bencoded(&List(vec!(Integer(1), Integer(2)))),
+                   Ok(vec!(1, 2)));
+        assert_eq!(Option::<i64>::from_field(None, b"a"), Ok(None));
+        assert_eq!(i64::from_field(None, b"a"),
+                   Err(ConvertError::MissingKey(b"a".to_vec())));
+    }
+}
@@ -0,0 +1,640 @@
+//! Encoding into a writer instead of a new buffer.
+
+use std::collections::HashMap;
+use std::error;
+use std::fmt;
+use std::io::{self, Write};
+
+use {assert_roundtrip, canonicalize_pairs, decode_raw, push_bytestring, uint_digits,
+     Bencoded, Int};
+use Bencoded::*;
+
+/// An error encountered while encoding into a writer.
+#[derive(Debug)]
+pub enum EncodeError {
+    /// The writer failed.
+    Io(io::Error),
+
+    /// The encoding would take `len` bytes, more than the `max` allowed.
+    TooLarge { len: usize, max: usize },
+
+    /// The validator passed to `encode_validated` rejected a value with this
+    /// message.
+    Invalid(String),
+
+    /// The encoding is not in canonical form from byte `pos` on, as checked
+    /// by `to_canonical_bytes_checked`.
+    NotCanonical { pos: usize },
+}
+
+impl fmt::Display for EncodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            EncodeError::Io(ref e) => {
+                write!(f, "write failed: {}", e)
+            }
+            EncodeError::TooLarge { len, max } => {
+                write!(f, "encoding takes {} bytes, more than the {} allowed",
+                       len, max)
+            }
+            EncodeError::Invalid(ref msg) => {
+                write!(f, "invalid value: {}", msg)
+            }
+            EncodeError::NotCanonical { pos } => {
+                write!(f, "encoding is not canonical from offset {}", pos)
+            }
+        }
+    }
+}
+
+impl error::Error for EncodeError {}
+
+impl From<io::Error> for EncodeError {
+    fn from(e: io::Error) -> EncodeError {
+        EncodeError::Io(e)
+    }
+}
+
+impl Bencoded {
+    /// Writes the same encoding as `to_bytes` to `w`, without building it in
+    /// memory first, and returns the number of bytes written. That is always
+    /// `encoded_len`, so a length prefix can be sent ahead of the value.
+    pub fn encode_to<W: Write>(&self, w: &mut W) -> io::Result<usize> {
+        let mut w = CountingWriter { inner: w, count: 0 };
+        self.write_encoding(&mut w)?;
+        Ok(w.count)
+    }
+
+    fn write_encoding<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        match *self {
+            Integer(n) => {
+                w.write_all(if n < 0 { b"i-" } else { b"i" })?;
+                w.write_all(uint_digits(n.unsigned_abs(), &mut [0; 20]))?;
+                w.write_all(b"e")
+            }
+            Bytestring(ref v) => encode_bytes(v, w),
+            List(ref v) => {
+                w.write_all(b"l")?;
+                for elem in v {
+                    elem.write_encoding(w)?;
+                }
+                w.write_all(b"e")
+            }
+            Dict(ref map) => {
+                let mut v: Vec<_> = map.iter().collect();
+                v.sort_by(|&(a, _), &(b, _)| a.cmp(b));
+
+                w.write_all(b"d")?;
+                for (key, val) in v {
+                    encode_bytes(key, w)?;
+                    val.write_encoding(w)?;
+                }
+                w.write_all(b"e")
+            }
+            OrderedDict(ref pairs) => {
+                w.write_all(b"d")?;
+                for &(ref key, ref val) in pairs {
+                    encode_bytes(key, w)?;
+                    val.write_encoding(w)?;
+                }
+                w.write_all(b"e")
+            }
+            Raw(ref v) => w.write_all(v),
+        }
+    }
+
+    /// Returns the length of the encoding, without building it.
+    pub fn encoded_len(&self) -> usize {
+        match *self {
+            Integer(n) => integer_encoded_len(n),
+            Bytestring(ref v) => bytes_encoded_len(v),
+            List(ref v) => {
+                v.iter().map(Bencoded::encoded_len).sum::<usize>() + 2
+            }
+            Dict(ref map) => {
+                map.iter()
+                   .map(|(key, val)| bytes_encoded_len(key) + val.encoded_len())
+                   .sum::<usize>() + 2
+            }
+            OrderedDict(ref pairs) => {
+                pairs.iter()
+                     .map(|&(ref key, ref val)| {
+                         bytes_encoded_len(key) + val.encoded_len()
+                     })
+                     .sum::<usize>() + 2
+            }
+            Raw(ref v) => v.len(),
+        }
+    }
+
+    /// Returns how much of the encoding is bytestring contents, e.g. the
+    /// `pieces` of a torrent, and how much is structure around them. The
+    /// contents of a `Raw` are counted as if it had been decoded, or as
+    /// structure if it doesn't decode.
+    pub fn size_breakdown(&self) -> SizeBreakdown {
+        let len = self.encoded_len();
+        let payload = self.payload_len();
+        SizeBreakdown { structural_bytes: len - payload, payload_bytes: payload }
+    }
+
+    fn payload_len(&self) -> usize {
+        match *self {
+            Integer(_) => 0,
+            Bytestring(ref v) => v.len(),
+            List(ref v) => v.iter().map(Bencoded::payload_len).sum(),
+            Dict(ref map) => map.values().map(Bencoded::payload_len).sum(),
+            OrderedDict(ref pairs) => {
+                pairs.iter().map(|&(_, ref val)| val.payload_len()).sum()
+            }
+            Raw(ref v) => decode_raw(v).map_or(0, |val| val.payload_len()),
+        }
+    }
+
+    /// Like `encode_to`, but fails with `EncodeError::TooLarge` if the
+    /// encoding would take more than `max` bytes. The length is checked
+    /// before anything is written, so in that case `w` is left untouched.
+    ///
+    /// Returns the number of bytes written.
+    pub fn encode_to_capped<W: Write>(&self, w: &mut W, max: usize)
+                                      -> Result<usize, EncodeError> {
+        let len = self.encoded_len();
+        if len > max {
+            return Err(EncodeError::TooLarge { len, max });
+        }
+        self.encode_to(w)?;
+        Ok(len)
+    }
+
+    /// Like `to_bytes`, but calls `validate` on every value in the tree as it
+    /// is encoded, parents before children, and fails with
+    /// `EncodeError::Invalid` as soon as it returns an error. This is meant
+    /// for enforcing rules of a specific protocol, e.g. that `piece length`
+    /// is a power of two.
+    ///
+    /// The contents of a `Raw` value are not visited.
+    pub fn encode_validated<F>(&self, validate: F) -> Result<Vec<u8>, EncodeError>
+        where F: Fn(&Bencoded) -> Result<(), String>
+    {
+        let mut res = Vec::new();
+        self.push_validated(&mut res, &validate)?;
+        Ok(res)
+    }
+
+    fn push_validated<F>(&self, res: &mut Vec<u8>, validate: &F) -> Result<(), EncodeError>
+        where F: Fn(&Bencoded) -> Result<(), String>
+    {
+        validate(self).map_err(EncodeError::Invalid)?;
+        match *self {
+            List(ref v) => {
+                res.push(b'l');
+                for elem in v {
+                    elem.push_validated(res, validate)?;
+                }
+                res.push(b'e');
+            }
+            Dict(ref map) => {
+                let mut v: Vec<_> = map.iter().collect();
+                v.sort_by(|&(a, _), &(b, _)| a.cmp(b));
+
+                res.push(b'd');
+                for (key, val) in v {
+                    push_bytestring(res, key);
+                    val.push_validated(res, validate)?;
+                }
+                res.push(b'e');
+            }
+            OrderedDict(ref pairs) => {
+                res.push(b'd');
+                for &(ref key, ref val) in pairs {
+                    push_bytestring(res, key);
+                    val.push_validated(res, validate)?;
+                }
+                res.push(b'e');
+            }
+            Integer(_) | Bytestring(_) | Raw(_) => self.push_bytes(res),
+        }
+        Ok(())
+    }
+
+    /// Like `to_bytes`, but checks that the result is in canonical form, and
+    /// fails with `EncodeError::NotCanonical` otherwise. Dicts always encode
+    /// canonically, but an `OrderedDict` with keys out of order or repeated,
+    /// or a `Raw` value that isn't canonical, doesn't. This is a last check
+    /// for writing files that must be canonical, e.g. because they are signed.
+    pub fn to_canonical_bytes_checked(&self) -> Result<Vec<u8>, EncodeError> {
+        let res = self.to_bytes();
+        match assert_roundtrip(&res) {
+            Ok(()) => Ok(res),
+            Err(err) => {
+                Err(EncodeError::NotCanonical { pos: err.pos().unwrap_or(res.len()) })
+            }
+        }
+    }
+
+    /// Like `to_bytes`, but remembers the sorted key order of each dict in
+    /// `cache`, so that encoding the same value again, e.g. a template that
+    /// is sent many times, doesn't sort its keys again.
+    ///
+    /// A dict is recognized by where it is in memory, and its remembered
+    /// order is only used after checking that its keys are still the same,
+    /// so the result is always the same as with `to_bytes`, even if the
+    /// value was changed in between.
+    pub fn to_bytes_cached(&self, cache: &mut EncodeCache) -> Vec<u8> {
+        let mut res = Vec::new();
+        self.push_cached(&mut res, cache);
+        res
+    }
+
+    fn push_cached(&self, res: &mut Vec<u8>, cache: &mut EncodeCache) {
+        match *self {
+            List(ref v) => {
+                res.push(b'l');
+                for elem in v {
+                    elem.push_cached(res, cache);
+                }
+                res.push(b'e');
+            }
+            Dict(ref map) => {
+                res.push(b'd');
+                for (key, val) in cache.sorted(map) {
+                    push_bytestring(res, key);
+                    val.push_cached(res, cache);
+                }
+                res.push(b'e');
+            }
+            OrderedDict(ref pairs) => {
+                res.push(b'd');
+                for &(ref key, ref val) in pairs {
+                    push_bytestring(res, key);
+                    val.push_cached(res, cache);
+                }
+                res.push(b'e');
+            }
+            Integer(_) | Bytestring(_) | Raw(_) => self.push_bytes(res),
+        }
+    }
+}
+
+/// The sorted key orders remembered by `Bencoded::to_bytes_cached`.
+///
+/// Entries are never removed, so a cache used for values that come and go
+/// should be cleared now and then.
+#[derive(Debug, Default)]
+pub struct EncodeCache {
+    // the sorted keys of each dict, by its address
+    orders: HashMap<usize, Vec<Vec<u8>>>,
+}
+
+impl EncodeCache {
+    pub fn new() -> EncodeCache {
+        EncodeCache::default()
+    }
+
+    /// Forgets every remembered order.
+    pub fn clear(&mut self) {
+        self.orders.clear();
+    }
+
+    /// Returns the entries of `map` sorted by key, using the remembered
+    /// order if the keys haven't changed.
+    fn sorted<'a>(&mut self, map: &'a HashMap<Vec<u8>, Bencoded>)
+                  -> Vec<(&'a Vec<u8>, &'a Bencoded)> {
+        let addr = map as *const _ as usize;
+        if let Some(keys) = self.orders.get(&addr) {
+            // the keys of a map are distinct, so if as many are found as
+            // there are entries, they are all of them
+            if keys.len() == map.len() {
+                let pairs: Option<Vec<_>> = keys.iter().map(|k| map.get_key_value(k))
+                                                .collect();
+                if let Some(pairs) = pairs {
+                    return pairs;
+                }
+            }
+        }
+
+        let mut pairs: Vec<_> = map.iter().collect();
+        pairs.sort_by(|&(a, _), &(b, _)| a.cmp(b));
+        self.orders.insert(addr, pairs.iter().map(|&(k, _)| k.clone()).collect());
+        pairs
+    }
+}
+
+/// How the bytes of an encoding split into bytestring contents and the rest,
+/// as returned by `Bencoded::size_breakdown`. The two add up to
+/// `encoded_len`.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct SizeBreakdown {
+    /// Markers, length prefixes, integers and dict keys.
+    pub structural_bytes: usize,
+
+    /// The contents of bytestrings that are values rather than keys.
+    pub payload_bytes: usize,
+}
+
+/// Writes a dict of the entries in `pairs` to `w`, in canonical order, but
+/// without building a `Bencoded::Dict` first, e.g. for a generator that
+/// produces entries one at a time. The entries are still
+/// collected so that they can be sorted by key; if a key is repeated, the
+/// last value wins.
+///
+/// Returns the number of bytes written.
+pub fn encode_dict_from<W, I>(w: &mut W, pairs: I) -> io::Result<usize>
+    where W: Write, I: IntoIterator<Item = (Vec<u8>, Bencoded)>
+{
+    let mut pairs: Vec<_> = pairs.into_iter().collect();
+    canonicalize_pairs(&mut pairs);
+
+    let mut w = CountingWriter { inner: w, count: 0 };
+    w.write_all(b"d")?;
+    for &(ref key, ref val) in &pairs {
+        encode_bytes(key, &mut w)?;
+        val.write_encoding(&mut w)?;
+    }
+    w.write_all(b"e")?;
+    Ok(w.count)
+}
+
+/// Writes a list to a writer one element at a time, so that a long list
+/// never has to be held in memory. `new` writes the opening `l`, each `push`
+/// encodes an element straight away, and `finish` writes the closing `e`;
+/// if the writer is dropped instead, the list is left unterminated.
+pub struct ListWriter<W: Write> {
+    inner: W,
+}
+
+impl<W: Write> ListWriter<W> {
+    pub fn new(mut inner: W) -> io::Result<ListWriter<W>> {
+        inner.write_all(b"l")?;
+        Ok(ListWriter { inner })
+    }
+
+    pub fn push(&mut self, value: &Bencoded) -> io::Result<()> {
+        value.encode_to(&mut self.inner).map(|_| ())
+    }
+
+    /// Ends the list and returns the writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.inner.write_all(b"e")?;
+        Ok(self.inner)
+    }
+}
+
+/// Like `ListWriter`, but for a dict. To write it in canonical order without
+/// holding on to the values, entries must be pushed in strictly increasing
+/// order of key, and only the last key is kept to check that; use
+/// `encode_dict_from` for entries in any order.
+pub struct DictWriter<W: Write> {
+    inner: W,
+    last_key: Option<Vec<u8>>,
+}
+
+impl<W: Write> DictWriter<W> {
+    pub fn new(mut inner: W) -> io::Result<DictWriter<W>> {
+        inner.write_all(b"d")?;
+        Ok(DictWriter { inner, last_key: None })
+    }
+
+    /// Writes an entry. Fails with `io::ErrorKind::InvalidInput`, writing
+    /// nothing, if `key` is not greater than the key pushed before it.
+    pub fn push(&mut self, key: &[u8], value: &Bencoded) -> io::Result<()> {
+        if self.last_key.as_ref().is_some_and(|last| &last[..] >= key) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      "dict keys must be pushed in increasing order"));
+        }
+        encode_bytes(key, &mut self.inner)?;
+        value.encode_to(&mut self.inner)?;
+        self.last_key = Some(key.to_vec());
+        Ok(())
+    }
+
+    /// Ends the dict and returns the writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.inner.write_all(b"e")?;
+        Ok(self.inner)
+    }
+}
+
+/// Passes writes through to `inner`, counting the bytes.
+struct CountingWriter<'a, W: 'a> {
+    inner: &'a mut W,
+    count: usize,
+}
+
+impl<'a, W: Write> Write for CountingWriter<'a, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.count += n;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+fn encode_bytes<W: Write>(v: &[u8], w: &mut W) -> io::Result<()> {
+    w.write_all(uint_digits(v.len() as u64, &mut [0; 20]))?;
+    w.write_all(b":")?;
+    w.write_all(v)
+}
+
+/// Returns the number of bytes that `n` takes when encoded as `i<n>e`,
+/// counting the `i`, the sign, the digits and the `e`.
+pub fn integer_encoded_len(n: Int) -> usize {
+    let sign = if n < 0 { 1 } else { 0 };
+    2 + sign + digits(n.unsigned_abs())
+}
+
+fn bytes_encoded_len(v: &[u8]) -> usize {
+    digits(v.len() as u64) + 1 + v.len()
+}
+
+/// Returns the number of decimal digits in `n`, which is 1 for 0.
+fn digits(mut n: u64) -> usize {
+    let mut len = 1;
+    while n >= 10 {
+        n /= 10;
+        len += 1;
+    }
+    len
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use super::{encode_dict_from, integer_encoded_len, DictWriter, EncodeCache, EncodeError,
+                ListWriter, SizeBreakdown};
+    use Int;
+    use Bencoded::*;
+
+    #[test]
+    fn encode_to() {
+        let values = vec!(::parse(b"d3:fooli-42e0:e3:bard1:ai1eee"),
+                          OrderedDict(vec!((b"b".to_vec(), Integer(10)),
+                                           (b"a".to_vec(), Bytestring(vec!(0xff))))));
+        for val in values {
+            let mut v = Vec::new();
+            assert_eq!(val.encode_to(&mut v).unwrap(), v.len());
+            assert_eq!(v, val.to_bytes());
+            assert_eq!(val.encoded_len(), v.len());
+        }
+    }
+
+    /// Builds a pseudo-random value from `seed`, nested at most `depth` deep.
+    fn arbitrary(seed: &mut u64, depth: u32) -> ::Bencoded {
+        *seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        let n = *seed >> 33;
+        match n % if depth == 0 { 2 } else { 5 } {
+            0 => Integer((n as Int).wrapping_mul(n as Int) - (1 << 40)),
+            1 => Bytestring(vec!(n as u8; (n % 300) as usize)),
+            2 => List((0..n % 4).map(|_| arbitrary(seed, depth - 1)).collect()),
+            3 => Dict((0..n % 4).map(|i| (vec!(i as u8), arbitrary(seed, depth - 1))).collect()),
+            _ => OrderedDict((0..n % 4).map(|i| (vec!(3 - i as u8), arbitrary(seed, depth - 1)))
+                                       .collect()),
+        }
+    }
+
+    #[test]
+    fn encode_to_counts_encoded_len() {
+        let mut seed = 1;
+        for _ in 0..500 {
+            let val = arbitrary(&mut seed, 4);
+            let mut v = Vec::new();
+            assert_eq!(val.encode_to(&mut v).unwrap(), val.encoded_len());
+            assert_eq!(v.len(), val.encoded_len());
+        }
+    }
+
+    #[test]
+    fn to_bytes_cached() {
+        let mut cache = EncodeCache::new();
+        let mut seed = 1;
+        for _ in 0..200 {
+            let val = arbitrary(&mut seed, 4);
+            assert_eq!(val.to_bytes_cached(&mut cache), val.to_bytes());
+            assert_eq!(val.to_bytes_cached(&mut cache), val.to_bytes());
+        }
+
+        // a changed dict at the same address is sorted again
+        let mut cache = EncodeCache::new();
+        let mut val = ::parse(b"d1:ai1e1:bi2ee");
+        assert_eq!(val.to_bytes_cached(&mut cache), b"d1:ai1e1:bi2ee".to_vec());
+        if let Dict(ref mut map) = val {
+            map.remove(&b"a"[..]);
+            map.insert(b"c".to_vec(), Integer(3));
+        }
+        assert_eq!(val.to_bytes_cached(&mut cache), b"d1:bi2e1:ci3ee".to_vec());
+    }
+
+    #[test]
+    fn encode_dict_from_sorts() {
+        let pairs = (0..3).rev().map(|i| (vec!(b'a' + i), Integer(i as Int)));
+        let mut v = Vec::new();
+        assert_eq!(encode_dict_from(&mut v, pairs).unwrap(), 20);
+        assert_eq!(v, b"d1:ai0e1:bi1e1:ci2ee".to_vec());
+
+        let pairs = vec!((b"b".to_vec(), List(vec!())), (b"a".to_vec(), Integer(1)),
+                         (b"b".to_vec(), Integer(2)));
+        let mut v = Vec::new();
+        encode_dict_from(&mut v, pairs).unwrap();
+        assert_eq!(v, b"d1:ai1e1:bi2ee".to_vec());
+    }
+
+    #[test]
+    fn list_writer() {
+        let mut w = ListWriter::new(Vec::new()).unwrap();
+        for i in 0..3 {
+            w.push(&Integer(i)).unwrap();
+        }
+        w.push(&::parse(b"d1:bi1e1:ai2ee")).unwrap();
+        assert_eq!(w.finish().unwrap(), b"li0ei1ei2ed1:ai2e1:bi1eee".to_vec());
+        assert_eq!(ListWriter::new(Vec::new()).unwrap().finish().unwrap(), b"le".to_vec());
+    }
+
+    #[test]
+    fn dict_writer() {
+        let mut v = Vec::new();
+        {
+            let mut w = DictWriter::new(&mut v).unwrap();
+            w.push(b"a", &Integer(1)).unwrap();
+            w.push(b"b", &List(vec!())).unwrap();
+            let err = w.push(b"b", &Integer(2)).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+            assert!(w.push(b"a", &Integer(2)).is_err());
+            w.push(b"c", &Integer(3)).unwrap();
+            w.finish().unwrap();
+        }
+        assert_eq!(v, b"d1:ai1e1:ble1:ci3ee".to_vec());
+    }
+
+    #[test]
+    fn integer_lengths() {
+        let ints = [0, 1, 9, 10, -1, -9, -10, 1234567, Int::MAX, Int::MIN];
+        for &n in &ints {
+            assert_eq!(integer_encoded_len(n), Integer(n).to_bytes().len(), "{}", n);
+        }
+        assert_eq!(integer_encoded_len(-10), 5);
+    }
+
+    #[test]
+    fn size_breakdown() {
+        let val = ::parse(b"d4:infod6:pieces20:01234567890123456789e4:name3:fooe");
+        assert_eq!(val.size_breakdown(),
+                   SizeBreakdown { structural_bytes: 29, payload_bytes: 23 });
+        assert_eq!(Integer(42).size_breakdown(),
+                   SizeBreakdown { structural_bytes: 4, payload_bytes: 0 });
+        let raw = Raw(b"l3:fooe".to_vec());
+        assert_eq!(raw.size_breakdown(),
+                   SizeBreakdown { structural_bytes: 4, payload_bytes: 3 });
+        let raw = Raw(b"l3:foo".to_vec());
+        assert_eq!(raw.size_breakdown().payload_bytes, 0);
+    }
+
+    #[test]
+    fn encode_validated() {
+        let val = ::parse(b"d12:piece lengthi3e4:sizeli1ei2eee");
+        let no_odd = |v: &::Bencoded| match *v {
+            Integer(n) if n % 2 != 0 => Err(format!("{} is odd", n)),
+            _ => Ok(()),
+        };
+        match val.encode_validated(no_odd) {
+            Err(EncodeError::Invalid(ref msg)) if msg == "3 is odd" => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+        assert_eq!(val.encode_validated(|_| Ok(())).unwrap(), val.to_bytes());
+    }
+
+    #[test]
+    fn to_canonical_bytes_checked() {
+        let val = ::parse(b"d1:ai1e1:bli-2eee");
+        assert_eq!(val.to_canonical_bytes_checked().unwrap(), val.to_bytes());
+
+        let check = |val: ::Bencoded| match val.to_canonical_bytes_checked() {
+            Err(EncodeError::NotCanonical { pos }) => pos,
+            res => panic!("unexpected result {:?}", res),
+        };
+        let unsorted = OrderedDict(vec!((b"b".to_vec(), Integer(1)),
+                                        (b"a".to_vec(), Integer(2))));
+        assert_eq!(check(unsorted), 3);
+        let repeated = OrderedDict(vec!((b"a".to_vec(), Integer(1)),
+                                        (b"a".to_vec(), Integer(2))));
+        assert_eq!(check(repeated), 5);
+        assert_eq!(check(List(vec!(Raw(b"i-0e".to_vec())))), 2);
+    }
+
+    #[test]
+    fn encode_to_capped() {
+        let val = ::parse(b"l3:fooi1ee");
+        let mut v = Vec::new();
+        assert_eq!(val.encode_to_capped(&mut v, 10).unwrap(), 10);
+        assert_eq!(v, b"l3:fooi1ee".to_vec());
+
+        let mut v = Vec::new();
+        match val.encode_to_capped(&mut v, 9) {
+            Err(EncodeError::TooLarge { len: 10, max: 9 }) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+        assert!(v.is_empty());
+    }
+}
@@ -0,0 +1,218 @@
+//! Sharing identical bytestrings between decoded values.
+
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+use {Bencoded, DecodeOptions, Int, ParseError, ParseResult, Parser};
+use Bencoded::*;
+
+/// A set of bytestrings that `InternedBencoded` values share, so that each
+/// distinct bytestring is only stored once, however many values use it.
+#[derive(Debug, Default)]
+pub struct Interner {
+    strings: HashSet<Rc<[u8]>>,
+}
+
+impl Interner {
+    pub fn new() -> Interner {
+        Interner { strings: HashSet::new() }
+    }
+
+    /// Returns the shared copy of `bytes`, storing it first if needed.
+    pub fn intern(&mut self, bytes: &[u8]) -> Rc<[u8]> {
+        if let Some(shared) = self.strings.get(bytes) {
+            return shared.clone();
+        }
+        let shared: Rc<[u8]> = Rc::from(bytes);
+        self.strings.insert(shared.clone());
+        shared
+    }
+
+    /// Returns the number of distinct bytestrings stored.
+    pub fn len(&self) -> usize {
+        self.strings.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.strings.is_empty()
+    }
+}
+
+/// Like `Bencoded`, but bytestrings and dict keys are shared through an
+/// `Interner`. Useful when holding many similar values at once, e.g. a cache
+/// of thousands of torrents that all use the keys `length`, `path` and
+/// `name`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum InternedBencoded {
+    Integer(Int),
+    Bytestring(Rc<[u8]>),
+    List(Vec<InternedBencoded>),
+    Dict(HashMap<Rc<[u8]>, InternedBencoded>),
+    Raw(Rc<[u8]>),
+}
+
+impl InternedBencoded {
+    pub fn get(&self, key: &[u8]) -> Option<&InternedBencoded> {
+        if let InternedBencoded::Dict(ref map) = *self {
+            map.get(key)
+        } else {
+            None
+        }
+    }
+
+    /// Copies the value back into a plain `Bencoded`.
+    pub fn to_bencoded(&self) -> Bencoded {
+        match *self {
+            InternedBencoded::Integer(n) => Integer(n),
+            InternedBencoded::Bytestring(ref v) => Bytestring(v.to_vec()),
+            InternedBencoded::List(ref v) => {
+                List(v.iter().map(InternedBencoded::to_bencoded).collect())
+            }
+            InternedBencoded::Dict(ref map) => {
+                Dict(map.iter().map(|(k, v)| (k.to_vec(), v.to_bencoded())).collect())
+            }
+            InternedBencoded::Raw(ref v) => Raw(v.to_vec()),
+        }
+    }
+}
+
+impl Bencoded {
+    /// Deep-copies the value, sharing every bytestring and dict key through
+    /// `interner`. The entries of an `OrderedDict` end up in a plain dict,
+    /// with later duplicates winning.
+    pub fn intern(&self, interner: &mut Interner) -> InternedBencoded {
+        match *self {
+            Integer(n) => InternedBencoded::Integer(n),
+            Bytestring(ref v) => InternedBencoded::Bytestring(interner.intern(v)),
+            List(ref v) => {
+                InternedBencoded::List(v.iter().map(|elem| elem.intern(interner)).collect())
+            }
+            Dict(ref map) => {
+                let mut res = HashMap::with_capacity(map.len());
+                for (key, val) in map {
+                    res.insert(interner.intern(key), val.intern(interner));
+                }
+                InternedBencoded::Dict(res)
+            }
+            OrderedDict(ref pairs) => {
+                let mut res = HashMap::with_capacity(pairs.len());
+                for &(ref key, ref val) in pairs {
+                    res.insert(interner.intern(key), val.intern(interner));
+                }
+                InternedBencoded::Dict(res)
+            }
+            Raw(ref v) => InternedBencoded::Raw(interner.intern(v)),
+        }
+    }
+}
+
+impl<'a> Parser<'a> {
+    fn parse_interned(&mut self, idx: usize, interner: &mut Interner)
+                      -> ParseResult<InternedBencoded> {
+        let idx = self.skip_whitespace(idx);
+        match self.peek(idx)? {
+            b'i' => {
+                let (n, idx) = self.parse_int(idx + 1)?;
+                Ok((InternedBencoded::Integer(n), idx))
+            }
+            b'l' => {
+                let mut v = Vec::new();
+                let mut idx = idx + 1;
+                loop {
+                    idx = self.skip_whitespace(idx);
+                    match self.peek(idx)? {
+                        b'e' => return Ok((InternedBencoded::List(v), idx + 1)),
+                        _ => {
+                            self.add_element(idx)?;
+                            let (elem, idx_) = self.parse_interned(idx, interner)?;
+                            idx = idx_;
+                            v.push(elem);
+                        }
+                    }
+                }
+            }
+            b'd' => {
+                let mut map = HashMap::new();
+                let mut idx = idx + 1;
+                loop {
+                    idx = self.skip_whitespace(idx);
+                    match self.peek(idx)? {
+                        b'e' => return Ok((InternedBencoded::Dict(map), idx + 1)),
+                        _ => {
+                            self.add_element(idx)?;
+                            let (key, idx_) = self.parse_key(idx)?;
+                            let (val, idx_) = self.parse_interned(idx_, interner)?;
+                            map.insert(interner.intern(key), val);
+                            idx = idx_;
+                        }
+                    }
+                }
+            }
+            _ => {
+                let (v, end) = self.parse_slice(idx)?;
+                self.allocate(idx, v.len())?;
+                Ok((InternedBencoded::Bytestring(Rc::from(v)), end))
+            }
+        }
+    }
+}
+
+/// Like `decode`, but shares dict keys through `interner`, so that decoding
+/// many similar values, e.g. thousands of torrents, allocates each distinct
+/// key only once.
+///
+/// Unlike `Bencoded::intern`, bytestring values are not interned, so that
+/// large one-off values such as `pieces` aren't kept alive by `interner`.
+pub fn decode_interned(s: &[u8], interner: &mut Interner)
+                       -> Result<InternedBencoded, ParseError> {
+    let mut parser = Parser::new(s, DecodeOptions::default());
+    let (val, idx) = parser.parse_interned(0, interner)?;
+    if idx != s.len() {
+        return Err(ParseError::TrailingData { pos: idx });
+    }
+    Ok(val)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use super::{decode_interned, InternedBencoded, Interner};
+
+    #[test]
+    fn shares_bytestrings() {
+        let mut interner = Interner::new();
+        let a = ::parse(b"d4:name3:foo4:pathl3:fooee").intern(&mut interner);
+        let b = ::parse(b"d4:name3:bare").intern(&mut interner);
+        assert_eq!(interner.len(), 4);
+
+        let foo = match (a.get(b"name"), b.get(b"name")) {
+            (Some(&InternedBencoded::Bytestring(ref foo)), Some(_)) => foo.clone(),
+            _ => panic!("expected bytestrings"),
+        };
+        // the interner, `name` and the element of `path`, plus `foo` itself
+        assert_eq!(Rc::strong_count(&foo), 4);
+
+        assert_eq!(a.to_bencoded(), ::parse(b"d4:name3:foo4:pathl3:fooee"));
+    }
+
+    #[test]
+    fn decode_shares_keys() {
+        let mut interner = Interner::new();
+        let s = b"d6:lengthi1e4:pathl3:fooee";
+        let a = decode_interned(s, &mut interner).unwrap();
+        let b = decode_interned(b"d6:lengthi2ee", &mut interner).unwrap();
+        // only the keys
+        assert_eq!(interner.len(), 2);
+        assert_eq!(a.to_bencoded(), ::parse(s));
+
+        let keys = |v: &InternedBencoded| match *v {
+            InternedBencoded::Dict(ref map) => map.keys().cloned().collect::<Vec<_>>(),
+            _ => panic!("expected a dict"),
+        };
+        let length = keys(&b).pop().unwrap();
+        assert!(keys(&a).iter().any(|k| Rc::ptr_eq(k, &length)));
+
+        assert!(decode_interned(b"i1ei2e", &mut interner).is_err());
+    }
+}
@@ -0,0 +1,315 @@
+//! Conversion to and from JSON, e.g. for inspecting a torrent.
+
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::str;
+
+use serde_json::{self, Map, Value};
+
+use Bencoded::*;
+use {canonical_refs, decode_raw, Bencoded, ConvertError, PrettyOptions};
+
+impl Bencoded {
+    /// Converts the value into JSON. Integers become numbers, lists become
+    /// arrays and dicts become objects.
+    ///
+    /// JSON strings must be text, so this is lossy: a bytestring or dict key
+    /// that is valid UTF-8 becomes that string, and any other is written as
+    /// its bytes in lower-case hex, with nothing to tell the two apart. If an
+    /// `OrderedDict` repeats a key, only the last value is kept.
+    pub fn to_json(&self) -> Value {
+        match *self {
+            Integer(n) => Value::from(n),
+            Bytestring(ref v) => Value::String(json_string(v)),
+            List(ref v) => Value::Array(v.iter().map(Bencoded::to_json).collect()),
+            Dict(ref map) => {
+                let mut res = Map::new();
+                for (key, val) in map {
+                    res.insert(json_string(key), val.to_json());
+                }
+                Value::Object(res)
+            }
+            OrderedDict(ref pairs) => {
+                let mut res = Map::new();
+                for &(ref key, ref val) in pairs {
+                    res.insert(json_string(key), val.to_json());
+                }
+                Value::Object(res)
+            }
+            Raw(ref v) => {
+                match decode_raw(v) {
+                    Ok(val) => val.to_json(),
+                    Err(_) => Value::String(hex(v)),
+                }
+            }
+        }
+    }
+
+    /// Returns `to_json` as a string, indented with `pretty`, e.g. for
+    /// logging. This is as lossy as `to_json`: a bytestring that isn't UTF-8
+    /// shows up as hex, which looks just like a string of hex digits would.
+    ///
+    /// The indented form is that of `to_json_string_with_options` with two
+    /// spaces per level and nothing cut short.
+    pub fn to_json_string(&self, pretty: bool) -> String {
+        if pretty {
+            return self.to_json_string_with_options(&PRETTY_JSON);
+        }
+        serde_json::to_string(&self.to_json()).expect("a `Value` always serializes")
+    }
+
+    /// Like `to_json_string`, but indented according to `opts`, as by
+    /// `write_json_with_options`.
+    pub fn to_json_string_with_options(&self, opts: &PrettyOptions) -> String {
+        let mut v = Vec::new();
+        self.write_json_with_options(&mut v, opts).expect("writing to a `Vec` cannot fail");
+        String::from_utf8(v).expect("JSON is UTF-8")
+    }
+
+    /// Converts JSON into a value. Numbers become integers, strings become
+    /// bytestrings of their UTF-8, arrays become lists, objects become dicts,
+    /// and `true` and `false` become `Integer(1)` and `Integer(0)`, as read
+    /// by `as_bool`.
+    ///
+    /// Bencode has no floats, so a number with a fractional part or exponent
+    /// fails with `ConvertError::UnsupportedFloat` instead of being
+    /// truncated. `null` and integers outside the range of `Int` fail with
+    /// `ConvertError::WrongType`.
+    pub fn from_json(v: &Value) -> Result<Bencoded, ConvertError> {
+        match *v {
+            Value::Null => Err(ConvertError::WrongType { expected: "non-null value" }),
+            Value::Bool(b) => Ok(Integer(if b { 1 } else { 0 })),
+            Value::Number(ref n) if n.is_f64() => Err(ConvertError::UnsupportedFloat),
+            Value::Number(ref n) => {
+                match n.as_i64() {
+                    Some(n) => Ok(Integer(n)),
+                    None => Err(ConvertError::WrongType { expected: "signed 64-bit integer" }),
+                }
+            }
+            Value::String(ref s) => Ok(Bytestring(s.as_bytes().to_vec())),
+            Value::Array(ref v) => {
+                let v: Result<Vec<_>, _> = v.iter().map(Bencoded::from_json).collect();
+                Ok(List(v?))
+            }
+            Value::Object(ref obj) => {
+                let mut map = HashMap::with_capacity(obj.len());
+                for (key, val) in obj {
+                    map.insert(key.as_bytes().to_vec(), Bencoded::from_json(val)?);
+                }
+                Ok(Dict(map))
+            }
+        }
+    }
+
+    /// Writes the same JSON as `to_json` to `w` as it goes, without building
+    /// a `Value`, so that even a huge torrent can be dumped with little
+    /// memory. With `pretty`, the output is indented like
+    /// `serde_json::to_string_pretty`.
+    ///
+    /// Dict entries are written in the order of their keys' bytes, which
+    /// only differs from `to_json` when non-UTF-8 keys are involved.
+    pub fn write_json<W: Write>(&self, w: &mut W, pretty: bool) -> io::Result<()> {
+        self.write_json_at(w, if pretty { Some(&PRETTY_JSON) } else { None }, 0)
+    }
+
+    /// Like `write_json`, but indented according to `opts`, with bytestrings
+    /// longer than `max_bytestring_display` bytes cut short with `…`. Dict
+    /// keys are always written in full, so that they stay unique. JSON strings
+    /// must be text, so bytestrings that aren't UTF-8 are written as hex
+    /// whatever `hex_non_utf8` is.
+    pub fn write_json_with_options<W: Write>(&self, w: &mut W, opts: &PrettyOptions)
+                                             -> io::Result<()> {
+        self.write_json_at(w, Some(opts), 0)
+    }
+
+    /// Writes the JSON indented according to `opts` if there are any, or all
+    /// on one line if not.
+    fn write_json_at<W: Write>(&self, w: &mut W, opts: Option<&PrettyOptions>, depth: usize)
+                               -> io::Result<()> {
+        match *self {
+            Integer(n) => write!(w, "{}", n),
+            Bytestring(ref v) => {
+                write_json_string(w, v, opts.map_or(usize::MAX, |opts| {
+                    opts.max_bytestring_display
+                }))
+            }
+            List(ref v) if v.is_empty() => w.write_all(b"[]"),
+            List(ref v) => {
+                w.write_all(b"[")?;
+                for (i, elem) in v.iter().enumerate() {
+                    write_separator(w, i, opts, depth + 1)?;
+                    elem.write_json_at(w, opts, depth + 1)?;
+                }
+                write_separator(w, 0, opts, depth)?;
+                w.write_all(b"]")
+            }
+            Dict(ref map) => {
+                let mut pairs: Vec<_> = map.iter().collect();
+                pairs.sort_by(|&(a, _), &(b, _)| a.cmp(b));
+                write_json_object(w, &pairs, opts, depth)
+            }
+            OrderedDict(ref pairs) => {
+                write_json_object(w, &canonical_refs(pairs), opts, depth)
+            }
+            Raw(ref v) => {
+                match decode_raw(v) {
+                    Ok(val) => val.write_json_at(w, opts, depth),
+                    Err(_) => write_hex_string(w, v, usize::MAX),
+                }
+            }
+        }
+    }
+}
+
+/// What `write_json` and `to_json_string` indent with, as
+/// `serde_json::to_string_pretty` does.
+const PRETTY_JSON: PrettyOptions = PrettyOptions {
+    indent: 2,
+    max_bytestring_display: usize::MAX,
+    hex_non_utf8: false,
+};
+
+fn write_json_object<W: Write>(w: &mut W, pairs: &[(&Vec<u8>, &Bencoded)],
+                               opts: Option<&PrettyOptions>, depth: usize) -> io::Result<()> {
+    if pairs.is_empty() {
+        return w.write_all(b"{}");
+    }
+    w.write_all(b"{")?;
+    for (i, &(key, val)) in pairs.iter().enumerate() {
+        write_separator(w, i, opts, depth + 1)?;
+        write_json_string(w, key, usize::MAX)?;
+        w.write_all(if opts.is_some() { b": " } else { b":" })?;
+        val.write_json_at(w, opts, depth + 1)?;
+    }
+    write_separator(w, 0, opts, depth)?;
+    w.write_all(b"}")
+}
+
+/// Writes what goes before element `i` of an array or object, or before its
+/// closing bracket when `i` is 0: a comma between elements, and a newline and
+/// indentation when there are `opts`.
+fn write_separator<W: Write>(w: &mut W, i: usize, opts: Option<&PrettyOptions>, depth: usize)
+                             -> io::Result<()> {
+    if i > 0 {
+        w.write_all(b",")?;
+    }
+    if let Some(opts) = opts {
+        w.write_all(b"\n")?;
+        for _ in 0..depth * opts.indent {
+            w.write_all(b" ")?;
+        }
+    }
+    Ok(())
+}
+
+/// Writes `v` as a JSON string, cut short with `…` after `max` bytes.
+fn write_json_string<W: Write>(w: &mut W, v: &[u8], max: usize) -> io::Result<()> {
+    match str::from_utf8(v) {
+        Ok(s) if s.len() > max => {
+            let mut end = max;
+            while !s.is_char_boundary(end) {
+                end -= 1;
+            }
+            serde_json::to_writer(w, &format!("{}…", &s[..end])).map_err(io::Error::from)
+        }
+        Ok(s) => serde_json::to_writer(w, s).map_err(io::Error::from),
+        Err(_) => write_hex_string(w, v, max),
+    }
+}
+
+fn write_hex_string<W: Write>(w: &mut W, v: &[u8], max: usize) -> io::Result<()> {
+    w.write_all(b"\"")?;
+    for c in v.iter().take(max) {
+        write!(w, "{:02x}", c)?;
+    }
+    if v.len() > max {
+        w.write_all("…".as_bytes())?;
+    }
+    w.write_all(b"\"")
+}
+
+fn json_string(v: &[u8]) -> String {
+    match String::from_utf8(v.to_vec()) {
+        Ok(s) => s,
+        Err(_) => hex(v),
+    }
+}
+
+fn hex(v: &[u8]) -> String {
+    v.iter().map(|c| format!("{:02x}", c)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use ConvertError;
+    use Bencoded;
+    use Bencoded::*;
+
+    #[test]
+    fn to_json() {
+        let val = ::parse(b"d4:name3:foo6:pieces2:\xff\x004:sizeli1ei-2eee");
+        assert_eq!(val.to_json().to_string(),
+                   r#"{"name":"foo","pieces":"ff00","size":[1,-2]}"#);
+    }
+
+    #[test]
+    fn to_json_string() {
+        let val = ::parse(b"d4:name3:foo6:pieces2:\xff\x00e");
+        assert_eq!(val.to_json_string(false), r#"{"name":"foo","pieces":"ff00"}"#);
+        assert_eq!(val.to_json_string(true),
+                   "{\n  \"name\": \"foo\",\n  \"pieces\": \"ff00\"\n}");
+    }
+
+    #[test]
+    fn write_json() {
+        let val = ::parse(b"d4:name3:foo6:pieces2:\xff\x004:sizeli1ei-2ee5:emptyldeee");
+        for &pretty in &[false, true] {
+            let mut v = Vec::new();
+            val.write_json(&mut v, pretty).unwrap();
+            let expected = if pretty {
+                ::serde_json::to_string_pretty(&val.to_json()).unwrap()
+            } else {
+                val.to_json().to_string()
+            };
+            assert_eq!(String::from_utf8(v).unwrap(), expected);
+        }
+
+        let mut v = Vec::new();
+        Raw(b"l1:\"e".to_vec()).write_json(&mut v, false).unwrap();
+        assert_eq!(v, br#"["\""]"#.to_vec());
+    }
+
+    #[test]
+    fn write_json_with_options() {
+        use PrettyOptions;
+
+        let val = ::parse(b"d4:name6:h\xc3\xa9llo6:pieces3:\xff\x00\x014:sizeli1eee");
+        let opts = PrettyOptions { indent: 1, max_bytestring_display: 2, hex_non_utf8: false };
+        assert_eq!(val.to_json_string_with_options(&opts),
+                   "{\n \"name\": \"h…\",\n \"pieces\": \"ff00…\",\n \"size\": [\n  1\n ]\n}");
+        let opts = PrettyOptions { max_bytestring_display: 3, ..Default::default() };
+        assert!(val.to_json_string_with_options(&opts).contains("\"h\u{e9}…\""));
+        assert_eq!(val.to_json_string_with_options(&super::PRETTY_JSON),
+                   val.to_json_string(true));
+
+        // a `Raw` is written as what it decodes to, with the same options
+        let mut v = Vec::new();
+        let opts = PrettyOptions { indent: 1, max_bytestring_display: 2, hex_non_utf8: false };
+        Raw(b"l5:abcde1:\xffe".to_vec()).write_json_with_options(&mut v, &opts).unwrap();
+        assert_eq!(String::from_utf8(v).unwrap(), "[\n \"ab…\",\n \"ff\"\n]");
+    }
+
+    #[test]
+    fn from_json() {
+        assert_eq!(Bencoded::from_json(&json!(2)), Ok(Integer(2)));
+        assert_eq!(Bencoded::from_json(&json!(1.5)), Err(ConvertError::UnsupportedFloat));
+        assert_eq!(Bencoded::from_json(&json!(2.0)), Err(ConvertError::UnsupportedFloat));
+        assert_eq!(Bencoded::from_json(&json!([1, [1.5]])), Err(ConvertError::UnsupportedFloat));
+        assert!(Bencoded::from_json(&json!(null)).is_err());
+        assert!(Bencoded::from_json(&json!(u64::MAX)).is_err());
+
+        let val = json!({"name": "foo", "private": true, "size": [1, -2]});
+        assert_eq!(Bencoded::from_json(&val),
+                   Ok(::parse(b"d4:name3:foo7:privatei1e4:sizeli1ei-2eee")));
+    }
+}
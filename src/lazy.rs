@@ -0,0 +1,222 @@
+//! A decoded representation that refers to bytestrings by their position in
+//! the input instead of holding them.
+
+use std::collections::HashMap;
+
+use {Bencoded, DecodeOptions, Int, ParseError, ParseResult, Parser};
+
+/// The position of a bytestring's contents within the input it was decoded
+/// from.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct ByteSpan {
+    pub offset: usize,
+    pub len: usize,
+}
+
+impl ByteSpan {
+    /// Returns the bytes this span covers in `buf`, which must be the input
+    /// it was decoded from.
+    pub fn slice<'a>(&self, buf: &'a [u8]) -> &'a [u8] {
+        &buf[self.offset..self.offset + self.len]
+    }
+
+    /// Widens the span of a bytestring's contents to also cover its length
+    /// prefix, so that it spans the whole encoded bytestring. This assumes
+    /// the length was written without leading zeros.
+    pub fn with_prefix(&self) -> ByteSpan {
+        let prefix = self.len.to_string().len() + 1;
+        ByteSpan { offset: self.offset - prefix, len: self.len + prefix }
+    }
+}
+
+/// Returns a copy of `s` with the encoded value that `span` covers replaced by
+/// the encoding of `new_value`. The rest of `s` is copied as is, so this is
+/// much cheaper than decoding and re-encoding everything to change one field,
+/// e.g. the `announce` URL of a torrent:
+///
+/// ```ignore
+/// let span = match decode_lazy(s)?.get(b"announce") {
+///     Some(&LazyBencoded::Bytestring(span)) => span.with_prefix(),
+///     _ => ...,
+/// };
+/// let s = splice(s, span, &Bencoded::Bytestring(url));
+/// ```
+///
+/// `span` must cover a complete value, or the result won't decode.
+pub fn splice(s: &[u8], span: ByteSpan, new_value: &Bencoded) -> Vec<u8> {
+    let new = new_value.to_bytes();
+    let mut res = Vec::with_capacity(s.len() - span.len + new.len());
+    res.extend_from_slice(&s[..span.offset]);
+    res.extend_from_slice(&new);
+    res.extend_from_slice(&s[span.offset + span.len..]);
+    res
+}
+
+/// Like `Bencoded`, but each bytestring value is a `ByteSpan` into the input
+/// rather than a copy of it. Dict keys are still copied, so lookups don't need
+/// the input.
+///
+/// This needs even less memory than `BencodedRef`, and doesn't keep the input
+/// borrowed, which makes it suitable for building an index over a file that is
+/// read (or memory mapped) on demand.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum LazyBencoded {
+    Integer(Int),
+    Bytestring(ByteSpan),
+    List(Vec<LazyBencoded>),
+    Dict(HashMap<Vec<u8>, LazyBencoded>),
+}
+
+impl LazyBencoded {
+    pub fn get(&self, key: &[u8]) -> Option<&LazyBencoded> {
+        if let LazyBencoded::Dict(ref map) = *self {
+            map.get(key)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the contents of a bytestring from `buf`, which must be the
+    /// input this was decoded from, or `None` if this is not a bytestring.
+    pub fn bytes<'b>(&self, buf: &'b [u8]) -> Option<&'b [u8]> {
+        match *self {
+            LazyBencoded::Bytestring(span) => Some(span.slice(buf)),
+            _ => None,
+        }
+    }
+
+    /// Copies the value into a `Bencoded`, taking the contents of its
+    /// bytestrings from `buf`, which must be the input it was decoded from.
+    pub fn resolve(&self, buf: &[u8]) -> Bencoded {
+        match *self {
+            LazyBencoded::Integer(n) => Bencoded::Integer(n),
+            LazyBencoded::Bytestring(span) => Bencoded::Bytestring(span.slice(buf).to_vec()),
+            LazyBencoded::List(ref v) => {
+                Bencoded::List(v.iter().map(|val| val.resolve(buf)).collect())
+            }
+            LazyBencoded::Dict(ref map) => {
+                let map = map.iter().map(|(k, v)| (k.clone(), v.resolve(buf)));
+                Bencoded::Dict(map.collect())
+            }
+        }
+    }
+}
+
+impl<'a> Parser<'a> {
+    fn parse_lazy(&mut self, idx: usize) -> ParseResult<LazyBencoded> {
+        let idx = self.skip_whitespace(idx);
+        match self.peek(idx)? {
+            b'i' => {
+                let (n, idx) = self.parse_int(idx + 1)?;
+                Ok((LazyBencoded::Integer(n), idx))
+            }
+            b'l' => {
+                let mut v = Vec::new();
+                let mut idx = idx + 1;
+                loop {
+                    idx = self.skip_whitespace(idx);
+                    match self.peek(idx)? {
+                        b'e' => return Ok((LazyBencoded::List(v), idx + 1)),
+                        _ => {
+                            self.add_element(idx)?;
+                            let (elem, idx_) = self.parse_lazy(idx)?;
+                            idx = idx_;
+                            v.push(elem);
+                        }
+                    }
+                }
+            }
+            b'd' => {
+                let mut map = HashMap::new();
+                let mut idx = idx + 1;
+                loop {
+                    idx = self.skip_whitespace(idx);
+                    match self.peek(idx)? {
+                        b'e' => return Ok((LazyBencoded::Dict(map), idx + 1)),
+                        _ => {
+                            self.add_element(idx)?;
+                            let (key, idx_) = self.parse_key(idx)?;
+                            self.allocate(idx, key.len())?;
+                            let (val, idx_) = self.parse_lazy(idx_)?;
+                            map.insert(key.to_vec(), val);
+                            idx = idx_;
+                        }
+                    }
+                }
+            }
+            _ => {
+                let (v, end) = self.parse_slice(idx)?;
+                let span = ByteSpan { offset: end - v.len(), len: v.len() };
+                Ok((LazyBencoded::Bytestring(span), end))
+            }
+        }
+    }
+}
+
+/// Like `decode`, but records where each bytestring value is in `s` instead
+/// of copying it.
+pub fn decode_lazy(s: &[u8]) -> Result<LazyBencoded, ParseError> {
+    let mut parser = Parser::new(s, DecodeOptions::default());
+    let (val, idx) = parser.parse_lazy(0)?;
+    if idx != s.len() {
+        return Err(ParseError::TrailingData { pos: idx });
+    }
+    Ok(val)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode_lazy, splice, ByteSpan, LazyBencoded};
+    use Bencoded;
+
+    #[test]
+    fn spans() {
+        let s = b"d3:fool3:bari42ee4:spam0:e";
+        let val = decode_lazy(s).unwrap();
+        match *val.get(b"foo").unwrap() {
+            LazyBencoded::List(ref v) => {
+                assert_eq!(v[0], LazyBencoded::Bytestring(ByteSpan { offset: 9, len: 3 }));
+                assert_eq!(v[1], LazyBencoded::Integer(42));
+            }
+            _ => panic!("expected a list"),
+        }
+        match *val.get(b"spam").unwrap() {
+            LazyBencoded::Bytestring(span) => assert_eq!(span.slice(s), b""),
+            _ => panic!("expected a bytestring"),
+        }
+        assert_eq!(decode_lazy(b"3:abc").unwrap(),
+                   LazyBencoded::Bytestring(ByteSpan { offset: 2, len: 3 }));
+    }
+
+    #[test]
+    fn resolve() {
+        let inputs: Vec<&[u8]> = vec!(b"i-1e", b"3:foo", b"le",
+                                      b"d3:fool3:bari42ee4:spamd0:0:ee");
+        for s in inputs {
+            assert_eq!(decode_lazy(s).unwrap().resolve(s), ::parse(s));
+        }
+
+        let s = b"l3:fooi1ee";
+        let val = decode_lazy(s).unwrap();
+        match val {
+            LazyBencoded::List(ref v) => {
+                assert_eq!(v[0].bytes(s), Some(&b"foo"[..]));
+                assert_eq!(v[1].bytes(s), None);
+            }
+            _ => panic!("expected a list"),
+        }
+    }
+
+    #[test]
+    fn splice_announce() {
+        let s = b"d8:announce9:http://a/4:infod6:lengthi1eee";
+        let span = match decode_lazy(s).unwrap().get(b"announce") {
+            Some(&LazyBencoded::Bytestring(span)) => span.with_prefix(),
+            _ => panic!("expected a bytestring"),
+        };
+        assert_eq!(span, ByteSpan { offset: 11, len: 11 });
+        let url = Bencoded::Bytestring(b"http://tracker.example/".to_vec());
+        assert_eq!(splice(s, span, &url),
+                   b"d8:announce23:http://tracker.example/4:infod6:lengthi1eee".to_vec());
+    }
+}
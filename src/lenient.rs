@@ -0,0 +1,190 @@
+//! Decoding input that is slightly off, reporting what was wrong with it.
+
+use std::fmt;
+#[cfg(feature = "canonical-hash")]
+use std::hash::Hasher;
+
+#[cfg(feature = "canonical-hash")]
+use sha2::{Digest, Sha256};
+
+use {Bencoded, DecodeOptions, ParseError, Parser};
+
+/// A problem that `decode_lenient` tolerated.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Warning {
+    /// The integer or bytestring length whose digits start at `pos` has
+    /// leading zeros.
+    LeadingZero { pos: usize },
+
+    /// The dict key at `pos` sorts before the key preceding it.
+    UnsortedKey { pos: usize },
+
+    /// The dict key at `pos` already appeared in the same dict. Its value
+    /// replaces the earlier one.
+    DuplicateKey { pos: usize },
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Warning::LeadingZero { pos } => {
+                write!(f, "number with leading zeros at offset {}", pos)
+            }
+            Warning::UnsortedKey { pos } => {
+                write!(f, "dict key out of order at offset {}", pos)
+            }
+            Warning::DuplicateKey { pos } => {
+                write!(f, "duplicate dict key at offset {}", pos)
+            }
+        }
+    }
+}
+
+/// Like `decode`, but accepts leading zeros, unsorted dict keys and duplicate
+/// dict keys (the last value winning), and returns a `Warning` for each of
+/// them along with the value. Input that is broken beyond that, e.g.
+/// truncated or with a length that isn't a number, is still an error.
+pub fn decode_lenient(s: &[u8]) -> Result<(Bencoded, Vec<Warning>), ParseError> {
+    decode_lenient_with_options(s, &DecodeOptions::default())
+}
+
+/// Like `decode_lenient`, but decodes according to `opts`. With
+/// `preserve_integer_text` set, integers with leading zeros are kept as they
+/// were written.
+pub fn decode_lenient_with_options(s: &[u8], opts: &DecodeOptions)
+                                   -> Result<(Bencoded, Vec<Warning>), ParseError> {
+    let mut parser = Parser::new(s, opts.clone());
+    parser.warnings = Some(Vec::new());
+    let (val, idx) = parser.parse_bencoded(0)?;
+    if idx != s.len() {
+        return Err(ParseError::TrailingData { pos: idx });
+    }
+    Ok((val, parser.warnings.unwrap_or_default()))
+}
+
+/// Returns whether `a` and `b` decode, as by `decode_lenient`, to the same
+/// value, i.e. have the same canonical form, whatever their key order,
+/// duplicate keys or leading zeros. Fails with the error of whichever of them
+/// doesn't decode, `a` first.
+pub fn equivalent(a: &[u8], b: &[u8]) -> Result<bool, ParseError> {
+    if a == b {
+        // no need to decode either, but it must still be valid
+        return decode_lenient(a).map(|_| true);
+    }
+    let (a, _) = decode_lenient(a)?;
+    let (b, _) = decode_lenient(b)?;
+    Ok(a == b)
+}
+
+/// Returns the SHA-256 hash of the canonical form of `s`, decoded as by
+/// `decode_lenient`, so that inputs differing only in key order, duplicate
+/// keys or leading zeros hash the same, e.g. for deduplicating files from
+/// careless encoders. The canonical form is fed to the hash by
+/// `encode_to_hasher`, and is never held in memory as bytes.
+#[cfg(feature = "canonical-hash")]
+pub fn canonical_hash(s: &[u8]) -> Result<[u8; 32], ParseError> {
+    let (val, _) = decode_lenient(s)?;
+    let mut hasher = Sha256Hasher(Sha256::new());
+    val.encode_to_hasher(&mut hasher);
+    Ok(hasher.0.finalize().into())
+}
+
+/// Lets `encode_to_hasher` feed a SHA-256 hash.
+#[cfg(feature = "canonical-hash")]
+struct Sha256Hasher(Sha256);
+
+#[cfg(feature = "canonical-hash")]
+impl Hasher for Sha256Hasher {
+    fn write(&mut self, bytes: &[u8]) {
+        self.0.update(bytes);
+    }
+
+    /// Unused, since the SHA-256 hash doesn't fit in a `u64`.
+    fn finish(&self) -> u64 {
+        0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode_lenient, decode_lenient_with_options, equivalent, Warning};
+    use {DecodeOptions, ParseError};
+    use Bencoded::*;
+
+    #[test]
+    fn warnings() {
+        let (val, warnings) = decode_lenient(b"d1:bi03e1:ai1e1:a002:hie").unwrap();
+        assert_eq!(val, ::parse(b"d1:a2:hi1:bi3ee"));
+        assert_eq!(warnings, vec!(Warning::LeadingZero { pos: 5 },
+                                  Warning::UnsortedKey { pos: 8 },
+                                  Warning::LeadingZero { pos: 17 },
+                                  Warning::DuplicateKey { pos: 14 }));
+
+        assert_eq!(decode_lenient(b"d1:ai1e1:bi2ee").unwrap().1, vec!());
+        assert_eq!(decode_lenient(b"d1:ai1e"), Err(ParseError::Incomplete { needed: None }));
+        assert_eq!(decode_lenient(b"x:"), Err(ParseError::UnexpectedByte { pos: 0, byte: b'x' }));
+    }
+
+    #[test]
+    fn key_order() {
+        use KeyOrder;
+
+        let s = b"d1:Bi1e1:ai2ee";
+        assert_eq!(decode_lenient(s).unwrap().1, vec!());
+        let opts = DecodeOptions {
+            key_order: KeyOrder::Custom(|a, b| a.to_ascii_lowercase().cmp(&b.to_ascii_lowercase())),
+            ..Default::default()
+        };
+        let (val, warnings) = decode_lenient_with_options(s, &opts).unwrap();
+        assert_eq!(warnings, vec!(Warning::UnsortedKey { pos: 7 }));
+        assert_eq!(val.to_bytes(), s.to_vec());
+
+        let s = b"d1:bi1e1:ai2ee";
+        let opts = DecodeOptions { key_order: KeyOrder::None, ..Default::default() };
+        assert_eq!(decode_lenient_with_options(s, &opts).unwrap().1, vec!());
+        // canonical form is still in raw byte order
+        let (_, meta) = ::decode_with_meta(s).unwrap();
+        assert!(!meta.was_canonical);
+    }
+
+    #[test]
+    fn equivalent_inputs() {
+        assert_eq!(equivalent(b"d1:ai1e1:bi3ee", b"d1:bi03e1:ai0e1:ai1ee"), Ok(true));
+        assert_eq!(equivalent(b"l3:fooe", b"l3:fooe"), Ok(true));
+        assert_eq!(equivalent(b"d1:ai1e1:bi3ee", b"d1:ai1e1:bi4ee"), Ok(false));
+        assert_eq!(equivalent(b"i1e", b"1:1"), Ok(false));
+        assert_eq!(equivalent(b"i1e", b"i1"), Err(ParseError::Incomplete { needed: None }));
+        assert_eq!(equivalent(b"i1", b"i1"), Err(ParseError::Incomplete { needed: None }));
+    }
+
+    #[test]
+    fn preserve_integer_text() {
+        let opts = DecodeOptions { preserve_integer_text: true, ..Default::default() };
+        let s = b"li03ei-0ei-003ei3ee";
+        let (val, warnings) = decode_lenient_with_options(s, &opts).unwrap();
+        assert_eq!(val, List(vec!(Raw(b"i03e".to_vec()), Raw(b"i-0e".to_vec()),
+                                  Raw(b"i-003e".to_vec()), Integer(3))));
+        assert_eq!(warnings.len(), 2);
+        assert_eq!(val.to_bytes(), s.to_vec());
+        assert_eq!(val.to_escaped_string(), "li3ei0ei-3ei3ee");
+
+        assert_eq!(::decode_with_options(b"i-0e", &opts), Ok(Raw(b"i-0e".to_vec())));
+        assert_eq!(::decode_with_options(b"i-1e", &opts), Ok(Integer(-1)));
+
+        // without the option the text is lost
+        assert_eq!(decode_lenient(s).unwrap().0.to_bytes(), b"li3ei0ei-3ei3ee".to_vec());
+    }
+
+    #[test]
+    #[cfg(feature = "canonical-hash")]
+    fn canonical_hash() {
+        use sha2::{Digest, Sha256};
+        use super::canonical_hash;
+
+        let hash = canonical_hash(b"d1:ai1e1:bi3ee").unwrap();
+        assert_eq!(hash, <[u8; 32]>::from(Sha256::digest(b"d1:ai1e1:bi3ee")));
+        assert_eq!(canonical_hash(b"d1:bi03e1:ai0e1:ai1ee"), Ok(hash));
+        assert!(canonical_hash(b"d1:ai1e1:bi4ee") != Ok(hash));
+        assert_eq!(canonical_hash(b"d1:ai1e"), Err(ParseError::Incomplete { needed: None }));
+    }
+}
@@ -4,17 +4,94 @@
 
 #![feature(vec_push_all)]
 
-use std::collections::HashMap;
+#[cfg(feature = "arena")]
+extern crate bumpalo;
+#[cfg(feature = "derive")]
+extern crate bencode_derive;
+#[cfg(feature = "wasm")]
+extern crate js_sys;
+#[cfg(feature = "json")]
+#[cfg_attr(test, macro_use)]
+extern crate serde_json;
+#[cfg(feature = "torrent")]
+extern crate sha1;
+#[cfg(feature = "canonical-hash")]
+extern crate sha2;
+#[cfg(feature = "fingerprint")]
+extern crate xxhash_rust;
+#[cfg(feature = "wasm")]
+extern crate wasm_bindgen;
+
+use std::cmp;
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::convert::TryFrom;
+use std::error;
 use std::fmt;
+use std::hash::Hasher;
+use std::io;
+use std::mem;
+use std::str;
 
 use Bencoded::*;
 
-#[derive(Debug, Eq, PartialEq)]
+pub use analyze::{analyze, try_repair, AnalysisReport};
+#[cfg(feature = "arena")]
+pub use arena::{decode_in, BencodedArena};
+pub use borrowed::{decode_borrowed, decode_borrowed_keys, BencodedBk, BencodedRef};
+pub use convert::{ConvertError, FromBencoded, ToBencoded};
+pub use encode::{encode_dict_from, integer_encoded_len, DictWriter, EncodeCache, EncodeError,
+                 ListWriter, SizeBreakdown};
+#[cfg(feature = "intern")]
+pub use intern::{decode_interned, InternedBencoded, Interner};
+pub use lazy::{decode_lazy, splice, ByteSpan, LazyBencoded};
+#[cfg(feature = "canonical-hash")]
+pub use lenient::canonical_hash;
+pub use lenient::{decode_lenient, decode_lenient_with_options, equivalent, Warning};
+pub use lint::Lint;
+pub use schema::{Schema, SchemaError};
+pub use shared::{decode_shared, ArcBencoded};
+#[cfg(feature = "derive")]
+pub use bencode_derive::FromBencoded;
+#[cfg(feature = "testing")]
+pub use testing::test_dict;
+#[cfg(feature = "torrent")]
+pub use torrent::{FileRecord, TorrentBuilder, TorrentError, TrackerTier, VerifyReport};
+#[cfg(feature = "wasm")]
+pub use wasm::decode_to_json;
+
+mod analyze;
+#[cfg(feature = "arena")]
+mod arena;
+mod borrowed;
+mod convert;
+mod encode;
+#[cfg(feature = "intern")]
+mod intern;
+#[cfg(feature = "json")]
+mod json;
+mod lazy;
+mod lenient;
+mod lint;
+mod schema;
+mod shared;
+#[cfg(any(test, feature = "testing"))]
+mod testing;
+#[cfg(feature = "torrent")]
+mod torrent;
+#[cfg(feature = "wasm")]
+mod wasm;
+
+/// The integer type used to represent bencoded integers. Every integer in this
+/// crate goes through this alias, so changing it here changes it everywhere.
+pub type Int = i64;
+
+#[derive(Clone, Eq, PartialEq)]
 pub enum Bencoded {
     /// An integer. The encoded format is `ix..xe` where `x..x` is the number
     /// encoded in base 10 ASCII. Negative numbers are permitted (prefix `-`),
     /// negative zero is not though.
-    Integer(isize),
+    Integer(Int),
 
     /// A bytestring. The encoded format is `<length>:<contents>`. The length is
     /// a number in base 10 ASCII. The contents are bytes not chars.
@@ -28,261 +105,4028 @@ pub enum Bencoded {
     /// Keys are bytestrings, which must appear lexicographically. There are no
     /// separators between elements.
     Dict(HashMap<Vec<u8>, Bencoded>),
+
+    /// A dictionary that keeps its entries in the order they were inserted,
+    /// and may hold duplicate keys. It is encoded in that order, so the output
+    /// is only canonical if the keys are sorted and unique; see
+    /// `canonicalize`.
+    OrderedDict(Vec<(Vec<u8>, Bencoded)>),
+
+    /// A value that is already encoded, and is written out verbatim instead
+    /// of being encoded again. This lets an unmodified sub-tree, such as the
+    /// `info` dict of a torrent, pass through a decode and re-encode byte for
+    /// byte. Build one with `Bencoded::raw`, which checks that the bytes are
+    /// a single canonical value. Decoding with
+    /// `DecodeOptions::preserve_integer_text` also produces them.
+    ///
+    /// Methods that look inside a `Raw` accept whatever `decode_lenient`
+    /// does. Comparisons look at the bytes, so a `Raw` is never equal to a decoded
+    /// value, even one with the same encoding.
+    Raw(Vec<u8>),
 }
 
 impl Bencoded {
+    pub fn empty_list() -> Bencoded {
+        List(Vec::new())
+    }
+
+    pub fn empty_dict() -> Bencoded {
+        Dict(HashMap::new())
+    }
+
+    /// Looks up `key` in a dict. Returns `None` if this is not a dict or the
+    /// key is missing.
     pub fn get(&self, key: &[u8]) -> Option<&Bencoded> {
-        if let &Dict(ref map) = self {
-            map.get(key)
-        } else {
-            None
-        }
+        self.get_entry(key).map(|(_, val)| val)
     }
 
-    pub fn to_bytes(&self) -> Vec<u8> {
-        let mut res = Vec::new();
+    /// Like `get`, but also returns the key as it is stored in the dict.
+    pub fn get_entry(&self, key: &[u8]) -> Option<(&[u8], &Bencoded)> {
         match *self {
-            Integer(n) => {
-                res.push(b'i');
-                res.push_all(n.to_string().as_bytes());
-                res.push(b'e');
-            },
-            Bytestring(ref v) => {
-                res.push_all(v.len().to_string().as_bytes());
-                res.push(b':');
-                res.push_all(v);
-            }
-            List(ref v) => {
-                res.push(b'l');
-                for elem in v {
-                    res.push_all(&elem.to_bytes())
-                }
-                res.push(b'e');
+            Dict(ref map) => map.get_key_value(key).map(|(k, v)| (&k[..], v)),
+            // later entries win, like they would when building a `Dict`
+            OrderedDict(ref pairs) => {
+                pairs.iter()
+                     .rev()
+                     .find(|&&(ref k, _)| &k[..] == key)
+                     .map(|&(ref k, ref v)| (&k[..], v))
             }
-            Dict(ref map) => {
-                let mut v = Vec::new();
-                for pair in map {
-                    v.push(pair);
-                }
+            _ => None,
+        }
+    }
 
-                v.sort_by(|&(a, _), &(b, _)| a.cmp(b));
+    /// Like `get`, but returns `default` if there is no value for `key`.
+    pub fn get_or<'a>(&'a self, key: &[u8], default: &'a Bencoded) -> &'a Bencoded {
+        self.get(key).unwrap_or(default)
+    }
 
-                res.push(b'd');
+    /// Returns the integer value for `key`, or `default` if it is missing or
+    /// not an integer.
+    pub fn get_int_or(&self, key: &[u8], default: Int) -> Int {
+        self.get(key).and_then(Bencoded::as_integer).unwrap_or(default)
+    }
 
-                for (key, val) in v {
-                    res.push_all(&Bytestring(key.clone()).to_bytes());
-                    res.push_all(&val.to_bytes());
-                }
+    /// Returns the integer value for `key`, which must be present and not
+    /// negative, as with `length` and `piece length` in a torrent. The error
+    /// names the key, so it can be shown as is. If this is not a dict, the
+    /// key is reported as missing.
+    pub fn require_non_negative_int(&self, key: &[u8]) -> Result<Int, FieldError> {
+        let val = self.get(key).ok_or_else(|| FieldError::Missing(key.to_vec()))?;
+        match *val {
+            Integer(n) if n < 0 => Err(FieldError::Negative { key: key.to_vec(), value: n }),
+            Integer(n) => Ok(n),
+            _ => {
+                Err(FieldError::WrongType {
+                    key: key.to_vec(),
+                    expected: "integer",
+                    found: val.type_name(),
+                })
+            }
+        }
+    }
 
-                res.push(b'e');
+    /// Returns the contents of the bytestring for `key`, which must be
+    /// present and exactly `len` bytes long, as with a 20-byte SHA-1 hash.
+    /// See `require_non_negative_int`.
+    pub fn require_fixed_bytestring(&self, key: &[u8], len: usize)
+                                    -> Result<&[u8], FieldError> {
+        let val = self.get(key).ok_or_else(|| FieldError::Missing(key.to_vec()))?;
+        match *val {
+            Bytestring(ref v) if v.len() == len => Ok(v),
+            Bytestring(ref v) => {
+                Err(FieldError::WrongLength {
+                    key: key.to_vec(),
+                    expected: len,
+                    actual: v.len(),
+                })
+            }
+            _ => {
+                Err(FieldError::WrongType {
+                    key: key.to_vec(),
+                    expected: "bytestring",
+                    found: val.type_name(),
+                })
             }
+        }
+    }
 
+    /// Returns element `i` of a list, like `get` does for a dict. Returns
+    /// `None` if this is not a list or `i` is out of bounds.
+    pub fn get_index(&self, i: usize) -> Option<&Bencoded> {
+        match *self {
+            List(ref v) => v.get(i),
+            _ => None,
         }
-        res
     }
-}
 
-impl fmt::Display for Bencoded {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    /// Like `get_index`, but the element can be changed in place.
+    pub fn get_index_mut(&mut self, i: usize) -> Option<&mut Bencoded> {
         match *self {
-            Integer(n) => {
-                write!(f, "i{}e", n)
-            },
-            Bytestring(ref v) => {
-                let mut s = String::new();
-                s.push_str(&v.len().to_string());
-                s.push(':');
-                for c in v {
-                    s.push(*c as char);
-                }
-                write!(f, "{}", s)
+            List(ref mut v) => v.get_mut(i),
+            _ => None,
+        }
+    }
+
+    /// Like `get`, but reports why the lookup failed.
+    pub fn at(&self, key: &[u8]) -> Result<&Bencoded, AccessError> {
+        match *self {
+            Dict(_) | OrderedDict(_) => {
+                self.get(key).ok_or_else(|| AccessError::MissingKey(key.to_vec()))
             }
+            _ => Err(AccessError::WrongType { expected: "dict" }),
+        }
+    }
+
+    /// Returns element `i` of a list, or reports why there is none.
+    pub fn at_index(&self, i: usize) -> Result<&Bencoded, AccessError> {
+        match *self {
             List(ref v) => {
-                let mut s = "l".to_string();
+                v.get(i).ok_or(AccessError::IndexOutOfBounds { index: i, len: v.len() })
+            }
+            _ => Err(AccessError::WrongType { expected: "list" }),
+        }
+    }
+
+    /// Sorts the entries of an `OrderedDict` by key and removes duplicate
+    /// keys, keeping the last value for each, so that it encodes canonically.
+    /// The dict stays an `OrderedDict`, and nested values are left alone.
+    ///
+    /// Does nothing to other variants; a `Dict` is always encoded
+    /// canonically.
+    pub fn canonicalize(&mut self) {
+        if let OrderedDict(ref mut pairs) = *self {
+            canonicalize_pairs(pairs);
+        }
+    }
 
+    /// Like `canonicalize`, but for every dict in the tree, so that the whole
+    /// value encodes canonically, e.g. after `decode_lenient`.
+    pub fn canonicalize_tree(&mut self) {
+        self.canonicalize();
+        match *self {
+            List(ref mut v) => {
                 for elem in v {
-                    s.push_str(&elem.to_string());
+                    elem.canonicalize_tree();
                 }
-
-                s.push('e');
-                write!(f, "{}", s)
             }
-            Dict(ref map) => {
-                let mut v = Vec::new();
-                for pair in map {
-                    v.push(pair);
+            Dict(ref mut map) => {
+                for val in map.values_mut() {
+                    val.canonicalize_tree();
+                }
+            }
+            OrderedDict(ref mut pairs) => {
+                for &mut (_, ref mut val) in pairs {
+                    val.canonicalize_tree();
                 }
+            }
+            Integer(_) | Bytestring(_) | Raw(_) => {}
+        }
+    }
+
+    /// Returns the first value in the tree, in pre-order, for which `pred`
+    /// is true. A dict's values are searched in the order they are encoded,
+    /// so the result does not depend on how the dict was built.
+    pub fn find<F: Fn(&Bencoded) -> bool>(&self, pred: F) -> Option<&Bencoded> {
+        self.find_by(&pred)
+    }
 
+    fn find_by<F: Fn(&Bencoded) -> bool>(&self, pred: &F) -> Option<&Bencoded> {
+        if pred(self) {
+            return Some(self);
+        }
+        match *self {
+            List(ref v) => v.iter().filter_map(|elem| elem.find_by(pred)).next(),
+            Dict(ref map) => {
+                let mut v: Vec<_> = map.iter().collect();
                 v.sort_by(|&(a, _), &(b, _)| a.cmp(b));
+                v.into_iter().filter_map(|(_, val)| val.find_by(pred)).next()
+            }
+            OrderedDict(ref pairs) => {
+                pairs.iter().filter_map(|&(_, ref val)| val.find_by(pred)).next()
+            }
+            Integer(_) | Bytestring(_) | Raw(_) => None,
+        }
+    }
 
-                let mut s = "d".to_string();
+    /// Removes `key` from every dict in the tree, and returns how many entries
+    /// were removed. Useful for scrubbing fields before sharing a torrent.
+    ///
+    /// `Raw` values are left as they are.
+    pub fn strip_key(&mut self, key: &[u8]) -> usize {
+        match *self {
+            List(ref mut v) => v.iter_mut().map(|elem| elem.strip_key(key)).sum(),
+            Dict(ref mut map) => {
+                let removed = if map.remove(key).is_some() { 1 } else { 0 };
+                removed + map.values_mut().map(|val| val.strip_key(key)).sum::<usize>()
+            }
+            OrderedDict(ref mut pairs) => {
+                let len = pairs.len();
+                pairs.retain(|&(ref k, _)| &k[..] != key);
+                let removed = len - pairs.len();
+                removed + pairs.iter_mut()
+                               .map(|&mut (_, ref mut val)| val.strip_key(key))
+                               .sum::<usize>()
+            }
+            Integer(_) | Bytestring(_) | Raw(_) => 0,
+        }
+    }
 
-                for (key, val) in v {
-                    s.push_str(&format!("{}{}", Bytestring(key.clone()), val))
-                }
+    /// Sets the value at `path`, a list of dict keys starting from this value,
+    /// creating empty dicts for any that are missing along the way. For
+    /// example, `set_path(&[b"info", b"private"], Integer(1))` also adds an
+    /// `info` dict if there is none. An empty `path` replaces the whole value.
+    ///
+    /// Fails with `AccessError::WrongType` if this value or an existing one on
+    /// the path is not a dict, in which case nothing is changed.
+    pub fn set_path(&mut self, path: &[&[u8]], value: Bencoded) -> Result<(), AccessError> {
+        let mut node = self;
+        for key in path {
+            node = node.entry_or_insert_with(key, Bencoded::empty_dict)?;
+        }
+        *node = value;
+        Ok(())
+    }
 
-                s.push('e');
-                write!(f, "{}", s)
+    /// Removes the value at `path`, a list of dict keys starting from this
+    /// value, and returns it, leaving the rest of the tree as it was. For
+    /// pulling `info` out of a torrent this moves it rather than cloning it
+    /// as `get(b"info").cloned()` would.
+    ///
+    /// Returns `None`, and changes nothing, if there is no value at `path`,
+    /// including when a value on the way is not a dict, or if `path` is
+    /// empty. A key repeated in an `OrderedDict` has all its entries removed,
+    /// and the last one, as `get` would find, is returned.
+    pub fn take_path(&mut self, path: &[&[u8]]) -> Option<Bencoded> {
+        let (last, init) = path.split_last()?;
+        let mut node = self;
+        for key in init {
+            node = match *node {
+                Dict(ref mut map) => map.get_mut(*key)?,
+                OrderedDict(ref mut pairs) => {
+                    pairs.iter_mut()
+                         .rev()
+                         .find(|&&mut (ref k, _)| &k[..] == *key)
+                         .map(|&mut (_, ref mut v)| v)?
+                }
+                _ => return None,
+            };
+        }
+        match *node {
+            Dict(ref mut map) => map.remove(*last),
+            OrderedDict(ref mut pairs) => {
+                let i = pairs.iter().rposition(|&(ref k, _)| &k[..] == *last)?;
+                let (_, taken) = pairs.remove(i);
+                pairs.retain(|&(ref k, _)| &k[..] != *last);
+                Some(taken)
             }
+            _ => None,
         }
     }
-}
 
-fn parse_integer(s: &[u8], mut idx: usize) -> (Bencoded, usize) {
-    let mut n = 0;
-    let arity = if let b'-' = s[idx] {
-        idx += 1;
-        -1
-    } else {
-        1
-    };
+    /// Renames the key `from` of a dict to `to`, keeping its value, and
+    /// returns whether it did. An `OrderedDict` keeps the entry where it was,
+    /// and renames every entry for `from` if it is repeated.
+    ///
+    /// Returns `false`, and changes nothing, if this is not a dict, `from` is
+    /// missing, or `to` is already a key, which includes `from` being the
+    /// same as `to`.
+    pub fn rename_key(&mut self, from: &[u8], to: &[u8]) -> bool {
+        match *self {
+            Dict(ref mut map) => {
+                if map.contains_key(to) {
+                    return false;
+                }
+                match map.remove(from) {
+                    Some(val) => {
+                        map.insert(to.to_vec(), val);
+                        true
+                    }
+                    None => false,
+                }
+            }
+            OrderedDict(ref mut pairs) => {
+                if pairs.iter().any(|&(ref k, _)| &k[..] == to) {
+                    return false;
+                }
+                let mut renamed = false;
+                for &mut (ref mut k, _) in pairs.iter_mut() {
+                    if &k[..] == from {
+                        *k = to.to_vec();
+                        renamed = true;
+                    }
+                }
+                renamed
+            }
+            _ => false,
+        }
+    }
 
-    loop {
-        match s[idx] {
-            b'e' => return (Integer(n * arity), idx + 1),
-            c => {
-                let d = (c as char).to_digit(10).unwrap();
-                n = n * 10 + d as isize;
-            },
+    /// Returns the value for `key` in a dict, inserting the result of `f` if
+    /// there is none.
+    fn entry_or_insert_with<F>(&mut self, key: &[u8], f: F) -> Result<&mut Bencoded, AccessError>
+        where F: FnOnce() -> Bencoded
+    {
+        match *self {
+            Dict(ref mut map) => Ok(map.entry(key.to_vec()).or_insert_with(f)),
+            // later entries win, like in `get_entry`
+            OrderedDict(ref mut pairs) => {
+                let i = match pairs.iter().rposition(|&(ref k, _)| &k[..] == key) {
+                    Some(i) => i,
+                    None => {
+                        pairs.push((key.to_vec(), f()));
+                        pairs.len() - 1
+                    }
+                };
+                Ok(&mut pairs[i].1)
+            }
+            _ => Err(AccessError::WrongType { expected: "dict" }),
         }
-        idx += 1;
     }
-}
 
-fn parse_bytestring(s: &[u8], mut idx: usize) -> (Bencoded, usize) {
-    let mut len = 0;
-    loop {
-        match s[idx] {
-            b':' => {
-                idx += 1;
-                break
+    /// Builds a dict from key-value pairs, which may be in any order.
+    ///
+    /// Returns `ParseError::DuplicateKey` if a key appears more than once,
+    /// with `pos` being the index in `pairs` of the second occurrence.
+    pub fn from_pairs(pairs: Vec<(Vec<u8>, Bencoded)>) -> Result<Bencoded, ParseError> {
+        let mut map = HashMap::with_capacity(pairs.len());
+        for (i, (key, val)) in pairs.into_iter().enumerate() {
+            if map.insert(key, val).is_some() {
+                return Err(ParseError::DuplicateKey { pos: i });
             }
-            c => {
-                let d = (c as char).to_digit(10).unwrap();
-                len = len * 10 + d as isize;
-            },
         }
-        idx += 1;
+        Ok(Dict(map))
     }
 
-    let mut v = Vec::new();
-    for i in 0..len {
-        v.push(s[idx+i as usize])
+    /// Builds a dict from key-value pairs that are already sorted by key with
+    /// no key repeated, e.g. because they were generated in that order. The
+    /// pairs are kept as they are in an `OrderedDict`, without hashing or
+    /// sorting anything.
+    ///
+    /// The order is only checked in debug builds, by a `debug_assert!`. In
+    /// release builds pairs out of order are trusted, and encode as they are,
+    /// i.e. not canonically.
+    pub fn from_pairs_unchecked(pairs: Vec<(Vec<u8>, Bencoded)>) -> Bencoded {
+        debug_assert!(pairs.windows(2).all(|w| w[0].0 < w[1].0),
+                      "keys are not sorted and unique");
+        OrderedDict(pairs)
     }
-    return (Bytestring(v), idx + len as usize)
-}
 
-fn parse_list(s: &[u8], mut idx: usize) -> (Bencoded, usize) {
-    let mut v = Vec::new();
-    loop {
-        match s[idx] {
-            b'e' => return (List(v), idx + 1),
-            _ => {
-                let (elem, idx_) = parse_bencoded(s, idx);
-                idx = idx_;
-                v.push(elem);
+    /// Wraps an already encoded value, to be written out as is. Fails if `v`
+    /// is not exactly one value in canonical form, with the same error as
+    /// `assert_roundtrip`.
+    pub fn raw(v: Vec<u8>) -> Result<Bencoded, ParseError> {
+        assert_roundtrip(&v)?;
+        Ok(Raw(v))
+    }
+
+    /// Returns the entries of a dict as key-value pairs sorted by key, or
+    /// `None` if this is not a dict. An `OrderedDict` is canonicalized first,
+    /// so only the last value of a duplicated key is kept.
+    pub fn into_pairs(self) -> Option<Vec<(Vec<u8>, Bencoded)>> {
+        match self {
+            Dict(map) => {
+                let mut v: Vec<_> = map.into_iter().collect();
+                v.sort_by(|a, b| a.0.cmp(&b.0));
+                Some(v)
             }
+            OrderedDict(mut pairs) => {
+                canonicalize_pairs(&mut pairs);
+                Some(pairs)
+            }
+            _ => None,
         }
     }
-}
 
-fn parse_dict(s: &[u8], mut idx: usize) -> (Bencoded, usize) {
-    let mut map = HashMap::new();
-    loop {
-        match s[idx] {
-            b'e' => return (Dict(map), idx + 1),
-            _ => {
-                // read bytestring
-                if let (Bytestring(key), idx_) = parse_bytestring(s, idx) {
-                    // read value
-                    let (val, idx_) = parse_bencoded(s, idx_);
+    /// Consumes a dict and returns its entries as owned key-value pairs sorted
+    /// by key, or `None` if this is not a dict, so that it can be turned into
+    /// another representation entry by entry, without re-sorting or cloning.
+    /// This is the same as `into_pairs`.
+    pub fn into_sorted_entries(self) -> Option<Vec<(Vec<u8>, Bencoded)>> {
+        self.into_pairs()
+    }
 
-                    // insert pair
-                    map.insert(key, val);
-                    idx = idx_;
-                } else {
-                    panic!("Couldn't parse dict");
-                }
-            }
+    /// Converts a dict into a map keyed by `String`, for dicts whose keys are
+    /// all text.
+    ///
+    /// Returns `ParseError::InvalidUtf8Key` if a key is not valid UTF-8, with
+    /// `pos` being the index of the first such key in key order, as
+    /// `into_pairs` returns them, or `ParseError::ExpectedDict` if this is
+    /// not a dict.
+    pub fn into_string_keyed(self) -> Result<HashMap<String, Bencoded>, ParseError> {
+        let pairs = self.into_pairs().ok_or(ParseError::ExpectedDict)?;
+        let mut map = HashMap::with_capacity(pairs.len());
+        for (i, (key, val)) in pairs.into_iter().enumerate() {
+            let key = String::from_utf8(key).map_err(|_| ParseError::InvalidUtf8Key { pos: i })?;
+            map.insert(key, val);
         }
+        Ok(map)
     }
-}
 
-fn parse_bencoded(s: &[u8], idx: usize) -> (Bencoded, usize) {
-    match s[idx] {
-        b'i' => parse_integer(s, idx + 1),
-        b'l' => parse_list(s, idx + 1),
-        b'd' => parse_dict(s, idx + 1),
-        _ => parse_bytestring(s, idx),
+    pub fn is_integer(&self) -> bool {
+        matches!(*self, Integer(_))
     }
-}
 
-/// Parses a bencoded string.
-pub fn parse(s: &[u8]) -> Bencoded {
-    parse_bencoded(s, 0).0
-}
+    pub fn is_bytestring(&self) -> bool {
+        matches!(*self, Bytestring(_))
+    }
 
-#[cfg(test)]
-mod tests {
-    use std::collections::HashMap;
-    use super::Bencoded::*;
+    pub fn is_list(&self) -> bool {
+        matches!(*self, List(_))
+    }
 
-    #[test]
-    fn parse_integer() {
-        assert_eq!(super::parse_integer(b"i42e", 1), (Integer(42), 4));
-        assert_eq!(super::parse_integer(b"i-42e", 1), (Integer(-42), 5));
+    /// Returns whether this is a `Dict` or an `OrderedDict`.
+    pub fn is_dict(&self) -> bool {
+        matches!(*self, Dict(_) | OrderedDict(_))
     }
 
-    #[test]
-    fn parse_bytestring() {
-        assert_eq!(super::parse_bytestring(b"5:hello", 0),
-                   (Bytestring(b"hello".to_vec()), 7));
+    /// Returns whether this is a list or a dict, i.e. whether it can contain
+    /// other values.
+    pub fn is_container(&self) -> bool {
+        self.is_list() || self.is_dict()
     }
 
-    #[test]
-    fn parse_list() {
-        assert_eq!(super::parse_list(b"li42ee", 1), (List(vec!(Integer(42))), 6));
+    /// Returns the number of bytes in a bytestring, elements in a list or
+    /// distinct keys in a dict, or `None` for an integer, which has no
+    /// length. A `Raw` has the length of what it decodes to.
+    pub fn len(&self) -> Option<usize> {
+        match *self {
+            Integer(_) => None,
+            Bytestring(ref v) => Some(v.len()),
+            List(ref v) => Some(v.len()),
+            Dict(ref map) => Some(map.len()),
+            OrderedDict(ref pairs) => Some(canonical_refs(pairs).len()),
+            Raw(ref v) => decode_raw(v).ok().and_then(|val| val.len()),
+        }
     }
 
-    #[test]
-    fn parse_dict() {
-        let mut m = HashMap::new();
-        m.insert(b"n".to_vec(), Integer(42));
-        assert_eq!(super::parse_dict(b"d1:ni42ee", 1), (Dict(m), 9));
+    /// Returns whether this is an empty bytestring, list or dict. An integer
+    /// is never empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == Some(0)
     }
 
-    #[test]
-    fn parse_bencoded() {
-        assert_eq!(super::parse_bencoded(b"i42e", 0), (Integer(42), 4));
-        assert_eq!(super::parse_bencoded(b"5:hello", 0),
-                   (Bytestring(b"hello".to_vec()), 7));
-        assert_eq!(super::parse_bencoded(b"li42ee", 0),
-                   (List(vec!(Integer(42))), 6));
-        assert_eq!(super::parse_bencoded(b"li42e5:helloe", 0),
-                   (List(vec!(Integer(42), Bytestring(b"hello".to_vec()))), 13));
+    /// Returns how many levels deep the value goes: 1 for an integer or
+    /// bytestring, 2 for a list or dict of them, even an empty one, and so
+    /// on. This is always one more than `DecodeMeta::max_depth` for the
+    /// input it was decoded from.
+    pub fn depth(&self) -> usize {
+        let deepest = |vals: &mut dyn Iterator<Item = &Bencoded>| {
+            1 + vals.map(Bencoded::depth).max().unwrap_or(1)
+        };
+        match *self {
+            Integer(_) | Bytestring(_) => 1,
+            List(ref v) => deepest(&mut v.iter()),
+            Dict(ref map) => deepest(&mut map.values()),
+            OrderedDict(ref pairs) => deepest(&mut pairs.iter().map(|&(_, ref val)| val)),
+            Raw(ref v) => decode_raw(v).map_or(1, |val| val.depth()),
+        }
+    }
 
-        let mut m = HashMap::new();
-        m.insert(b"n".to_vec(), Integer(42));
-        assert_eq!(super::parse_bencoded(b"d1:ni42ee", 0), (Dict(m), 9));
+    /// Returns the name of the variant, as used in error messages: one of
+    /// `"integer"`, `"bytestring"`, `"list"`, `"dict"` (for both kinds of
+    /// dict) and `"raw"`.
+    pub fn type_name(&self) -> &'static str {
+        match *self {
+            Integer(_) => "integer",
+            Bytestring(_) => "bytestring",
+            List(_) => "list",
+            Dict(_) | OrderedDict(_) => "dict",
+            Raw(_) => "raw",
+        }
     }
 
-    #[test]
-    fn test_to_bytes() {
-        let bytes = b"i42e";
-        assert_eq!(super::parse(bytes).to_bytes(),
-                   bytes);
+    /// Returns whether every bytestring and dict key in the tree is valid
+    /// UTF-8, e.g. to check that the value can be converted to string-keyed
+    /// JSON without loss.
+    pub fn all_strings_utf8(&self) -> bool {
+        match *self {
+            Integer(_) => true,
+            Bytestring(ref v) => str::from_utf8(v).is_ok(),
+            List(ref v) => v.iter().all(Bencoded::all_strings_utf8),
+            Dict(ref map) => {
+                map.iter().all(|(key, val)| {
+                    str::from_utf8(key).is_ok() && val.all_strings_utf8()
+                })
+            }
+            OrderedDict(ref pairs) => {
+                pairs.iter().all(|&(ref key, ref val)| {
+                    str::from_utf8(key).is_ok() && val.all_strings_utf8()
+                })
+            }
+            Raw(ref v) => decode_raw(v).is_ok_and(|val| val.all_strings_utf8()),
+        }
+    }
 
-        let bytes = b"5:hello";
-        assert_eq!(super::parse(bytes).to_bytes(),
-                   bytes);
+    /// Returns a signature of the structure, ignoring the values, e.g.
+    /// `d(announce:s,info:d(length:i,name:s,pieces:s))`. Integers are `i`,
+    /// bytestrings `s`, and dicts list their keys in sorted order.
+    ///
+    /// A list shows the distinct shapes of its elements in the order they
+    /// first appear, so e.g. torrents with different numbers of files can
+    /// still have the same shape.
+    pub fn shape(&self) -> String {
+        match *self {
+            Integer(_) => "i".to_string(),
+            Bytestring(_) => "s".to_string(),
+            List(ref v) => {
+                let mut shapes: Vec<String> = Vec::new();
+                for elem in v {
+                    let shape = elem.shape();
+                    if !shapes.contains(&shape) {
+                        shapes.push(shape);
+                    }
+                }
+                format!("l({})", shapes.join(","))
+            }
+            Dict(ref map) => {
+                let mut v: Vec<_> = map.iter().collect();
+                v.sort_by(|&(a, _), &(b, _)| a.cmp(b));
+                dict_shape(v)
+            }
+            OrderedDict(ref pairs) => {
+                dict_shape(canonical_refs(pairs))
+            }
+            Raw(ref v) => decode_raw(v).map(|val| val.shape()).unwrap_or_else(|_| "r".to_string()),
+        }
+    }
 
-        let bytes = b"li42e3:fooe";
-        assert_eq!(super::parse(bytes).to_bytes(),
-                   bytes);
+    /// Returns the elements of a list, or the values of a dict in the order
+    /// they are encoded. Other values have no children.
+    pub fn children(&self) -> Vec<&Bencoded> {
+        match *self {
+            List(ref v) => v.iter().collect(),
+            Dict(ref map) => {
+                let mut v: Vec<_> = map.iter().collect();
+                v.sort_by(|&(a, _), &(b, _)| a.cmp(b));
+                v.into_iter().map(|(_, val)| val).collect()
+            }
+            OrderedDict(ref pairs) => pairs.iter().map(|&(_, ref val)| val).collect(),
+            Integer(_) | Bytestring(_) | Raw(_) => Vec::new(),
+        }
+    }
 
-        let bytes = b"d3:fooi42ee";
-        assert_eq!(super::parse(bytes).to_bytes(),
-                   bytes);
+    /// Returns the keys of a dict in sorted order, borrowed rather than
+    /// copied, or `None` if this is not a dict. A key repeated in an
+    /// `OrderedDict` is only returned once.
+    pub fn sorted_keys(&self) -> Option<Vec<&[u8]>> {
+        let mut keys: Vec<&[u8]> = match *self {
+            Dict(ref map) => map.keys().map(|k| &k[..]).collect(),
+            OrderedDict(ref pairs) => pairs.iter().map(|&(ref k, _)| &k[..]).collect(),
+            _ => return None,
+        };
+        keys.sort();
+        keys.dedup();
+        Some(keys)
+    }
+
+    /// Returns every dict key used anywhere in this value, at any depth, e.g.
+    /// to find out which fields appear across a collection of files. A `Raw`
+    /// value is searched for what it decodes to.
+    pub fn all_keys(&self) -> BTreeSet<Vec<u8>> {
+        let mut keys = BTreeSet::new();
+        self.push_keys(&mut keys);
+        keys
+    }
+
+    fn push_keys(&self, keys: &mut BTreeSet<Vec<u8>>) {
+        match *self {
+            Integer(_) | Bytestring(_) => {}
+            List(ref v) => {
+                for elem in v {
+                    elem.push_keys(keys);
+                }
+            }
+            Dict(ref map) => {
+                for (key, val) in map {
+                    keys.insert(key.clone());
+                    val.push_keys(keys);
+                }
+            }
+            OrderedDict(ref pairs) => {
+                for &(ref key, ref val) in pairs {
+                    keys.insert(key.clone());
+                    val.push_keys(keys);
+                }
+            }
+            Raw(ref v) => {
+                if let Ok(val) = decode_raw(v) {
+                    val.push_keys(keys);
+                }
+            }
+        }
+    }
+
+    /// Returns every integer in this value along with the path leading to
+    /// it, e.g. to look for negative lengths anywhere in a torrent. Dicts are
+    /// visited in key order, so the result is always in the same order; a key
+    /// repeated in an `OrderedDict` is only visited for its last value, and a
+    /// `Raw` value for what it decodes to.
+    pub fn integers(&self) -> Vec<(Vec<PathSegment>, Int)> {
+        let mut res = Vec::new();
+        self.push_integers(&mut Vec::new(), &mut res);
+        res
+    }
+
+    fn push_integers(&self,
+                     path: &mut Vec<PathSegment>,
+                     res: &mut Vec<(Vec<PathSegment>, Int)>) {
+        match *self {
+            Integer(n) => res.push((path.clone(), n)),
+            Bytestring(_) => {}
+            List(ref v) => {
+                for (i, val) in v.iter().enumerate() {
+                    path.push(PathSegment::Index(i));
+                    val.push_integers(path, res);
+                    path.pop();
+                }
+            }
+            Dict(ref map) => {
+                let mut pairs: Vec<_> = map.iter().collect();
+                pairs.sort_by(|&(a, _), &(b, _)| a.cmp(b));
+                push_pair_integers(&pairs, path, res);
+            }
+            OrderedDict(ref pairs) => push_pair_integers(&canonical_refs(pairs), path, res),
+            Raw(ref v) => {
+                if let Ok(val) = decode_raw(v) {
+                    val.push_integers(path, res);
+                }
+            }
+        }
+    }
+
+    /// Returns the contents of a bytestring, or `None` if this is not one.
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match *self {
+            Bytestring(ref v) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// Like `as_bytes`, but moves the contents out instead of borrowing them.
+    pub fn into_bytestring(self) -> Option<Vec<u8>> {
+        match self {
+            Bytestring(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// Returns the integer, or `None` if this is not one.
+    pub fn as_integer(&self) -> Option<Int> {
+        match *self {
+            Integer(n) => Some(n),
+            _ => None,
+        }
+    }
+
+    /// Returns the integer as a `u64`, or `None` if this is not an integer or
+    /// if it is negative.
+    ///
+    /// Length-like fields should be read this way rather than with an `as`
+    /// cast, which would silently turn a negative length into a huge one.
+    pub fn as_u64(&self) -> Option<u64> {
+        match *self {
+            Integer(n) if n >= 0 => Some(n as u64),
+            _ => None,
+        }
+    }
+
+    /// Returns the integer as a `usize`, or `None` if this is not an integer
+    /// or if it is negative. See `as_u64`.
+    pub fn as_usize(&self) -> Option<usize> {
+        match *self {
+            Integer(n) if n >= 0 => Some(n as usize),
+            _ => None,
+        }
+    }
+
+    /// Returns the elements of a list of exactly `N` elements as an array,
+    /// for positional formats such as a `[host, port]` pair, or `None` if
+    /// this is not a list or its length is not `N`.
+    pub fn as_fixed<const N: usize>(&self) -> Option<&[Bencoded; N]> {
+        match *self {
+            List(ref v) => <&[Bencoded; N]>::try_from(&v[..]).ok(),
+            _ => None,
+        }
+    }
+
+    /// Returns the two elements of a list of exactly two elements. See
+    /// `as_fixed`.
+    pub fn as_pair(&self) -> Option<(&Bencoded, &Bencoded)> {
+        self.as_fixed::<2>().map(|&[ref a, ref b]| (a, b))
+    }
+
+    /// Returns the elements of a list of integers, or `None` if this is not a
+    /// list or any element is not an integer.
+    pub fn as_int_list(&self) -> Option<Vec<Int>> {
+        if let &List(ref v) = self {
+            let mut res = Vec::with_capacity(v.len());
+            for elem in v {
+                match *elem {
+                    Integer(n) => res.push(n),
+                    _ => return None,
+                }
+            }
+            Some(res)
+        } else {
+            None
+        }
+    }
+
+    /// Returns a list of integers as bytes, for payloads that store byte
+    /// arrays as lists. Returns `None` if this is not a list, or if any
+    /// element is not an integer in `0..256`.
+    pub fn as_byte_list(&self) -> Option<Vec<u8>> {
+        if let &List(ref v) = self {
+            let mut res = Vec::with_capacity(v.len());
+            for elem in v {
+                match *elem {
+                    Integer(n) => res.push(u8::try_from(n).ok()?),
+                    _ => return None,
+                }
+            }
+            Some(res)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the sole element of a one-element list, or `None` for anything
+    /// else. Handy for APIs that sometimes wrap a scalar in a list.
+    pub fn as_single(&self) -> Option<&Bencoded> {
+        match *self {
+            List(ref v) if v.len() == 1 => Some(&v[0]),
+            _ => None,
+        }
+    }
+
+    /// Returns the elements of a list, or any other value wrapped in a
+    /// one-element `Vec`; the inverse of `as_single`. This is a convenience
+    /// for loose formats that only sometimes wrap a scalar in a list, not
+    /// something the spec has any notion of.
+    pub fn into_list_or_wrap(self) -> Vec<Bencoded> {
+        match self {
+            List(v) => v,
+            val => vec!(val),
+        }
+    }
+
+    /// Returns `Some(true)` for `Integer(1)`, `Some(false)` for `Integer(0)`,
+    /// and `None` for anything else. Flags such as a torrent's `private` field
+    /// are encoded this way.
+    pub fn as_bool(&self) -> Option<bool> {
+        match *self {
+            Integer(0) => Some(false),
+            Integer(1) => Some(true),
+            _ => None,
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut res = Vec::new();
+        self.push_bytes(&mut res);
+        res
+    }
+
+    /// Appends the encoding to `res`, so that nested values don't each need
+    /// a buffer of their own.
+    fn push_bytes(&self, res: &mut Vec<u8>) {
+        match *self {
+            Integer(n) => {
+                res.push(b'i');
+                if n < 0 {
+                    res.push(b'-');
+                }
+                res.push_all(uint_digits(n.unsigned_abs(), &mut [0; 20]));
+                res.push(b'e');
+            },
+            Bytestring(ref v) => push_bytestring(res, v),
+            List(ref v) => {
+                res.push(b'l');
+                for elem in v {
+                    elem.push_bytes(res);
+                }
+                res.push(b'e');
+            }
+            Dict(ref map) => {
+                let mut v = Vec::new();
+                for pair in map {
+                    v.push(pair);
+                }
+
+                v.sort_by(|&(a, _), &(b, _)| a.cmp(b));
+
+                res.push(b'd');
+
+                for (key, val) in v {
+                    push_bytestring(res, key);
+                    val.push_bytes(res);
+                }
+
+                res.push(b'e');
+            }
+            OrderedDict(ref pairs) => {
+                res.push(b'd');
+
+                for &(ref key, ref val) in pairs {
+                    push_bytestring(res, key);
+                    val.push_bytes(res);
+                }
+
+                res.push(b'e');
+            }
+            Raw(ref v) => res.push_all(v),
+        }
+    }
+
+    /// Returns the canonical encoding as a string that is safe to log.
+    ///
+    /// Bytestring contents that are not printable ASCII are rendered as
+    /// `\xNN`, and so is the backslash itself, so the output stays
+    /// unambiguous. Length prefixes still count the raw bytes.
+    pub fn to_escaped_string(&self) -> String {
+        let mut s = String::new();
+        match *self {
+            Integer(n) => {
+                s.push_str(&format!("i{}e", n));
+            },
+            Bytestring(ref v) => {
+                s.push_str(&v.len().to_string());
+                s.push(':');
+                push_escaped(&mut s, v);
+            }
+            List(ref v) => {
+                s.push('l');
+                for elem in v {
+                    s.push_str(&elem.to_escaped_string());
+                }
+                s.push('e');
+            }
+            Dict(ref map) => {
+                let mut v = Vec::new();
+                for pair in map {
+                    v.push(pair);
+                }
+
+                v.sort_by(|&(a, _), &(b, _)| a.cmp(b));
+
+                s.push('d');
+
+                for (key, val) in v {
+                    s.push_str(&Bytestring(key.clone()).to_escaped_string());
+                    s.push_str(&val.to_escaped_string());
+                }
+
+                s.push('e');
+            }
+            OrderedDict(ref pairs) => {
+                s.push('d');
+
+                for &(ref key, ref val) in pairs {
+                    s.push_str(&Bytestring(key.clone()).to_escaped_string());
+                    s.push_str(&val.to_escaped_string());
+                }
+
+                s.push('e');
+            }
+            Raw(ref v) => {
+                match decode_raw(v) {
+                    Ok(val) => s.push_str(&val.to_escaped_string()),
+                    // not a value after all, so escape all of it
+                    Err(_) => push_escaped_all(&mut s, v),
+                }
+            }
+        }
+        s
+    }
+
+    /// Returns the canonical encoding laid out with one token per line and
+    /// each nesting level indented, e.g. for reading the structure of a
+    /// torrent while debugging:
+    ///
+    /// ```text
+    /// d
+    ///   8:announce 3:url
+    ///   4:info d
+    ///     6:length i1e
+    ///   e
+    /// e
+    /// ```
+    ///
+    /// Dict keys share a line with their value. Bytestrings are escaped as by
+    /// `to_escaped_string`, and only the first 32 bytes of longer ones are
+    /// shown, followed by `…`. The length prefix still counts all of them.
+    ///
+    /// This uses the default `PrettyOptions`; see
+    /// `to_indented_bencode_with_options` to change them.
+    pub fn to_indented_bencode(&self) -> String {
+        self.to_indented_bencode_with_options(&PrettyOptions::default())
+    }
+
+    /// Like `to_indented_bencode`, but laid out according to `opts`, e.g. with
+    /// less indentation and shorter bytestrings for a deep torrent, or with
+    /// every byte shown for a small message.
+    pub fn to_indented_bencode_with_options(&self, opts: &PrettyOptions) -> String {
+        let mut s = String::new();
+        self.push_indented(&mut s, 0, opts);
+        s
+    }
+
+    fn push_indented(&self, s: &mut String, depth: usize, opts: &PrettyOptions) {
+        match *self {
+            Integer(n) => s.push_str(&format!("i{}e", n)),
+            Bytestring(ref v) => {
+                s.push_str(&v.len().to_string());
+                s.push(':');
+                let shown = &v[..cmp::min(v.len(), opts.max_bytestring_display)];
+                if opts.hex_non_utf8 && str::from_utf8(v).is_err() {
+                    s.push('<');
+                    for c in shown {
+                        s.push_str(&format!("{:02x}", c));
+                    }
+                    s.push('>');
+                } else {
+                    push_escaped(s, shown);
+                }
+                if shown.len() < v.len() {
+                    s.push('…');
+                }
+            }
+            List(ref v) if v.is_empty() => s.push_str("le"),
+            List(ref v) => {
+                s.push('l');
+                for elem in v {
+                    s.push('\n');
+                    push_indent(s, depth + 1, opts);
+                    elem.push_indented(s, depth + 1, opts);
+                }
+                s.push('\n');
+                push_indent(s, depth, opts);
+                s.push('e');
+            }
+            Dict(ref map) => {
+                let mut pairs: Vec<_> = map.iter().collect();
+                pairs.sort_by(|&(a, _), &(b, _)| a.cmp(b));
+                push_indented_pairs(s, &pairs, depth, opts);
+            }
+            OrderedDict(ref pairs) => {
+                let pairs: Vec<_> = pairs.iter().map(|&(ref k, ref v)| (k, v)).collect();
+                push_indented_pairs(s, &pairs, depth, opts);
+            }
+            Raw(ref v) => {
+                match decode_raw(v) {
+                    Ok(val) => val.push_indented(s, depth, opts),
+                    Err(_) => push_escaped_all(s, v),
+                }
+            }
+        }
+    }
+
+    /// Returns a text form meant to be kept under version control, where a
+    /// small change to the value makes a small diff: one line for each
+    /// integer, bytestring and empty list or dict, giving its path and value,
+    /// in key order, e.g.
+    ///
+    /// ```text
+    /// announce = "http://tracker/announce"
+    /// info.files[0].length = 1
+    /// info.files[0].path[0] = "a"
+    /// info.pieces = <40 bytes, hash 810c03bbda7711b9>
+    /// ```
+    ///
+    /// Bytestrings that are UTF-8 are quoted, with `"`, `\` and control
+    /// characters escaped as `\xNN`, and others are shown as hex. Those over
+    /// 32 bytes are instead shown by their length and `content_hash`, so that
+    /// `pieces` takes one line. Dict keys are escaped as by
+    /// `to_escaped_string`. A value that isn't a list or dict is a single line
+    /// with no path.
+    ///
+    /// The output is the same for a value however it was built, and this
+    /// format will not change.
+    pub fn to_stable_text(&self) -> String {
+        let mut s = String::new();
+        self.push_stable_text(&mut String::new(), &mut s);
+        s
+    }
+
+    fn push_stable_text(&self, path: &mut String, s: &mut String) {
+        let line = match *self {
+            Integer(n) => n.to_string(),
+            Bytestring(ref v) if v.len() > SHORT_MAX_BYTES => {
+                format!("<{} bytes, hash {:016x}>", v.len(), self.content_hash())
+            }
+            Bytestring(ref v) => match str::from_utf8(v) {
+                Ok(text) => {
+                    let mut line = String::from("\"");
+                    for c in text.chars() {
+                        if c == '"' || c == '\\' || c.is_control() {
+                            let mut buf = [0; 4];
+                            push_escaped_all(&mut line, c.encode_utf8(&mut buf).as_bytes());
+                        } else {
+                            line.push(c);
+                        }
+                    }
+                    line.push('"');
+                    line
+                }
+                Err(_) => v.iter().map(|b| format!("{:02x}", b)).collect(),
+            },
+            List(ref v) if v.is_empty() => "[]".to_string(),
+            List(ref v) => {
+                for (i, elem) in v.iter().enumerate() {
+                    let len = path.len();
+                    path.push_str(&format!("[{}]", i));
+                    elem.push_stable_text(path, s);
+                    path.truncate(len);
+                }
+                return;
+            }
+            Dict(ref map) => {
+                let mut pairs: Vec<_> = map.iter().collect();
+                pairs.sort_by(|&(a, _), &(b, _)| a.cmp(b));
+                return push_stable_pairs(&pairs, path, s);
+            }
+            OrderedDict(ref pairs) => {
+                return push_stable_pairs(&canonical_refs(pairs), path, s);
+            }
+            Raw(ref v) => {
+                match decode_raw(v) {
+                    Ok(val) => return val.push_stable_text(path, s),
+                    Err(_) => v.iter().map(|b| format!("{:02x}", b)).collect(),
+                }
+            }
+        };
+        if !path.is_empty() {
+            s.push_str(path);
+            s.push_str(" = ");
+        }
+        s.push_str(&line);
+        s.push('\n');
+    }
+
+    /// Returns a copy in which every bytestring longer than `max` bytes is cut
+    /// down to its first `max` bytes followed by a marker such as `…(100
+    /// bytes)`, e.g. so that the `pieces` of a torrent can be printed with
+    /// `{:?}`. Dict keys are left whole, and a `Raw` value is truncated by what
+    /// it decodes to.
+    ///
+    /// The result is only meant for inspection: it no longer says what the
+    /// original did.
+    pub fn with_truncated_strings(&self, max: usize) -> Bencoded {
+        match *self {
+            Integer(n) => Integer(n),
+            Bytestring(ref v) if v.len() > max => {
+                let mut res = v[..max].to_vec();
+                res.push_all(format!("…({} bytes)", v.len()).as_bytes());
+                Bytestring(res)
+            }
+            Bytestring(ref v) => Bytestring(v.clone()),
+            List(ref v) => List(v.iter().map(|val| val.with_truncated_strings(max)).collect()),
+            Dict(ref map) => {
+                let map = map.iter().map(|(k, v)| (k.clone(), v.with_truncated_strings(max)));
+                Dict(map.collect())
+            }
+            OrderedDict(ref pairs) => {
+                let pairs = pairs.iter().map(|&(ref k, ref v)| {
+                    (k.clone(), v.with_truncated_strings(max))
+                });
+                OrderedDict(pairs.collect())
+            }
+            Raw(ref v) => {
+                match decode_raw(v) {
+                    Ok(val) => val.with_truncated_strings(max),
+                    Err(_) => Raw(v.clone()),
+                }
+            }
+        }
+    }
+
+    /// Returns a 64-bit hash of the canonical encoding, which is the same on
+    /// every run and platform, e.g. for use as a cache key. A dict hashes the
+    /// same however it was built; an `OrderedDict` hashes like the `Dict` it
+    /// would canonicalize to, even with its keys out of order, and a `Raw`
+    /// like the value it decodes to, as described for `encode_to_hasher`.
+    ///
+    /// This is FNV-1a, which is fast but not cryptographic, so the hash must
+    /// not be trusted to tell apart values chosen by an attacker.
+    pub fn content_hash(&self) -> u64 {
+        let mut hasher = Fnv1a(FNV_OFFSET_BASIS);
+        self.encode_to_hasher(&mut hasher);
+        hasher.0
+    }
+
+    /// Returns a 128-bit fingerprint of the canonical encoding, for telling
+    /// whether a value has changed, e.g. to invalidate a cache. Like
+    /// `content_hash`, it is the same on every run and platform, and it is
+    /// not cryptographic.
+    ///
+    /// This is XXH3 with the default seed.
+    #[cfg(feature = "fingerprint")]
+    pub fn fingerprint(&self) -> u128 {
+        let mut hasher = xxhash_rust::xxh3::Xxh3::new();
+        self.encode_to_hasher(&mut hasher);
+        hasher.digest128()
+    }
+
+    /// Feeds the canonical encoding to `hasher` in pieces, without building
+    /// it in memory first, so hashing it with any `Hasher` gives the same
+    /// result as hashing `to_bytes` of the value after `canonicalize_tree`.
+    /// `content_hash`, `fingerprint` and `canonical_hash` are all built on this.
+    ///
+    /// Unlike `to_bytes`, this hashes a `Raw` that isn't canonical, such as
+    /// the `Raw(b"i03e")` that `preserve_integer_text` makes, as the value it
+    /// decodes to, so that equal values hash the same. A `Raw` that doesn't
+    /// decode at all is hashed as it is.
+    ///
+    /// Only `Hasher::write` is called, with no length prefixes or other
+    /// framing, so the hash depends on nothing but the encoded bytes.
+    pub fn encode_to_hasher<H: Hasher>(&self, hasher: &mut H) {
+        match *self {
+            Integer(n) => {
+                hasher.write(if n < 0 { b"i-" } else { b"i" });
+                hasher.write(uint_digits(n.unsigned_abs(), &mut [0; 20]));
+                hasher.write(b"e");
+            }
+            Bytestring(ref v) => hash_bytes(v, hasher),
+            List(ref v) => {
+                hasher.write(b"l");
+                for elem in v {
+                    elem.encode_to_hasher(hasher);
+                }
+                hasher.write(b"e");
+            }
+            Dict(ref map) => {
+                let mut v: Vec<_> = map.iter().collect();
+                v.sort_by(|&(a, _), &(b, _)| a.cmp(b));
+                hash_pairs(v, hasher);
+            }
+            OrderedDict(ref pairs) => {
+                hash_pairs(canonical_refs(pairs), hasher);
+            }
+            // canonical already if built with `Bencoded::raw`, so only
+            // decoded if it isn't
+            Raw(ref v) if is_canonical(v) => hasher.write(v),
+            Raw(ref v) => {
+                match decode_raw(v) {
+                    Ok(val) => val.encode_to_hasher(hasher),
+                    Err(_) => hasher.write(v),
+                }
+            }
+        }
+    }
+}
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+struct Fnv1a(u64);
+
+impl Hasher for Fnv1a {
+    fn write(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.0 = (self.0 ^ b as u64).wrapping_mul(FNV_PRIME);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+fn hash_bytes<H: Hasher>(v: &[u8], hasher: &mut H) {
+    hasher.write(uint_digits(v.len() as u64, &mut [0; 20]));
+    hasher.write(b":");
+    hasher.write(v);
+}
+
+fn hash_pairs<H: Hasher>(pairs: Vec<(&Vec<u8>, &Bencoded)>, hasher: &mut H) {
+    hasher.write(b"d");
+    for (key, val) in pairs {
+        hash_bytes(key, hasher);
+        val.encode_to_hasher(hasher);
+    }
+    hasher.write(b"e");
+}
+
+fn dict_shape(pairs: Vec<(&Vec<u8>, &Bencoded)>) -> String {
+    let entries: Vec<_> = pairs.into_iter()
+                               .map(|(key, val)| {
+                                   format!("{}:{}", String::from_utf8_lossy(key), val.shape())
+                               })
+                               .collect();
+    format!("d({})", entries.join(","))
+}
+
+/// Options controlling how the pretty-printers lay out a value:
+/// `to_indented_bencode_with_options`, and with the `json` feature,
+/// `write_json_with_options` and `to_json_string_with_options`. The defaults
+/// are what `to_indented_bencode` uses.
+#[derive(Clone, Debug)]
+pub struct PrettyOptions {
+    /// How many spaces each nesting level is indented by. Defaults to 2.
+    pub indent: usize,
+
+    /// How many bytes of a bytestring, or of a dict key, are shown before the
+    /// rest is cut short with `…`. Defaults to 32; use `usize::MAX` to show
+    /// everything.
+    pub max_bytestring_display: usize,
+
+    /// Show bytestrings that aren't UTF-8 as hex in angle brackets, e.g.
+    /// `2:<ff5c>`, rather than escaped, which suits hashes and other binary
+    /// data better.
+    pub hex_non_utf8: bool,
+}
+
+impl Default for PrettyOptions {
+    fn default() -> PrettyOptions {
+        PrettyOptions {
+            indent: 2,
+            max_bytestring_display: SHORT_MAX_BYTES,
+            hex_non_utf8: false,
+        }
+    }
+}
+
+/// Bytestrings longer than this are cut short by `to_indented_bencode`, and
+/// shown by their hash by `to_stable_text`.
+const SHORT_MAX_BYTES: usize = 32;
+
+fn push_stable_pairs(pairs: &[(&Vec<u8>, &Bencoded)], path: &mut String, s: &mut String) {
+    if pairs.is_empty() {
+        if !path.is_empty() {
+            s.push_str(path);
+            s.push_str(" = ");
+        }
+        return s.push_str("{}\n");
+    }
+    for &(key, val) in pairs {
+        let len = path.len();
+        if len > 0 {
+            path.push('.');
+        }
+        push_escaped(path, key);
+        val.push_stable_text(path, s);
+        path.truncate(len);
+    }
+}
+
+fn push_indented_pairs(s: &mut String,
+                       pairs: &[(&Vec<u8>, &Bencoded)],
+                       depth: usize,
+                       opts: &PrettyOptions) {
+    if pairs.is_empty() {
+        return s.push_str("de");
+    }
+    s.push('d');
+    for &(key, val) in pairs {
+        s.push('\n');
+        push_indent(s, depth + 1, opts);
+        Bytestring(key.clone()).push_indented(s, depth + 1, opts);
+        s.push(' ');
+        val.push_indented(s, depth + 1, opts);
+    }
+    s.push('\n');
+    push_indent(s, depth, opts);
+    s.push('e');
+}
+
+fn push_indent(s: &mut String, depth: usize, opts: &PrettyOptions) {
+    for _ in 0..depth * opts.indent {
+        s.push(' ');
+    }
+}
+
+/// Pushes `v` with everything but printable ASCII, and the backslash, as
+/// `\xNN`.
+fn push_escaped(s: &mut String, v: &[u8]) {
+    for &c in v {
+        if (0x20..0x7f).contains(&c) && c != b'\\' {
+            s.push(c as char);
+        } else {
+            s.push_str(&format!("\\x{:02x}", c));
+        }
+    }
+}
+
+/// Pushes every byte of `v` as `\xNN`.
+fn push_escaped_all(s: &mut String, v: &[u8]) {
+    for &c in v {
+        s.push_str(&format!("\\x{:02x}", c));
+    }
+}
+
+fn push_bytestring(res: &mut Vec<u8>, v: &[u8]) {
+    res.push_all(uint_digits(v.len() as u64, &mut [0; 20]));
+    res.push(b':');
+    res.push_all(v);
+}
+
+/// Writes the decimal digits of `n` to the end of `buf`, and returns them.
+/// This avoids allocating a `String` for every length and integer encoded.
+fn uint_digits(mut n: u64, buf: &mut [u8; 20]) -> &[u8] {
+    let mut start = buf.len();
+    loop {
+        start -= 1;
+        buf[start] = b'0' + (n % 10) as u8;
+        n /= 10;
+        if n == 0 {
+            return &buf[start..];
+        }
+    }
+}
+
+/// Returns the entries that `canonicalize_pairs` would keep, without
+/// modifying `pairs`.
+fn canonical_refs(pairs: &[(Vec<u8>, Bencoded)]) -> Vec<(&Vec<u8>, &Bencoded)> {
+    let mut sorted: Vec<_> = pairs.iter().map(|&(ref key, ref val)| (key, val)).collect();
+    // the sort is stable, so duplicates stay in insertion order
+    sorted.sort_by(|&(a, _), &(b, _)| a.cmp(b));
+
+    let mut v: Vec<(&Vec<u8>, &Bencoded)> = Vec::with_capacity(sorted.len());
+    for (key, val) in sorted {
+        if v.last().is_some_and(|&(last, _)| last == key) {
+            v.pop();
+        }
+        v.push((key, val));
+    }
+    v
+}
+
+fn push_pair_integers(pairs: &[(&Vec<u8>, &Bencoded)],
+                      path: &mut Vec<PathSegment>,
+                      res: &mut Vec<(Vec<PathSegment>, Int)>) {
+    for &(key, val) in pairs {
+        path.push(PathSegment::Key(key.clone()));
+        val.push_integers(path, res);
+        path.pop();
+    }
+}
+
+/// Sorts `pairs` by key and removes duplicate keys, keeping the last value.
+fn canonicalize_pairs(pairs: &mut Vec<(Vec<u8>, Bencoded)>) {
+    // the sort is stable, so duplicates stay in insertion order
+    pairs.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let old = mem::take(pairs);
+    for (key, val) in old {
+        if pairs.last().is_some_and(|last| last.0 == key) {
+            pairs.pop();
+        }
+        pairs.push((key, val));
+    }
+}
+
+impl From<bool> for Bencoded {
+    fn from(b: bool) -> Bencoded {
+        Integer(if b { 1 } else { 0 })
+    }
+}
+
+/// Builds a `Dict`. The map's keys are sorted and unique, so unlike
+/// `from_pairs` this can't fail, and its order is trusted rather than checked.
+impl From<BTreeMap<Vec<u8>, Bencoded>> for Bencoded {
+    fn from(map: BTreeMap<Vec<u8>, Bencoded>) -> Bencoded {
+        let mut res = HashMap::with_capacity(map.len());
+        res.extend(map);
+        Dict(res)
+    }
+}
+
+/// An integer equals an `Int` of the same value.
+impl PartialEq<Int> for Bencoded {
+    fn eq(&self, other: &Int) -> bool {
+        match *self {
+            Integer(n) => n == *other,
+            _ => false,
+        }
+    }
+}
+
+/// A bytestring equals a string with the same bytes.
+impl<'a> PartialEq<&'a str> for Bencoded {
+    fn eq(&self, other: &&'a str) -> bool {
+        *self == *other.as_bytes()
+    }
+}
+
+/// A bytestring equals a byte slice with the same contents.
+impl PartialEq<[u8]> for Bencoded {
+    fn eq(&self, other: &[u8]) -> bool {
+        match *self {
+            Bytestring(ref v) => &v[..] == other,
+            _ => false,
+        }
+    }
+}
+
+/// Shows bytestrings as quoted strings when they are valid UTF-8, and as
+/// `<hex>` otherwise.
+impl fmt::Debug for Bencoded {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Integer(n) => f.debug_tuple("Integer").field(&n).finish(),
+            Bytestring(ref v) => {
+                f.debug_tuple("Bytestring").field(&DebugBytes(v)).finish()
+            }
+            List(ref v) => f.debug_tuple("List").field(v).finish(),
+            Dict(ref map) => {
+                f.debug_tuple("Dict").field(&DebugDict(map)).finish()
+            }
+            OrderedDict(ref pairs) => {
+                let v: Vec<_> = pairs.iter()
+                                     .map(|&(ref k, ref v)| (DebugBytes(k), v))
+                                     .collect();
+                f.debug_tuple("OrderedDict").field(&v).finish()
+            }
+            Raw(ref v) => f.debug_tuple("Raw").field(&DebugBytes(v)).finish(),
+        }
+    }
+}
+
+struct DebugBytes<'a>(&'a [u8]);
+
+/// Shows a dict's entries sorted by key, so the output is deterministic.
+struct DebugDict<'a>(&'a HashMap<Vec<u8>, Bencoded>);
+
+impl<'a> fmt::Debug for DebugDict<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut v: Vec<_> = self.0.iter().collect();
+        v.sort_by(|&(a, _), &(b, _)| a.cmp(b));
+
+        let mut m = f.debug_map();
+        for (key, val) in v {
+            m.entry(&DebugBytes(key), val);
+        }
+        m.finish()
+    }
+}
+
+impl<'a> fmt::Debug for DebugBytes<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match str::from_utf8(self.0) {
+            Ok(s) => write!(f, "{:?}", s),
+            Err(_) => {
+                write!(f, "<")?;
+                for c in self.0 {
+                    write!(f, "{:02x}", c)?;
+                }
+                write!(f, ">")
+            }
+        }
+    }
+}
+
+impl fmt::Display for Bencoded {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Integer(n) => {
+                write!(f, "i{}e", n)
+            },
+            Bytestring(ref v) => {
+                let mut s = String::new();
+                s.push_str(&v.len().to_string());
+                s.push(':');
+                for c in v {
+                    s.push(*c as char);
+                }
+                write!(f, "{}", s)
+            }
+            List(ref v) => {
+                let mut s = "l".to_string();
+
+                for elem in v {
+                    s.push_str(&elem.to_string());
+                }
+
+                s.push('e');
+                write!(f, "{}", s)
+            }
+            Dict(ref map) => {
+                let mut v = Vec::new();
+                for pair in map {
+                    v.push(pair);
+                }
+
+                v.sort_by(|&(a, _), &(b, _)| a.cmp(b));
+
+                let mut s = "d".to_string();
+
+                for (key, val) in v {
+                    s.push_str(&format!("{}{}", Bytestring(key.clone()), val))
+                }
+
+                s.push('e');
+                write!(f, "{}", s)
+            }
+            OrderedDict(ref pairs) => {
+                let mut s = "d".to_string();
+
+                for &(ref key, ref val) in pairs {
+                    s.push_str(&format!("{}{}", Bytestring(key.clone()), val))
+                }
+
+                s.push('e');
+                write!(f, "{}", s)
+            }
+            Raw(ref v) => {
+                let s: String = v.iter().map(|&c| c as char).collect();
+                write!(f, "{}", s)
+            }
+        }
+    }
+}
+
+/// One step of the path from a value to a value nested inside it.
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum PathSegment {
+    /// The value of this key in a dict.
+    Key(Vec<u8>),
+
+    /// The element at this index in a list.
+    Index(usize),
+}
+
+/// An error encountered while accessing part of a value.
+#[derive(Debug, Eq, PartialEq)]
+pub enum AccessError {
+    /// The value is not a container of the `expected` type.
+    WrongType { expected: &'static str },
+
+    /// A dict does not contain the key.
+    MissingKey(Vec<u8>),
+
+    /// A list of length `len` has no element `index`.
+    IndexOutOfBounds { index: usize, len: usize },
+}
+
+impl fmt::Display for AccessError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            AccessError::WrongType { expected } => {
+                write!(f, "value is not a {}", expected)
+            }
+            AccessError::MissingKey(ref key) => {
+                write!(f, "missing key {:?}", String::from_utf8_lossy(key))
+            }
+            AccessError::IndexOutOfBounds { index, len } => {
+                write!(f, "index {} is out of bounds for a list of length {}", index, len)
+            }
+        }
+    }
+}
+
+impl error::Error for AccessError {}
+
+/// Why a dict field could not be read, as reported by
+/// `Bencoded::require_non_negative_int` and
+/// `Bencoded::require_fixed_bytestring`.
+#[derive(Debug, Eq, PartialEq)]
+pub enum FieldError {
+    /// The dict does not contain the key.
+    Missing(Vec<u8>),
+
+    /// The value of `key` is a `found` rather than an `expected`, both named
+    /// as by `Bencoded::type_name`.
+    WrongType { key: Vec<u8>, expected: &'static str, found: &'static str },
+
+    /// The value of `key` is `value`, which is negative.
+    Negative { key: Vec<u8>, value: Int },
+
+    /// The value of `key` is a bytestring of `actual` bytes rather than
+    /// `expected`.
+    WrongLength { key: Vec<u8>, expected: usize, actual: usize },
+}
+
+impl fmt::Display for FieldError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FieldError::Missing(ref key) => {
+                write!(f, "missing field {:?}", String::from_utf8_lossy(key))
+            }
+            FieldError::WrongType { ref key, expected, found } => {
+                write!(f, "expected {} for field {:?}, found {}",
+                       expected, String::from_utf8_lossy(key), found)
+            }
+            FieldError::Negative { ref key, value } => {
+                write!(f, "field {:?} is {}, which is negative",
+                       String::from_utf8_lossy(key), value)
+            }
+            FieldError::WrongLength { ref key, expected, actual } => {
+                write!(f, "field {:?} is {} bytes long, expected {}",
+                       String::from_utf8_lossy(key), actual, expected)
+            }
+        }
+    }
+}
+
+impl error::Error for FieldError {}
+
+/// An error encountered while decoding.
+#[derive(Debug, Eq, PartialEq)]
+pub enum ParseError {
+    /// The input ended before a complete value was read, so it may still turn
+    /// out to be valid once more input arrives. `needed` is the number of
+    /// bytes missing when that is known, i.e. when a bytestring's declared
+    /// length runs past the end; more may be needed after them.
+    Incomplete { needed: Option<usize> },
+
+    /// A byte that cannot appear at this point was found at `pos`.
+    UnexpectedByte { pos: usize, byte: u8 },
+
+    /// A complete value was decoded, but more input follows it at `pos`.
+    TrailingData { pos: usize },
+
+    /// The length of the bytestring starting at `pos` does not fit in a
+    /// `usize`.
+    LengthOverflow { pos: usize },
+
+    /// The bytestring starting at `pos` declares a length of `len`, which is
+    /// more than `DecodeOptions::max_bytestring_len` allows.
+    BytestringTooLong { pos: usize, len: usize },
+
+    /// The integer whose digits start at `pos` does not fit in an `Int`.
+    IntegerOverflow { pos: usize },
+
+    /// A dict key at `pos` is an integer, list or dict rather than a
+    /// bytestring.
+    NonBytestringKey { pos: usize },
+
+    /// A dict key at `pos` appeared more than once.
+    DuplicateKey { pos: usize },
+
+    /// The dict key at `pos` is not valid UTF-8, where keys must be text.
+    InvalidUtf8Key { pos: usize },
+
+    /// The input is a value other than the dict that was required.
+    ExpectedDict,
+
+    /// The input is valid, but differs from its canonical encoding starting
+    /// at `pos`.
+    NotCanonical { pos: usize },
+
+    /// The list element or dict entry at `pos` is one more than
+    /// `DecodeOptions::max_elements` allows.
+    TooManyElements { pos: usize },
+
+    /// The integer whose digits start at `pos` is `value`, which is outside
+    /// `DecodeOptions::integer_range`.
+    IntegerOutOfRange { pos: usize, value: Int },
+
+    /// Decoding the value at `pos` would take more memory than
+    /// `DecodeOptions::max_total_allocated` allows.
+    MemoryLimitExceeded { pos: usize },
+
+    /// Decoding the value at `pos` would take more work than
+    /// `DecodeOptions::max_work_units` allows.
+    WorkBudgetExceeded { pos: usize },
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ParseError::Incomplete { needed: None } => {
+                write!(f, "unexpected end of input")
+            }
+            ParseError::Incomplete { needed: Some(n) } => {
+                write!(f, "unexpected end of input, {} more bytes needed", n)
+            }
+            ParseError::UnexpectedByte { pos, byte } => {
+                write!(f, "unexpected byte 0x{:02x} at offset {}", byte, pos)
+            }
+            ParseError::TrailingData { pos } => {
+                write!(f, "trailing data at offset {}", pos)
+            }
+            ParseError::LengthOverflow { pos } => {
+                write!(f, "bytestring length overflows at offset {}", pos)
+            }
+            ParseError::BytestringTooLong { pos, len } => {
+                write!(f, "bytestring of length {} at offset {} is too long",
+                       len, pos)
+            }
+            ParseError::IntegerOverflow { pos } => {
+                write!(f, "integer overflows at offset {}", pos)
+            }
+            ParseError::NonBytestringKey { pos } => {
+                write!(f, "dict key at offset {} is not a bytestring", pos)
+            }
+            ParseError::DuplicateKey { pos } => {
+                write!(f, "duplicate dict key at offset {}", pos)
+            }
+            ParseError::InvalidUtf8Key { pos } => {
+                write!(f, "dict key at position {} is not UTF-8", pos)
+            }
+            ParseError::ExpectedDict => {
+                write!(f, "expected a dict")
+            }
+            ParseError::NotCanonical { pos } => {
+                write!(f, "input is not canonical from offset {}", pos)
+            }
+            ParseError::TooManyElements { pos } => {
+                write!(f, "too many list elements and dict entries at offset {}",
+                       pos)
+            }
+            ParseError::IntegerOutOfRange { pos, value } => {
+                write!(f, "integer {} at offset {} is out of range", value, pos)
+            }
+            ParseError::MemoryLimitExceeded { pos } => {
+                write!(f, "memory limit exceeded at offset {}", pos)
+            }
+            ParseError::WorkBudgetExceeded { pos } => {
+                write!(f, "work budget exceeded at offset {}", pos)
+            }
+        }
+    }
+}
+
+impl error::Error for ParseError {}
+
+impl ParseError {
+    /// Returns the offset in the input that the error refers to. The end of
+    /// input is reported as `None`, since it depends on the input's length.
+    pub fn pos(&self) -> Option<usize> {
+        match *self {
+            ParseError::Incomplete { .. } | ParseError::ExpectedDict => None,
+            ParseError::UnexpectedByte { pos, .. }
+            | ParseError::TrailingData { pos }
+            | ParseError::LengthOverflow { pos }
+            | ParseError::BytestringTooLong { pos, .. }
+            | ParseError::IntegerOverflow { pos }
+            | ParseError::NonBytestringKey { pos }
+            | ParseError::DuplicateKey { pos }
+            | ParseError::InvalidUtf8Key { pos }
+            | ParseError::NotCanonical { pos }
+            | ParseError::TooManyElements { pos }
+            | ParseError::IntegerOutOfRange { pos, .. }
+            | ParseError::MemoryLimitExceeded { pos }
+            | ParseError::WorkBudgetExceeded { pos } => Some(pos),
+        }
+    }
+
+    /// Renders the error along with the bytes of `input` around where it
+    /// occurred, as a line of a hex dump with the offending byte marked,
+    /// e.g.
+    ///
+    /// ```text
+    /// unexpected byte 0x78 at offset 5
+    /// 00000000  64 31 3a 61 69 78 65 65                          |d1:aixee|
+    ///                          ^^
+    /// ```
+    ///
+    /// If the error is past the end of `input`, e.g. because it came from
+    /// decoding a longer buffer, the end of `input` is marked instead.
+    pub fn context(&self, input: &[u8]) -> String {
+        let pos = cmp::min(self.pos().unwrap_or(input.len()), input.len());
+        let start = pos.saturating_sub(CONTEXT_BEFORE);
+        let end = cmp::min(input.len(), start + CONTEXT_WIDTH);
+
+        let mut hex = String::new();
+        let mut ascii = String::new();
+        for i in start..start + CONTEXT_WIDTH {
+            match input[start..end].get(i - start) {
+                Some(&c) => {
+                    hex.push_str(&format!("{:02x} ", c));
+                    ascii.push(if (0x20..0x7f).contains(&c) { c as char } else { '.' });
+                }
+                None => hex.push_str("   "),
+            }
+        }
+
+        // the offset takes 8 columns plus 2 of padding, and each byte 3
+        let marker = " ".repeat(10 + 3 * (pos - start));
+        format!("{}\n{:08x}  {} |{}|\n{}^^", self, start, hex, ascii, marker)
+    }
+}
+
+/// How many bytes `ParseError::context` shows, and how many of them come
+/// before the error.
+const CONTEXT_WIDTH: usize = 16;
+const CONTEXT_BEFORE: usize = 8;
+
+/// Options controlling how input is decoded. The defaults decode strictly
+/// according to the spec.
+#[derive(Clone, Debug)]
+pub struct DecodeOptions {
+    /// Skip ASCII whitespace (space, `\t`, `\n` and `\r`) between tokens.
+    ///
+    /// This is not standard bencode, which has no separators at all. It only
+    /// exists to read the output of broken encoders that insert newlines
+    /// between entries.
+    pub allow_whitespace: bool,
+
+    /// The expected number of elements in the top-level list or dict, which
+    /// is reserved up front to avoid reallocating while it is filled.
+    pub hint_capacity: Option<usize>,
+
+    /// The longest bytestring allowed, if any. The declared length is checked
+    /// before the contents are read, so this also bounds how much an
+    /// `IncrementalDecoder` buffers while waiting for a single bytestring.
+    pub max_bytestring_len: Option<usize>,
+
+    /// Skip a UTF-8 byte order mark and ASCII whitespace before the value, as
+    /// some tools prepend them to files. Unlike `allow_whitespace`, this
+    /// doesn't affect anything after the start of the value.
+    ///
+    /// Only `decode_with_options` and `validate_with_options` look at this.
+    pub trim_leading_junk: bool,
+
+    /// Skip ASCII whitespace after the value, most often a single `\n` that
+    /// an editor or download tool appended to the file. Anything else after
+    /// the value is still `ParseError::TrailingData`.
+    ///
+    /// Only `decode_with_options` and `validate_with_options` look at this.
+    pub allow_trailing_newline: bool,
+
+    /// Decode an integer whose text isn't canonical, e.g. `i-0e`, or `i03e`
+    /// with `decode_lenient_with_options`, as a `Raw` holding that text, so
+    /// that encoding it again reproduces the input byte for byte. This is for
+    /// files that must not change, e.g. because they are signed.
+    pub preserve_integer_text: bool,
+
+    /// The most list elements and dict entries allowed, counted over the
+    /// whole tree, if any. This bounds the memory a flat list of tiny values
+    /// can take, which neither the input's size nor `max_bytestring_len`
+    /// does well. Defaults to `DEFAULT_MAX_ELEMENTS`.
+    pub max_elements: Option<usize>,
+
+    /// The smallest and largest integer allowed, if any, e.g. `Some((0,
+    /// 65535))` for a format with no negative or huge numbers. This applies
+    /// to every integer in the input.
+    pub integer_range: Option<(Int, Int)>,
+
+    /// Decode dicts as `OrderedDict`s that keep every entry, duplicates
+    /// included, in the order they appear. Re-encoding then reproduces the
+    /// input byte for byte, even if its keys were unsorted or repeated.
+    pub preserve_order: bool,
+
+    /// Lowercase the ASCII letters of every dict key, so that `get` finds
+    /// `b"announce"` even in input from a producer that wrote `Announce`.
+    /// This loses the original keys, so re-encoding won't reproduce the
+    /// input, and keys that only differ in case become duplicates, the last
+    /// value winning as usual.
+    pub lowercase_keys: bool,
+
+    /// The order dict keys are expected in, for the `UnsortedKey` warnings
+    /// of `decode_lenient_with_options`, e.g. to import from a system that
+    /// sorted keys some other way without a warning for every dict. Defaults
+    /// to `KeyOrder::RawBytes`, the order bencode requires.
+    ///
+    /// Canonical form is always in raw byte order whatever this is, so it
+    /// doesn't affect `DecodeMeta::was_canonical` or how values re-encode.
+    pub key_order: KeyOrder,
+
+    /// What to do with a dict key that appears more than once. Defaults to
+    /// `DuplicateKeyPolicy::LastWins`. This doesn't apply with
+    /// `preserve_order`, which keeps every entry anyway.
+    pub duplicate_keys: DuplicateKeyPolicy,
+
+    /// The most memory decoding may allocate, in bytes, if any. This counts
+    /// the contents of every bytestring and dict key that is copied out of
+    /// the input, plus `ELEMENT_OVERHEAD` for each list element and dict
+    /// entry, so it is a ceiling on the size of the result no matter how the
+    /// input is laid out, which `max_bytestring_len` and `max_elements` only
+    /// approximate. It doesn't count spare capacity in vectors and maps.
+    pub max_total_allocated: Option<usize>,
+
+    /// The most work decoding may do, if any, as a single bound on the time
+    /// one decode can take, whether the input is deep, wide or full of large
+    /// bytestrings. Each list element and dict entry costs one unit, and each
+    /// byte of a bytestring or dict key copied out of the input costs one
+    /// more. The units are coarse, and only roughly proportional to time.
+    pub max_work_units: Option<usize>,
+}
+
+/// How dict keys are expected to be ordered, for `DecodeOptions::key_order`.
+#[derive(Clone, Copy, Debug)]
+pub enum KeyOrder {
+    /// By their raw bytes, as `[u8]` compares them.
+    RawBytes,
+
+    /// In any order.
+    None,
+
+    /// By a comparison of their raw bytes.
+    Custom(fn(&[u8], &[u8]) -> cmp::Ordering),
+}
+
+/// What to do with repeated dict keys, for `DecodeOptions::duplicate_keys`.
+/// Either way, `decode_lenient_with_options` warns of each repetition.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum DuplicateKeyPolicy {
+    /// Keep the last value, as most decoders do.
+    LastWins,
+
+    /// Keep every value, in the order they appear, in a `List` under the key,
+    /// for producers that repeat a key to mean "append". A key that appears
+    /// once still maps straight to its value.
+    ///
+    /// This is not standard bencode, and it changes the shape of the value, so
+    /// it re-encodes differently and `get` may find a list where a single
+    /// value was expected. Only use it for input known to need it.
+    Collect,
+}
+
+/// The memory counted against `DecodeOptions::max_total_allocated` for each
+/// list element and dict entry, on top of the bytes it copies: the size of a
+/// dict entry, which is also enough for a list element.
+pub const ELEMENT_OVERHEAD: usize = mem::size_of::<(Vec<u8>, Bencoded)>();
+
+/// The default `DecodeOptions::max_elements`, which is far more than any
+/// torrent needs.
+pub const DEFAULT_MAX_ELEMENTS: usize = 1 << 24;
+
+impl Default for DecodeOptions {
+    fn default() -> DecodeOptions {
+        DecodeOptions {
+            allow_whitespace: false,
+            hint_capacity: None,
+            max_bytestring_len: None,
+            trim_leading_junk: false,
+            allow_trailing_newline: false,
+            preserve_integer_text: false,
+            max_elements: Some(DEFAULT_MAX_ELEMENTS),
+            integer_range: None,
+            preserve_order: false,
+            lowercase_keys: false,
+            key_order: KeyOrder::RawBytes,
+            duplicate_keys: DuplicateKeyPolicy::LastWins,
+            max_total_allocated: None,
+            max_work_units: None,
+        }
+    }
+}
+
+/// A decoded value along with the index just past it.
+type ParseResult<T> = Result<(T, usize), ParseError>;
+
+/// The callback of `decode_with_callback`.
+type NodeCallback<'a> = &'a mut dyn FnMut(&Bencoded, usize);
+
+/// The function of `decode_map_bytestrings` and `decode_map_values`.
+type MapBytes<'a> = &'a mut dyn FnMut(&[u8]) -> Vec<u8>;
+
+/// Nested lists start out with room for at most this many elements.
+const MAX_LIST_RESERVE: usize = 16;
+
+struct Parser<'a> {
+    s: &'a [u8],
+    opts: DecodeOptions,
+    // taken by the first container, which is the top-level one
+    hint_capacity: Option<usize>,
+    // only collected by `decode_lenient`, which also tolerates what they
+    // report
+    warnings: Option<Vec<Warning>>,
+    // list elements and dict entries decoded so far, and the memory and work
+    // counted against `max_total_allocated` and `max_work_units`
+    elements: usize,
+    allocated: usize,
+    work: usize,
+    // for `decode_with_meta`
+    canonical: bool,
+    depth: usize,
+    max_depth: usize,
+    // called by `parse_bencoded` for `decode_with_callback`
+    on_node: Option<NodeCallback<'a>>,
+    // called by `parse_bytestring` for `decode_map_bytestrings`, and by
+    // `parse_dict` for keys too if `map_keys` is set
+    map_bytes: Option<MapBytes<'a>>,
+    map_keys: bool,
+    // the start and declared length of a bytestring that ran past the end,
+    // for `analyze`
+    truncated: Option<(usize, usize)>,
+}
+
+impl<'a> Parser<'a> {
+    fn new(s: &'a [u8], opts: DecodeOptions) -> Parser<'a> {
+        let hint_capacity = opts.hint_capacity;
+        Parser {
+            s,
+            opts,
+            hint_capacity,
+            warnings: None,
+            elements: 0,
+            allocated: 0,
+            work: 0,
+            canonical: true,
+            depth: 0,
+            max_depth: 0,
+            on_node: None,
+            map_bytes: None,
+            map_keys: false,
+            truncated: None,
+        }
+    }
+
+    /// Counts the list element or dict entry at `pos` towards
+    /// `max_elements`, `max_total_allocated` and `max_work_units`.
+    fn add_element(&mut self, pos: usize) -> Result<(), ParseError> {
+        self.elements += 1;
+        match self.opts.max_elements {
+            Some(max) if self.elements > max => {
+                Err(ParseError::TooManyElements { pos })
+            }
+            _ => {
+                self.spend(pos, 1)?;
+                self.count_allocated(pos, ELEMENT_OVERHEAD)
+            }
+        }
+    }
+
+    /// Counts `bytes` copied for the value at `pos` towards
+    /// `max_total_allocated` and `max_work_units`.
+    fn allocate(&mut self, pos: usize, bytes: usize) -> Result<(), ParseError> {
+        self.spend(pos, bytes)?;
+        self.count_allocated(pos, bytes)
+    }
+
+    fn spend(&mut self, pos: usize, units: usize) -> Result<(), ParseError> {
+        self.work = self.work.saturating_add(units);
+        match self.opts.max_work_units {
+            Some(max) if self.work > max => Err(ParseError::WorkBudgetExceeded { pos }),
+            _ => Ok(()),
+        }
+    }
+
+    fn count_allocated(&mut self, pos: usize, bytes: usize) -> Result<(), ParseError> {
+        self.allocated = self.allocated.saturating_add(bytes);
+        match self.opts.max_total_allocated {
+            Some(max) if self.allocated > max => {
+                Err(ParseError::MemoryLimitExceeded { pos })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn warn(&mut self, warning: Warning) {
+        if let Some(ref mut warnings) = self.warnings {
+            warnings.push(warning);
+        }
+    }
+
+    /// Returns the index of the first significant digit at `idx`. Leading
+    /// zeros are only skipped when collecting warnings; otherwise they are
+    /// left for `parse_uint` to reject.
+    fn skip_leading_zeros(&mut self, idx: usize) -> usize {
+        if self.warnings.is_none() {
+            return idx;
+        }
+        let mut end = idx;
+        while self.s.get(end) == Some(&b'0')
+              && self.s.get(end + 1).is_some_and(u8::is_ascii_digit) {
+            end += 1;
+        }
+        if end != idx {
+            self.warn(Warning::LeadingZero { pos: idx });
+        }
+        end
+    }
+
+    fn peek(&self, idx: usize) -> Result<u8, ParseError> {
+        self.s.get(idx).cloned().ok_or(ParseError::Incomplete { needed: None })
+    }
+
+    /// Returns the index of the next token, skipping whitespace if allowed.
+    fn skip_whitespace(&self, mut idx: usize) -> usize {
+        if self.opts.allow_whitespace {
+            while idx < self.s.len() && is_whitespace(self.s[idx]) {
+                idx += 1;
+            }
+        }
+        idx
+    }
+
+    fn parse_integer(&mut self, idx: usize) -> ParseResult<Bencoded> {
+        let (n, end) = self.parse_int(idx)?;
+        if self.opts.preserve_integer_text {
+            // `idx` is just past the `i`
+            let text = &self.s[idx - 1..end];
+            if text != &Integer(n).to_bytes()[..] {
+                return Ok((Raw(text.to_vec()), end));
+            }
+        }
+        Ok((Integer(n), end))
+    }
+
+    fn parse_int(&mut self, idx: usize) -> ParseResult<Int> {
+        let negative = self.peek(idx)? == b'-';
+        let start = if negative { idx + 1 } else { idx };
+        let digits = self.skip_leading_zeros(start);
+        let (n, idx) = parse_uint(self.s, digits, b'e')?;
+        if negative && n == 0 {
+            self.canonical = false;
+        }
+
+        // the magnitude of `Int::MIN` is one more than that of `Int::MAX`
+        let n = if negative {
+            Int::try_from(-i128::from(n)).ok()
+        } else {
+            Int::try_from(n).ok()
+        };
+        match (n, self.opts.integer_range) {
+            (Some(n), Some((min, max))) if n < min || n > max => {
+                Err(ParseError::IntegerOutOfRange { pos: start, value: n })
+            }
+            (Some(n), _) => Ok((n, idx)),
+            (None, _) => Err(ParseError::IntegerOverflow { pos: start }),
+        }
+    }
+
+    fn parse_bytes(&mut self, idx: usize) -> ParseResult<Vec<u8>> {
+        let (v, end) = self.parse_slice(idx)?;
+        self.allocate(idx, v.len())?;
+        Ok((v.to_vec(), end))
+    }
+
+    /// Reads a bytestring, returning its contents as a slice of the input.
+    fn parse_slice(&mut self, start: usize) -> ParseResult<&'a [u8]> {
+        let digits = self.skip_leading_zeros(start);
+        let (len, idx) = match parse_uint(self.s, digits, b':') {
+            Err(ParseError::IntegerOverflow { pos }) => {
+                return Err(ParseError::LengthOverflow { pos })
+            }
+            res => res?,
+        };
+        let len = usize::try_from(len)
+                      .map_err(|_| ParseError::LengthOverflow { pos: start })?;
+
+        if self.opts.max_bytestring_len.is_some_and(|max| len > max) {
+            return Err(ParseError::BytestringTooLong { pos: start, len });
+        }
+
+        let end = idx.checked_add(len)
+                     .ok_or(ParseError::LengthOverflow { pos: start })?;
+        if end > self.s.len() {
+            self.truncated = Some((start, len));
+            return Err(ParseError::Incomplete { needed: Some(end - self.s.len()) });
+        }
+        Ok((&self.s[idx..end], end))
+    }
+
+    /// Reads a dict key, which must be a bytestring.
+    fn parse_key(&mut self, idx: usize) -> ParseResult<&'a [u8]> {
+        match self.peek(idx)? {
+            b'i' | b'l' | b'd' => Err(ParseError::NonBytestringKey { pos: idx }),
+            _ => self.parse_slice(idx),
+        }
+    }
+
+    fn parse_bytestring(&mut self, idx: usize) -> ParseResult<Bencoded> {
+        if self.map_bytes.is_some() {
+            let (v, end) = self.parse_slice(idx)?;
+            let v = (self.map_bytes.as_mut().unwrap())(v);
+            self.allocate(idx, v.len())?;
+            return Ok((Bytestring(v), end));
+        }
+        let (v, idx) = self.parse_bytes(idx)?;
+        Ok((Bytestring(v), idx))
+    }
+
+    fn parse_list(&mut self, mut idx: usize) -> ParseResult<Bencoded> {
+        // every element takes at least two bytes (`0:`), so this never
+        // reserves more than the remaining input could fill
+        let most = (self.s.len() - idx) / 2;
+        let hint = self.hint_capacity.take().map(|hint| cmp::min(hint, most));
+        let mut v = Vec::with_capacity(hint.unwrap_or(cmp::min(most, MAX_LIST_RESERVE)));
+        loop {
+            idx = self.skip_whitespace(idx);
+            match self.peek(idx)? {
+                b'e' => return Ok((List(v), idx + 1)),
+                _ => {
+                    self.add_element(idx)?;
+                    let (elem, idx_) = self.parse_element(idx)?;
+                    idx = idx_;
+                    v.push(elem);
+                }
+            }
+        }
+    }
+
+    fn parse_dict(&mut self, mut idx: usize) -> ParseResult<Bencoded> {
+        // as in `parse_list`, never more than the remaining input could fill
+        let hint = cmp::min(self.hint_capacity.take().unwrap_or(0), (self.s.len() - idx) / 2);
+        let ordered = self.opts.preserve_order;
+        let mut map = HashMap::with_capacity(if ordered { 0 } else { hint });
+        let mut pairs: Vec<(Vec<u8>, Bencoded)> =
+            Vec::with_capacity(if ordered { hint } else { 0 });
+        let mut prev: Option<&[u8]> = None;
+        // the keys whose values were collected into a list, so far
+        let mut collected: Vec<Vec<u8>> = Vec::new();
+        loop {
+            idx = self.skip_whitespace(idx);
+            match self.peek(idx)? {
+                b'e' if ordered => return Ok((OrderedDict(pairs), idx + 1)),
+                b'e' => return Ok((Dict(map), idx + 1)),
+                _ => {
+                    self.add_element(idx)?;
+
+                    // read bytestring
+                    let (key, idx_) = self.parse_key(idx)?;
+                    self.allocate(idx, key.len())?;
+                    let unsorted = prev.is_some_and(|prev| {
+                        match self.opts.key_order {
+                            KeyOrder::RawBytes => prev > key,
+                            KeyOrder::None => false,
+                            KeyOrder::Custom(cmp) => cmp(prev, key) == cmp::Ordering::Greater,
+                        }
+                    });
+                    if unsorted {
+                        self.warn(Warning::UnsortedKey { pos: idx });
+                    }
+                    if prev.is_some_and(|prev| prev >= key) {
+                        self.canonical = false;
+                    }
+                    prev = Some(key);
+                    let key = match self.map_bytes {
+                        Some(ref mut f) if self.map_keys => f(key),
+                        _ => key.to_vec(),
+                    };
+                    let key = if self.opts.lowercase_keys {
+                        key.to_ascii_lowercase()
+                    } else {
+                        key
+                    };
+
+                    // read value
+                    let (val, idx_) = self.parse_element(idx_)?;
+
+                    // insert pair, the last value winning unless all are kept
+                    let duplicate = if ordered {
+                        let duplicate = self.warnings.is_some()
+                                        && pairs.iter().any(|&(ref k, _)| *k == key);
+                        pairs.push((key, val));
+                        duplicate
+                    } else if self.opts.duplicate_keys == DuplicateKeyPolicy::Collect {
+                        match map.entry(key) {
+                            Entry::Occupied(mut entry) => {
+                                if collected.contains(entry.key()) {
+                                    if let List(ref mut v) = *entry.get_mut() {
+                                        v.push(val);
+                                    }
+                                } else {
+                                    collected.push(entry.key().clone());
+                                    let first = mem::replace(entry.get_mut(), List(Vec::new()));
+                                    *entry.get_mut() = List(vec!(first, val));
+                                }
+                                true
+                            }
+                            Entry::Vacant(entry) => {
+                                entry.insert(val);
+                                false
+                            }
+                        }
+                    } else {
+                        map.insert(key, val).is_some()
+                    };
+                    if duplicate {
+                        self.warn(Warning::DuplicateKey { pos: idx });
+                    }
+                    idx = idx_;
+                }
+            }
+        }
+    }
+
+    /// Returns the index just past the value at `idx`, checking it like
+    /// `parse_bencoded` would but without building it. Elements and bytes are
+    /// still counted against the limits in `opts`, so this fails wherever
+    /// decoding would.
+    fn skip(&mut self, idx: usize) -> Result<usize, ParseError> {
+        let idx = self.skip_whitespace(idx);
+        match self.peek(idx)? {
+            b'i' => self.parse_int(idx + 1).map(|(_, idx)| idx),
+            b'l' => {
+                let mut idx = idx + 1;
+                loop {
+                    idx = self.skip_whitespace(idx);
+                    match self.peek(idx)? {
+                        b'e' => return Ok(idx + 1),
+                        _ => {
+                            self.add_element(idx)?;
+                            idx = self.skip(idx)?;
+                        }
+                    }
+                }
+            }
+            b'd' => {
+                let mut idx = idx + 1;
+                loop {
+                    idx = self.skip_whitespace(idx);
+                    match self.peek(idx)? {
+                        b'e' => return Ok(idx + 1),
+                        _ => {
+                            self.add_element(idx)?;
+                            let (key, idx_) = self.parse_key(idx)?;
+                            self.allocate(idx, key.len())?;
+                            idx = self.skip(idx_)?;
+                        }
+                    }
+                }
+            }
+            _ => {
+                let (v, end) = self.parse_slice(idx)?;
+                self.allocate(idx, v.len())?;
+                Ok(end)
+            }
+        }
+    }
+
+    /// Like `parse_bencoded`, for the elements of lists and values of dicts.
+    /// Integers and bytestrings, which are most of what small messages such
+    /// as DHT queries hold, are read directly, without the bookkeeping
+    /// nested values need.
+    #[inline]
+    fn parse_element(&mut self, idx: usize) -> ParseResult<Bencoded> {
+        if self.on_node.is_none() && !self.opts.allow_whitespace {
+            match self.s.get(idx) {
+                Some(&b'i') => return self.parse_integer(idx + 1),
+                Some(&(b'0'..=b'9')) => return self.parse_bytestring(idx),
+                _ => {}
+            }
+        }
+        self.parse_bencoded(idx)
+    }
+
+    /// Calls `f` with each key of the dict that makes up the whole input and
+    /// the bytes of its value, in input order, skipping over the values
+    /// without decoding them. Fails if the input is anything but one dict.
+    fn scan_top_level_dict<F>(&mut self, mut f: F) -> Result<(), ParseError>
+        where F: FnMut(&'a [u8], &'a [u8])
+    {
+        match self.peek(0)? {
+            b'd' => {}
+            c => return Err(ParseError::UnexpectedByte { pos: 0, byte: c }),
+        }
+
+        let mut idx = 1;
+        while self.peek(idx)? != b'e' {
+            let (key, start) = self.parse_key(idx)?;
+            idx = self.skip(start)?;
+            f(key, &self.s[start..idx]);
+        }
+
+        if idx + 1 != self.s.len() {
+            return Err(ParseError::TrailingData { pos: idx + 1 });
+        }
+        Ok(())
+    }
+
+    /// Decodes the values selected by `paths` within the value at `idx`,
+    /// whose first `depth` keys have already been followed, into `found`.
+    /// Returns the index just past the value.
+    fn parse_selected(&mut self,
+                      idx: usize,
+                      paths: &[&[&[u8]]],
+                      depth: usize,
+                      found: &mut HashMap<Vec<Vec<u8>>, Bencoded>)
+                      -> Result<usize, ParseError> {
+        if paths.iter().any(|p| p.len() == depth) {
+            let (val, end) = self.parse_bencoded(idx)?;
+            for path in paths {
+                // Longer paths lead into the value just decoded
+                let selected = path[depth..].iter()
+                                            .try_fold(&val, |val, key| val.get(key));
+                if let Some(selected) = selected {
+                    found.insert(selected_key(path), selected.clone());
+                }
+            }
+            return Ok(end);
+        }
+
+        if self.peek(idx)? != b'd' {
+            return self.skip(idx);
+        }
+        let mut idx = idx + 1;
+        while self.peek(idx)? != b'e' {
+            let (key, start) = self.parse_key(idx)?;
+            let inner: Vec<&[&[u8]]> = paths.iter().cloned()
+                                            .filter(|p| p[depth] == key)
+                                            .collect();
+            idx = if inner.is_empty() {
+                self.skip(start)?
+            } else {
+                // a repeated key replaces whatever was found in its last value
+                for path in &inner {
+                    found.remove(&selected_key(path));
+                }
+                self.parse_selected(start, &inner, depth + 1, found)?
+            };
+        }
+        Ok(idx + 1)
+    }
+
+    fn parse_bencoded(&mut self, idx: usize) -> ParseResult<Bencoded> {
+        let idx = self.skip_whitespace(idx);
+        let res = self.parse_node(idx)?;
+        if let Some(ref mut on_node) = self.on_node {
+            on_node(&res.0, idx);
+        }
+        Ok(res)
+    }
+
+    fn parse_node(&mut self, idx: usize) -> ParseResult<Bencoded> {
+        match self.peek(idx)? {
+            b'i' => self.parse_integer(idx + 1),
+            c @ b'l' | c @ b'd' => {
+                self.depth += 1;
+                self.max_depth = cmp::max(self.max_depth, self.depth);
+                let res = if c == b'l' {
+                    self.parse_list(idx + 1)
+                } else {
+                    self.parse_dict(idx + 1)
+                };
+                self.depth -= 1;
+                res
+            }
+            _ => self.parse_bytestring(idx),
+        }
+    }
+}
+
+/// Reads a non-negative decimal number starting at `idx` and terminated by
+/// `stop`, returning it along with the index just past `stop`. This is the
+/// only place digits are parsed, for both integers and bytestring lengths.
+///
+/// At least one digit is required, and leading zeros are rejected. Fails with
+/// `ParseError::IntegerOverflow` if the number does not fit in a `u64`.
+fn parse_uint(s: &[u8], idx: usize, stop: u8) -> Result<(u64, usize), ParseError> {
+    let mut n: u64 = 0;
+    let mut end = idx;
+    loop {
+        let c = *s.get(end).ok_or(ParseError::Incomplete { needed: None })?;
+        match c {
+            _ if c == stop && end > idx => return Ok((n, end + 1)),
+            // a leading zero must be the only digit
+            b'0'..=b'9' if end == idx + 1 && s[idx] == b'0' => {
+                return Err(ParseError::UnexpectedByte { pos: end, byte: c })
+            }
+            b'0'..=b'9' => {
+                n = n.checked_mul(10)
+                     .and_then(|n| n.checked_add((c - b'0') as u64))
+                     .ok_or(ParseError::IntegerOverflow { pos: idx })?;
+            }
+            _ => return Err(ParseError::UnexpectedByte { pos: end, byte: c }),
+        }
+        end += 1;
+    }
+}
+
+fn is_whitespace(c: u8) -> bool {
+    c == b' ' || c == b'\t' || c == b'\n' || c == b'\r'
+}
+
+/// Parses a bencoded string.
+///
+/// Panics if the input is malformed; see `decode` for a fallible version.
+pub fn parse(s: &[u8]) -> Bencoded {
+    Parser::new(s, DecodeOptions::default()).parse_bencoded(0).unwrap().0
+}
+
+/// Decodes a bencoded string, which must consist of exactly one value.
+pub fn decode(s: &[u8]) -> Result<Bencoded, ParseError> {
+    decode_with_options(s, &DecodeOptions::default())
+}
+
+/// Like `decode`, but fails with `ParseError::ExpectedDict` unless the value
+/// is a dict, as torrents and most protocol messages are.
+pub fn decode_dict(s: &[u8]) -> Result<Bencoded, ParseError> {
+    let val = decode(s)?;
+    if !val.is_dict() {
+        return Err(ParseError::ExpectedDict);
+    }
+    Ok(val)
+}
+
+/// Decodes the contents of a `Raw`, which may be anything `decode_lenient`
+/// accepts.
+fn decode_raw(v: &[u8]) -> Result<Bencoded, ParseError> {
+    decode_lenient(v).map(|(val, _)| val)
+}
+
+/// Like `decode`, but for input that is already a string.
+pub fn decode_str(s: &str) -> Result<Bencoded, ParseError> {
+    decode(s.as_bytes())
+}
+
+/// Like `decode`, but decodes according to `opts`.
+pub fn decode_with_options(s: &[u8], opts: &DecodeOptions)
+                           -> Result<Bencoded, ParseError> {
+    let mut parser = Parser::new(s, opts.clone());
+    let (val, idx) = parser.parse_bencoded(value_start(s, opts))?;
+    check_end(&parser, idx)?;
+    Ok(val)
+}
+
+/// Checks that `s` is exactly one value, as `decode` would, and returns its
+/// length, but builds nothing. This is much cheaper than decoding when only
+/// whether the input is well-formed matters, e.g. to reject bad frames.
+pub fn validate(s: &[u8]) -> Result<usize, ParseError> {
+    validate_with_options(s, &DecodeOptions::default())
+}
+
+/// Like `validate`, but checks according to `opts`, failing wherever
+/// `decode_with_options` would, limits included. The length returned is that
+/// of the value alone, without any junk before or whitespace after it that
+/// `opts` allows.
+pub fn validate_with_options(s: &[u8], opts: &DecodeOptions) -> Result<usize, ParseError> {
+    let mut parser = Parser::new(s, opts.clone());
+    let start = value_start(s, opts);
+    let idx = parser.skip(start)?;
+    check_end(&parser, idx)?;
+    Ok(idx - start)
+}
+
+/// Returns where the value in `s` starts, after any leading junk that `opts`
+/// allows.
+fn value_start(s: &[u8], opts: &DecodeOptions) -> usize {
+    let mut start = 0;
+    if opts.trim_leading_junk {
+        if s.starts_with(b"\xef\xbb\xbf") {
+            start = 3;
+        }
+        while start < s.len() && is_whitespace(s[start]) {
+            start += 1;
+        }
+    }
+    start
+}
+
+/// Checks that nothing but whitespace allowed by the parser's options comes
+/// after the value ending at `idx`.
+fn check_end(parser: &Parser, idx: usize) -> Result<(), ParseError> {
+    let mut idx = parser.skip_whitespace(idx);
+    if parser.opts.allow_trailing_newline {
+        while idx < parser.s.len() && is_whitespace(parser.s[idx]) {
+            idx += 1;
+        }
+    }
+    if idx != parser.s.len() {
+        return Err(ParseError::TrailingData { pos: idx });
+    }
+    Ok(())
+}
+
+/// Like `decode`, but calls `on_node` with each value and the offset where it
+/// starts, e.g. to index the bytestrings of a file in the same pass that
+/// decodes it.
+///
+/// Values are passed in post-order, as each one is complete, so the elements
+/// of a list come before the list itself. Dict keys are not values of their
+/// own, and are only seen as part of their dict.
+pub fn decode_with_callback<F>(s: &[u8], mut on_node: F) -> Result<Bencoded, ParseError>
+    where F: FnMut(&Bencoded, usize)
+{
+    let mut parser = Parser::new(s, DecodeOptions::default());
+    parser.on_node = Some(&mut on_node);
+    let (val, idx) = parser.parse_bencoded(0)?;
+    if idx != s.len() {
+        return Err(ParseError::TrailingData { pos: idx });
+    }
+    Ok(val)
+}
+
+/// Like `decode`, but replaces the contents of every bytestring with what `f`
+/// returns for them as they are read, e.g. to decrypt values without going
+/// over the result a second time.
+///
+/// Dict keys are bytestrings too, and are also passed to `f`, in the order
+/// they appear, so keys that `f` maps to the same bytes become duplicates,
+/// the last value winning. Key order is checked on the keys as written. Use
+/// `decode_map_values` to keep keys as they are.
+pub fn decode_map_bytestrings<F>(s: &[u8], f: F) -> Result<Bencoded, ParseError>
+    where F: FnMut(&[u8]) -> Vec<u8>
+{
+    map_bytestrings(s, f, true)
+}
+
+/// Like `decode_map_bytestrings`, but only passes bytestring values to `f`,
+/// and keeps dict keys as they are, e.g. when only the values are encrypted.
+pub fn decode_map_values<F>(s: &[u8], f: F) -> Result<Bencoded, ParseError>
+    where F: FnMut(&[u8]) -> Vec<u8>
+{
+    map_bytestrings(s, f, false)
+}
+
+fn map_bytestrings<F>(s: &[u8], mut f: F, map_keys: bool) -> Result<Bencoded, ParseError>
+    where F: FnMut(&[u8]) -> Vec<u8>
+{
+    let mut parser = Parser::new(s, DecodeOptions::default());
+    parser.map_bytes = Some(&mut f);
+    parser.map_keys = map_keys;
+    let (val, idx) = parser.parse_bencoded(0)?;
+    if idx != s.len() {
+        return Err(ParseError::TrailingData { pos: idx });
+    }
+    Ok(val)
+}
+
+/// What `decode_with_meta` found out about its input.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DecodeMeta {
+    /// Whether the value was in canonical form, as checked by
+    /// `is_canonical`.
+    pub was_canonical: bool,
+
+    /// The number of bytes the value occupied.
+    pub bytes_consumed: usize,
+
+    /// How deeply lists and dicts were nested, which is 0 for an integer or
+    /// bytestring, 1 for a list of them, and so on.
+    pub max_depth: usize,
+}
+
+/// Like `decode_with_len`, but also reports whether the value was canonical
+/// and how deeply it was nested, all in the same pass.
+pub fn decode_with_meta(s: &[u8]) -> Result<(Bencoded, DecodeMeta), ParseError> {
+    let mut parser = Parser::new(s, DecodeOptions::default());
+    let (val, len) = parser.parse_bencoded(0)?;
+    let meta = DecodeMeta {
+        was_canonical: parser.canonical,
+        bytes_consumed: len,
+        max_depth: parser.max_depth,
+    };
+    Ok((val, meta))
+}
+
+/// Decodes the value at the start of `s`, and returns it along with the number
+/// of bytes it occupied. Unlike `decode`, anything after the value is ignored.
+///
+/// `&s[..len]` is exactly the original input that was consumed, not a
+/// re-encoding of it, so it can be used to verify a signature over the
+/// value even if the input was not canonical.
+pub fn decode_with_len(s: &[u8]) -> Result<(Bencoded, usize), ParseError> {
+    Parser::new(s, DecodeOptions::default()).parse_bencoded(0)
+}
+
+/// Decodes a value followed by exactly `trailer_len` more bytes, such as a
+/// signature appended to a dict, and returns the value along with those
+/// bytes. The value's own bytes are `&s[..s.len() - trailer_len]`, as they
+/// appear in the input, for verifying the signature against.
+///
+/// Fails with `ParseError::Incomplete` if fewer than `trailer_len` bytes
+/// follow the value, and with `ParseError::TrailingData` at the end of the
+/// trailer if more do.
+pub fn decode_with_trailer(s: &[u8], trailer_len: usize)
+                           -> Result<(Bencoded, &[u8]), ParseError> {
+    let (val, len) = decode_with_len(s)?;
+    let rest = &s[len..];
+    if rest.len() < trailer_len {
+        return Err(ParseError::Incomplete { needed: Some(trailer_len - rest.len()) });
+    }
+    if rest.len() > trailer_len {
+        return Err(ParseError::TrailingData { pos: len + trailer_len });
+    }
+    Ok((val, rest))
+}
+
+/// Like `decode_with_len`, but decodes the value starting at `offset`, e.g.
+/// after a fixed-size header. Returns the index just past the value, and
+/// error positions are offsets into all of `s`.
+///
+/// An `offset` at or past the end of `s` is `ParseError::Incomplete`.
+pub fn decode_from(s: &[u8], offset: usize) -> Result<(Bencoded, usize), ParseError> {
+    Parser::new(s, DecodeOptions::default()).parse_bencoded(offset)
+}
+
+/// Decodes a value framed by a 4-byte big-endian length, as some protocols
+/// send them, and returns it along with the number of bytes the frame took,
+/// including the length. The value must fill the frame exactly; anything
+/// after the frame is ignored. Error positions are offsets into all of `s`.
+///
+/// Fails with `ParseError::Incomplete` if `s` ends before the frame does, and
+/// also if the value is cut short by the end of its frame, though in that
+/// case more input won't help.
+pub fn decode_length_prefixed(s: &[u8]) -> Result<(Bencoded, usize), ParseError> {
+    if s.len() < 4 {
+        return Err(ParseError::Incomplete { needed: Some(4 - s.len()) });
+    }
+    let len = u32::from_be_bytes([s[0], s[1], s[2], s[3]]) as usize;
+    let end = match 4usize.checked_add(len) {
+        Some(end) if end <= s.len() => end,
+        _ => return Err(ParseError::Incomplete { needed: Some(len - (s.len() - 4)) }),
+    };
+
+    let (val, idx) = decode_from(&s[..end], 4)?;
+    if idx != end {
+        return Err(ParseError::TrailingData { pos: idx });
+    }
+    Ok((val, end))
+}
+
+/// Returns the index just past the value starting at `idx` in `s`, without
+/// decoding it. The value is checked as thoroughly as `decode` would, but
+/// nothing is allocated.
+pub fn skip_value(s: &[u8], idx: usize) -> Result<usize, ParseError> {
+    Parser::new(s, DecodeOptions::default()).skip(idx)
+}
+
+/// Counts how many times each key appears in the dict `s`, which is useful to
+/// find out which keys are duplicated in a file that `decode` rejects.
+///
+/// Only the top-level dict is scanned; its values are skipped over with
+/// `skip_value`.
+pub fn key_occurrences(s: &[u8]) -> Result<HashMap<Vec<u8>, usize>, ParseError> {
+    let mut counts = HashMap::new();
+    Parser::new(s, DecodeOptions::default()).scan_top_level_dict(|key, _| {
+        *counts.entry(key.to_vec()).or_insert(0) += 1;
+    })?;
+    Ok(counts)
+}
+
+/// Returns each key of the dict `s` along with the bytes of its value exactly
+/// as they appear in `s`, in input order. A value can then be cached by its
+/// own hash and only decoded again when those bytes change.
+///
+/// As with `key_occurrences`, the values are only skipped over, and nothing
+/// is re-encoded.
+pub fn top_level_entry_bytes(s: &[u8]) -> Result<Vec<(Vec<u8>, Vec<u8>)>, ParseError> {
+    let mut entries = Vec::new();
+    Parser::new(s, DecodeOptions::default()).scan_top_level_dict(|key, val| {
+        entries.push((key.to_vec(), val.to_vec()));
+    })?;
+    Ok(entries)
+}
+
+/// Returns the bytes of the value of `key` in the dict `s`, exactly as they
+/// appear in `s`, or `None` if it has no such key. If the key is repeated,
+/// the last value is returned, as `decode` would keep.
+///
+/// Like `skip_value`, this allocates nothing. SHA-1 of `field_bytes(s,
+/// b"info")` is the info hash of a torrent, even one whose info dict is not
+/// in canonical form, where `Bencoded::info_hash` would hash its re-encoding.
+pub fn field_bytes<'a>(s: &'a [u8], key: &[u8]) -> Result<Option<&'a [u8]>, ParseError> {
+    let mut found = None;
+    Parser::new(s, DecodeOptions::default()).scan_top_level_dict(|k, val| {
+        if k == key {
+            found = Some(val);
+        }
+    })?;
+    Ok(found)
+}
+
+/// Decodes only the values at the given paths of dict keys, skipping over
+/// everything else, and returns them keyed by path. Paths that aren't in `s`
+/// are left out, and the empty path selects the whole value. This is for
+/// picking a few fields, like `info.name`, out of a torrent without
+/// materializing `pieces` and the rest.
+///
+/// If a key is repeated, the last value is the one returned, as `decode`
+/// would keep, so the whole input is still scanned after every path has
+/// been found, but only skipped over.
+pub fn decode_selected(s: &[u8], paths: &[&[&[u8]]])
+                       -> Result<HashMap<Vec<Vec<u8>>, Bencoded>, ParseError> {
+    let mut parser = Parser::new(s, DecodeOptions::default());
+    let mut found = HashMap::new();
+    let mut wanted: Vec<&[&[u8]]> = paths.to_vec();
+    wanted.sort();
+    wanted.dedup();
+    let idx = parser.parse_selected(0, &wanted, 0, &mut found)?;
+    if idx != s.len() {
+        return Err(ParseError::TrailingData { pos: idx });
+    }
+    Ok(found)
+}
+
+fn selected_key(path: &[&[u8]]) -> Vec<Vec<u8>> {
+    path.iter().map(|k| k.to_vec()).collect()
+}
+
+/// Returns whether `s` is exactly one value in canonical form: integers and
+/// lengths without leading zeros or a negative zero, and dict keys in
+/// strictly increasing order.
+///
+/// This scans the input without decoding it. See `assert_roundtrip` for a
+/// check that instead compares against a re-encoding.
+pub fn is_canonical(s: &[u8]) -> bool {
+    canonical_value(s, 0) == Some(s.len())
+}
+
+/// Returns the index just past the canonical value at `idx`, or `None` if
+/// there is no such value.
+fn canonical_value(s: &[u8], idx: usize) -> Option<usize> {
+    match *s.get(idx)? {
+        b'i' => {
+            let idx = if s.get(idx + 1) == Some(&b'-') { idx + 2 } else { idx + 1 };
+            let end = canonical_digits(s, idx)?;
+            // `-0` is not allowed, though `0` is
+            if s[idx - 1] == b'-' && s[idx] == b'0' {
+                return None;
+            }
+            if s.get(end) == Some(&b'e') { Some(end + 1) } else { None }
+        }
+        b'l' => {
+            let mut idx = idx + 1;
+            while *s.get(idx)? != b'e' {
+                idx = canonical_value(s, idx)?;
+            }
+            Some(idx + 1)
+        }
+        b'd' => {
+            let mut idx = idx + 1;
+            let mut prev: Option<&[u8]> = None;
+            while *s.get(idx)? != b'e' {
+                let (key, end) = canonical_bytes(s, idx)?;
+                if prev.is_some_and(|prev| prev >= key) {
+                    return None;
+                }
+                prev = Some(key);
+                idx = canonical_value(s, end)?;
+            }
+            Some(idx + 1)
+        }
+        _ => canonical_bytes(s, idx).map(|(_, end)| end),
+    }
+}
+
+/// Returns the index just past a run of at least one digit starting at
+/// `idx`, which must not have leading zeros.
+fn canonical_digits(s: &[u8], idx: usize) -> Option<usize> {
+    let mut end = idx;
+    while end < s.len() && s[end].is_ascii_digit() {
+        end += 1;
+    }
+    if end == idx || (s[idx] == b'0' && end - idx > 1) {
+        None
+    } else {
+        Some(end)
+    }
+}
+
+fn canonical_bytes(s: &[u8], idx: usize) -> Option<(&[u8], usize)> {
+    let colon = canonical_digits(s, idx)?;
+    if s.get(colon) != Some(&b':') {
+        return None;
+    }
+    let len: usize = str::from_utf8(&s[idx..colon]).ok()?.parse().ok()?;
+    let end = (colon + 1).checked_add(len)?;
+    if end > s.len() {
+        return None;
+    }
+    Some((&s[colon + 1..end], end))
+}
+
+/// Decodes `s` and checks that re-encoding it gives back exactly `s`. If not,
+/// returns `ParseError::NotCanonical` with the first offset at which the two
+/// differ.
+///
+/// This should agree with `is_canonical`, which checks the same property
+/// without re-encoding.
+pub fn assert_roundtrip(s: &[u8]) -> Result<(), ParseError> {
+    match reencode_difference(s)? {
+        None => Ok(()),
+        Some(pos) => Err(ParseError::NotCanonical { pos }),
+    }
+}
+
+/// Decodes `s` and returns whether re-encoding it gives back exactly `s`,
+/// i.e. whether it is safe to hash as it is. Like `assert_roundtrip`, but
+/// with a `bool` rather than an error. See `reencode_difference` for where
+/// the two differ.
+pub fn would_reencode_identically(s: &[u8]) -> Result<bool, ParseError> {
+    reencode_difference(s).map(|pos| pos.is_none())
+}
+
+/// Decodes `s` and returns the first offset at which re-encoding it differs
+/// from `s`, or `None` if it gives back exactly `s`. An encoding that is a
+/// prefix of `s`, or the other way around, differs at the end of the shorter
+/// one.
+///
+/// The encoding is compared as it is written, and is never held in memory.
+pub fn reencode_difference(s: &[u8]) -> Result<Option<usize>, ParseError> {
+    let mut w = CompareWriter { expected: s, pos: 0, diff: None };
+    decode(s)?.encode_to(&mut w).expect("comparing cannot fail");
+    Ok(w.diff.or(if w.pos < s.len() { Some(w.pos) } else { None }))
+}
+
+/// Compares the bytes written to it with `expected`, remembering the offset
+/// of the first difference.
+struct CompareWriter<'a> {
+    expected: &'a [u8],
+    pos: usize,
+    diff: Option<usize>,
+}
+
+impl<'a> io::Write for CompareWriter<'a> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.diff.is_none() {
+            let rest = &self.expected[cmp::min(self.pos, self.expected.len())..];
+            let past_end = if buf.len() > rest.len() { Some(rest.len()) } else { None };
+            self.diff = buf.iter()
+                           .zip(rest)
+                           .position(|(a, b)| a != b)
+                           .or(past_end)
+                           .map(|i| self.pos + i);
+        }
+        self.pos += buf.len();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A decoder that is fed its input piece by piece, e.g. as it arrives from a
+/// socket, and yields values as soon as they are complete.
+///
+/// Input is buffered until a complete value has arrived. Any bytes following
+/// that value are kept for the next one. The decoder keeps track of where it
+/// is in the value between calls, so each byte is only looked at a bounded
+/// number of times, however small the pieces are.
+#[derive(Debug, Default)]
+pub struct IncrementalDecoder {
+    buf: Vec<u8>,
+    opts: DecodeOptions,
+    scan: Scan,
+    // the buffer's length when it was last decoded from the start
+    parsed_len: usize,
+}
+
+/// How far `IncrementalDecoder` has got through the buffered input. This only
+/// follows the structure of the value to find where it ends, and leaves
+/// checking it to `Parser`.
+#[derive(Debug, Default)]
+struct Scan {
+    pos: usize,
+    // what the enclosing lists and dicts expect next, innermost last
+    stack: Vec<Expect>,
+    token: Token,
+}
+
+#[derive(Debug)]
+enum Expect {
+    ListElement,
+    DictKey,
+    DictValue,
+}
+
+/// The token `Scan::pos` is in.
+#[derive(Debug, Default)]
+enum Token {
+    /// Between tokens.
+    #[default]
+    None,
+    Integer,
+    /// The length of a bytestring, as far as it has been read.
+    Length(usize),
+    /// The contents of a bytestring, with this many bytes left.
+    Contents(usize),
+}
+
+impl Scan {
+    /// Follows `buf` from where it left off, and returns whether it's worth
+    /// decoding now, because a whole value has arrived or something invalid
+    /// has, which decoding will report.
+    fn advance(&mut self, buf: &[u8], opts: &DecodeOptions) -> bool {
+        while self.pos < buf.len() {
+            let c = buf[self.pos];
+            match self.token {
+                Token::Contents(n) => {
+                    let len = cmp::min(n, buf.len() - self.pos);
+                    self.pos += len;
+                    if len < n {
+                        self.token = Token::Contents(n - len);
+                    } else if self.end_value() {
+                        return true;
+                    }
+                    continue;
+                }
+                Token::Integer => {
+                    self.pos += 1;
+                    match c {
+                        b'e' => {
+                            if self.end_value() {
+                                return true;
+                            }
+                        }
+                        b'0'..=b'9' | b'-' => {}
+                        _ => return true,
+                    }
+                }
+                Token::Length(n) => {
+                    self.pos += 1;
+                    match c {
+                        b':' if opts.max_bytestring_len.is_some_and(|max| n > max) => {
+                            return true
+                        }
+                        b':' if n == 0 => {
+                            if self.end_value() {
+                                return true;
+                            }
+                        }
+                        b':' => self.token = Token::Contents(n),
+                        b'0'..=b'9' => {
+                            let digit = (c - b'0') as usize;
+                            match n.checked_mul(10).and_then(|n| n.checked_add(digit)) {
+                                Some(n) => self.token = Token::Length(n),
+                                None => return true,
+                            }
+                        }
+                        _ => return true,
+                    }
+                }
+                Token::None => {
+                    if opts.allow_whitespace && is_whitespace(c) {
+                        self.pos += 1;
+                        continue;
+                    }
+                    let in_key = matches!(self.stack.last(), Some(&Expect::DictKey));
+                    match c {
+                        b'e' => {
+                            match self.stack.pop() {
+                                Some(Expect::ListElement) | Some(Expect::DictKey) => {}
+                                _ => return true,
+                            }
+                            self.pos += 1;
+                            if self.end_value() {
+                                return true;
+                            }
+                        }
+                        b'0'..=b'9' => self.token = Token::Length(0),
+                        _ if in_key => return true,
+                        b'i' => {
+                            self.pos += 1;
+                            self.token = Token::Integer;
+                        }
+                        b'l' => {
+                            self.pos += 1;
+                            self.stack.push(Expect::ListElement);
+                        }
+                        b'd' => {
+                            self.pos += 1;
+                            self.stack.push(Expect::DictKey);
+                        }
+                        _ => return true,
+                    }
+                }
+            }
+        }
+        false
+    }
+
+    /// Moves past the end of a value, and returns whether it was the whole
+    /// value rather than part of a list or dict.
+    fn end_value(&mut self) -> bool {
+        self.token = Token::None;
+        match self.stack.last_mut() {
+            None => true,
+            Some(expect) => {
+                *expect = match *expect {
+                    Expect::DictKey => Expect::DictValue,
+                    Expect::DictValue => Expect::DictKey,
+                    Expect::ListElement => Expect::ListElement,
+                };
+                false
+            }
+        }
+    }
+}
+
+impl IncrementalDecoder {
+    pub fn new() -> IncrementalDecoder {
+        IncrementalDecoder::with_options(DecodeOptions::default())
+    }
+
+    /// Like `new`, but decodes according to `opts`. Setting
+    /// `max_bytestring_len` is recommended for untrusted peers, as otherwise
+    /// a single declared length can make the decoder buffer without bound.
+    pub fn with_options(opts: DecodeOptions) -> IncrementalDecoder {
+        IncrementalDecoder {
+            buf: Vec::new(),
+            opts,
+            scan: Scan::default(),
+            parsed_len: 0,
+        }
+    }
+
+    /// Adds `more` to the buffered input. Returns `Ok(None)` if more input
+    /// is needed to complete the current value, and `Ok(Some(value))` once it
+    /// has arrived.
+    ///
+    /// Only one value is returned per call; if the buffer may hold several,
+    /// keep calling `feed(&[])` until it returns `Ok(None)`. On error the
+    /// buffered input is discarded.
+    ///
+    /// Malformed input is reported as soon as it arrives, and so is a
+    /// bytestring longer than `max_bytestring_len`. Other limits in the
+    /// options, like `max_elements`, are checked whenever the buffered input
+    /// has doubled since it was last decoded, so some more input than the
+    /// limit allows may be buffered before it is rejected.
+    pub fn feed(&mut self, more: &[u8]) -> Result<Option<Bencoded>, ParseError> {
+        self.buf.push_all(more);
+        if self.buf.is_empty() {
+            return Ok(None);
+        }
+        let ready = self.scan.advance(&self.buf, &self.opts);
+        if !ready && self.buf.len() < 2 * self.parsed_len {
+            return Ok(None);
+        }
+
+        self.parsed_len = self.buf.len();
+        let res = Parser::new(&self.buf, self.opts.clone()).parse_bencoded(0);
+        match res {
+            Ok((val, idx)) => {
+                self.buf.drain(..idx);
+                self.scan = Scan::default();
+                self.parsed_len = 0;
+                Ok(Some(val))
+            }
+            Err(ParseError::Incomplete { .. }) => Ok(None),
+            Err(e) => {
+                self.buf.clear();
+                self.scan = Scan::default();
+                self.parsed_len = 0;
+                Err(e)
+            }
+        }
+    }
+
+    /// Returns the input that has been buffered but not yet decoded.
+    pub fn buffered(&self) -> &[u8] {
+        &self.buf
+    }
+}
+
+/// Decodes one value from input that arrives in `chunks`, e.g. the body of an
+/// HTTP response. Returns the value and whatever followed it in the chunk
+/// where it ended.
+///
+/// Chunks are only taken until the value is complete, so pass `by_ref()` to
+/// keep using the rest of an iterator. Fails with `ParseError::Incomplete` if
+/// the chunks run out first. Positions in errors count from the start of the
+/// first chunk, as if the chunks had been joined together.
+///
+/// This is built on `IncrementalDecoder`, so many small chunks cost no more
+/// than a few large ones. Like it, this buffers all of the value's bytes
+/// until the value is complete, so decoding needs memory for both the
+/// encoded and the decoded value.
+pub fn decode_chunks<'a, I>(chunks: I) -> Result<(Bencoded, Vec<u8>), ParseError>
+    where I: IntoIterator<Item = &'a [u8]>
+{
+    let mut decoder = IncrementalDecoder::new();
+    for chunk in chunks {
+        if let Some(val) = decoder.feed(chunk)? {
+            return Ok((val, decoder.buf));
+        }
+    }
+    // reparse for the `Incomplete` error that `feed` turned into `Ok(None)`
+    match decode_with_len(&decoder.buf) {
+        Err(e) => Err(e),
+        Ok(_) => Err(ParseError::Incomplete { needed: None }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use super::Bencoded::*;
+    use super::{DecodeOptions, Parser};
+
+    fn parser<'a>(s: &'a [u8]) -> Parser<'a> {
+        Parser::new(s, DecodeOptions::default())
+    }
+
+    /// Values are moved between threads, e.g. to hash them in a pool, so
+    /// this must keep compiling. Types that need `Rc`, like
+    /// `InternedBencoded`, are kept separate and behind a feature.
+    #[test]
+    fn send_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<super::Bencoded>();
+        assert_send_sync::<super::BencodedRef>();
+        assert_send_sync::<super::ArcBencoded>();
+        assert_send_sync::<super::LazyBencoded>();
+        assert_send_sync::<super::ParseError>();
+        assert_send_sync::<DecodeOptions>();
+    }
+
+    #[test]
+    fn parse_integer() {
+        assert_eq!(parser(b"i42e").parse_integer(1), Ok((Integer(42), 4)));
+        assert_eq!(parser(b"i-42e").parse_integer(1), Ok((Integer(-42), 5)));
+    }
+
+    #[test]
+    fn parse_bytestring() {
+        assert_eq!(parser(b"5:hello").parse_bytestring(0),
+                   Ok((Bytestring(b"hello".to_vec()), 7)));
+    }
+
+    #[test]
+    fn parse_list() {
+        assert_eq!(parser(b"li42ee").parse_list(1), Ok((List(vec!(Integer(42))), 6)));
+    }
+
+    #[test]
+    fn parse_dict() {
+        let mut m = HashMap::new();
+        m.insert(b"n".to_vec(), Integer(42));
+        assert_eq!(parser(b"d1:ni42ee").parse_dict(1), Ok((Dict(m), 9)));
+    }
+
+    #[test]
+    fn parse_bencoded() {
+        assert_eq!(parser(b"i42e").parse_bencoded(0), Ok((Integer(42), 4)));
+        assert_eq!(parser(b"5:hello").parse_bencoded(0),
+                   Ok((Bytestring(b"hello".to_vec()), 7)));
+        assert_eq!(parser(b"li42ee").parse_bencoded(0),
+                   Ok((List(vec!(Integer(42))), 6)));
+        assert_eq!(parser(b"li42e5:helloe").parse_bencoded(0),
+                   Ok((List(vec!(Integer(42), Bytestring(b"hello".to_vec()))), 13)));
+
+        let mut m = HashMap::new();
+        m.insert(b"n".to_vec(), Integer(42));
+        assert_eq!(parser(b"d1:ni42ee").parse_bencoded(0), Ok((Dict(m), 9)));
+    }
+
+    #[test]
+    fn length_overflow() {
+        use super::ParseError::LengthOverflow;
+
+        let s = b"99999999999999999999999999:x";
+        assert_eq!(parser(s).parse_bytestring(0), Err(LengthOverflow { pos: 0 }));
+        // the length fits, but the end index doesn't
+        let s = b"l18446744073709551615:xe";
+        assert_eq!(super::decode(s), Err(LengthOverflow { pos: 1 }));
+    }
+
+    #[test]
+    fn parse_uint() {
+        use super::parse_uint;
+        use super::ParseError::*;
+
+        assert_eq!(parse_uint(b"0:", 0, b':'), Ok((0, 2)));
+        assert_eq!(parse_uint(b"i42e", 1, b'e'), Ok((42, 4)));
+        assert_eq!(parse_uint(b"18446744073709551615e", 0, b'e'),
+                   Ok((u64::MAX, 21)));
+        assert_eq!(parse_uint(b"18446744073709551616e", 0, b'e'),
+                   Err(IntegerOverflow { pos: 0 }));
+        assert_eq!(parse_uint(b"03:", 0, b':'), Err(UnexpectedByte { pos: 1, byte: b'3' }));
+        assert_eq!(parse_uint(b":", 0, b':'), Err(UnexpectedByte { pos: 0, byte: b':' }));
+        assert_eq!(parse_uint(b"12", 0, b':'), Err(Incomplete { needed: None }));
+
+        assert_eq!(super::decode(b"i-9223372036854775808e"), Ok(Integer(super::Int::MIN)));
+        assert_eq!(super::decode(b"i9223372036854775808e"), Err(IntegerOverflow { pos: 1 }));
+        assert_eq!(super::decode(b"i-03e"), Err(UnexpectedByte { pos: 3, byte: b'3' }));
+    }
+
+    #[test]
+    fn hint_capacity() {
+        let opts = DecodeOptions { hint_capacity: Some(100), ..Default::default() };
+        let mut s = b"lle".to_vec();
+        for _ in 0..99 {
+            s.push_all(b"0:");
+        }
+        s.push(b'e');
+        let (v, _) = Parser::new(&s, opts).parse_list(1).unwrap();
+        match v {
+            List(ref v) => {
+                assert!(v.capacity() >= 100);
+                match v[0] {
+                    List(ref inner) => assert!(inner.capacity() < 100),
+                    _ => panic!("expected a list"),
+                }
+            }
+            _ => panic!("expected a list"),
+        }
+
+        // the hint is capped by what the input could hold
+        let opts = DecodeOptions { hint_capacity: Some(usize::MAX), ..Default::default() };
+        assert_eq!(super::decode_with_options(b"le", &opts), Ok(List(vec!())));
+        assert_eq!(super::decode_with_options(b"de", &opts), Ok(Dict(HashMap::new())));
+        let opts = DecodeOptions { preserve_order: true, ..opts };
+        assert_eq!(super::decode_with_options(b"de", &opts), Ok(OrderedDict(vec!())));
+    }
+
+    #[test]
+    fn unterminated_containers() {
+        use super::ParseError::Incomplete;
+
+        assert_eq!(parser(b"li1e").parse_list(1), Err(Incomplete { needed: None }));
+        assert_eq!(parser(b"d1:ai1e").parse_dict(1), Err(Incomplete { needed: None }));
+        assert_eq!(parser(b"d1:a").parse_dict(1), Err(Incomplete { needed: None }));
+        assert_eq!(super::decode(b"lli1ee"), Err(Incomplete { needed: None }));
+        assert_eq!(super::decode(b"ld1:ai1ee"), Err(Incomplete { needed: None }));
+        assert_eq!(super::decode(b"d1:ali1ee"), Err(Incomplete { needed: None }));
+
+        // a top-level list that ends right after an element
+        assert_eq!(super::decode(b"l"), Err(Incomplete { needed: None }));
+        assert_eq!(super::decode(b"li1e"), Err(Incomplete { needed: None }));
+        assert_eq!(super::decode(b"li1ei2e"), Err(Incomplete { needed: None }));
+        // as opposed to a malformed element
+        assert_eq!(super::decode(b"li1exe"),
+                   Err(super::ParseError::UnexpectedByte { pos: 4, byte: b'x' }));
+    }
+
+    #[test]
+    fn test_to_bytes() {
+        let bytes = b"i42e";
+        assert_eq!(super::parse(bytes).to_bytes(),
+                   bytes);
+
+        let bytes = b"5:hello";
+        assert_eq!(super::parse(bytes).to_bytes(),
+                   bytes);
+
+        let bytes = b"li42e3:fooe";
+        assert_eq!(super::parse(bytes).to_bytes(),
+                   bytes);
+
+        let bytes = b"d3:fooi42ee";
+        assert_eq!(super::parse(bytes).to_bytes(),
+                   bytes);
+    }
+
+    #[test]
+    fn uint_digits() {
+        for &n in &[0, 7, 10, 1234, u64::MAX] {
+            assert_eq!(super::uint_digits(n, &mut [0; 20]), n.to_string().as_bytes());
+        }
+    }
+
+    #[test]
+    fn empty_containers() {
+        assert_eq!(super::Bencoded::empty_list().to_bytes(), b"le".to_vec());
+        assert_eq!(super::Bencoded::empty_dict().to_bytes(), b"de".to_vec());
+    }
+
+    #[test]
+    fn get_entry() {
+        let d = super::parse(b"d3:fooi42ee");
+        assert_eq!(d.get_entry(b"foo"), Some((&b"foo"[..], &Integer(42))));
+        assert_eq!(d.get_entry(b"bar"), None);
+        let d = OrderedDict(vec!((b"a".to_vec(), Integer(1)), (b"a".to_vec(), Integer(2))));
+        assert_eq!(d.get_entry(b"a"), Some((&b"a"[..], &Integer(2))));
+        assert_eq!(Integer(1).get_entry(b"a"), None);
+    }
+
+    #[test]
+    fn get_or() {
+        let d = super::parse(b"d1:ai-1e1:b1:xe");
+        let default = Integer(0);
+        assert_eq!(d.get_or(b"a", &default), &Integer(-1));
+        assert_eq!(d.get_or(b"c", &default), &default);
+        assert_eq!(d.get_int_or(b"a", 7), -1);
+        assert_eq!(d.get_int_or(b"b", 7), 7);
+        assert_eq!(d.get_int_or(b"c", 7), 7);
+        assert_eq!(d.get(b"b").unwrap().as_integer(), None);
+    }
+
+    #[test]
+    fn require_non_negative_int() {
+        use super::FieldError;
+
+        let d = super::parse(b"d6:lengthi0e4:name1:x12:piece lengthi-1ee");
+        assert_eq!(d.require_non_negative_int(b"length"), Ok(0));
+        assert_eq!(d.require_non_negative_int(b"piece length"),
+                   Err(FieldError::Negative { key: b"piece length".to_vec(), value: -1 }));
+        let err = d.require_non_negative_int(b"name").unwrap_err();
+        assert_eq!(err.to_string(), "expected integer for field \"name\", found bytestring");
+        let err = d.require_non_negative_int(b"creation date").unwrap_err();
+        assert_eq!(err.to_string(), "missing field \"creation date\"");
+        assert_eq!(Integer(1).require_non_negative_int(b"length"),
+                   Err(FieldError::Missing(b"length".to_vec())));
+    }
+
+    #[test]
+    fn require_fixed_bytestring() {
+        use super::FieldError;
+
+        let d = super::parse(b"d4:hash3:abc6:lengthi1ee");
+        assert_eq!(d.require_fixed_bytestring(b"hash", 3), Ok(&b"abc"[..]));
+        assert_eq!(d.require_fixed_bytestring(b"hash", 20),
+                   Err(FieldError::WrongLength {
+                       key: b"hash".to_vec(),
+                       expected: 20,
+                       actual: 3,
+                   }));
+        let err = d.require_fixed_bytestring(b"hash", 20).unwrap_err();
+        assert_eq!(err.to_string(), "field \"hash\" is 3 bytes long, expected 20");
+        assert_eq!(d.require_fixed_bytestring(b"length", 1),
+                   Err(FieldError::WrongType {
+                       key: b"length".to_vec(),
+                       expected: "bytestring",
+                       found: "integer",
+                   }));
+        assert_eq!(d.require_fixed_bytestring(b"missing", 1),
+                   Err(FieldError::Missing(b"missing".to_vec())));
+    }
+
+    #[test]
+    fn get_index() {
+        let mut l = super::parse(b"li1e3:fooe");
+        assert_eq!(l.get_index(1), Some(&Bytestring(b"foo".to_vec())));
+        assert_eq!(l.get_index(2), None);
+        assert_eq!(super::parse(b"d1:ai1ee").get_index(0), None);
+
+        *l.get_index_mut(0).unwrap() = Integer(2);
+        assert_eq!(l, super::parse(b"li2e3:fooe"));
+        assert!(l.get_index_mut(2).is_none());
+        assert!(Integer(1).get_index_mut(0).is_none());
+    }
+
+    #[test]
+    fn at() {
+        use super::AccessError::*;
+
+        let d = super::parse(b"d1:ali1eee");
+        assert_eq!(d.at(b"a").and_then(|l| l.at_index(0)), Ok(&Integer(1)));
+        assert_eq!(d.at(b"b"), Err(MissingKey(b"b".to_vec())));
+        assert_eq!(d.at(b"a").and_then(|l| l.at_index(1)),
+                   Err(IndexOutOfBounds { index: 1, len: 1 }));
+        assert_eq!(d.at_index(0), Err(WrongType { expected: "list" }));
+        assert_eq!(Integer(1).at(b"a"), Err(WrongType { expected: "dict" }));
+    }
+
+    #[test]
+    fn dict_keys_sort_by_raw_bytes() {
+        let mut m = HashMap::new();
+        for key in [vec!(0xff), b"aa".to_vec(), b"b".to_vec(), vec!(0x01), b"a".to_vec()] {
+            m.insert(key, Integer(0));
+        }
+        let d = Dict(m);
+        assert_eq!(d.to_bytes(),
+                   b"d1:\x01i0e1:ai0e2:aai0e1:bi0e1:\xffi0ee".to_vec());
+
+        // `Display` writes each byte as a char, so 0xff becomes U+00FF, but the
+        // order is the same
+        assert_eq!(d.to_string(), "d1:\u{1}i0e1:ai0e2:aai0e1:bi0e1:\u{ff}i0ee");
+    }
+
+    #[test]
+    fn predicates() {
+        let values = [Integer(1), Bytestring(vec!()), List(vec!()),
+                      Dict(HashMap::new()), OrderedDict(vec!())];
+        let kinds: Vec<_> = values.iter()
+                                  .map(|v| (v.is_integer(), v.is_bytestring(),
+                                            v.is_list(), v.is_dict(), v.is_container()))
+                                  .collect();
+        assert_eq!(kinds, vec!((true, false, false, false, false),
+                               (false, true, false, false, false),
+                               (false, false, true, false, true),
+                               (false, false, false, true, true),
+                               (false, false, false, true, true)));
+    }
+
+    #[test]
+    fn all_strings_utf8() {
+        assert!(::parse(b"d3:fooli1e3:bare4:\xc3\xa6\xc3\xb8i2ee").all_strings_utf8());
+        assert!(!::parse(b"d3:fooli1e1:\xffee").all_strings_utf8());
+        assert!(!::parse(b"d1:\xffi1ee").all_strings_utf8());
+        assert!(Integer(1).all_strings_utf8());
+    }
+
+    #[test]
+    fn shape() {
+        let s = b"d8:announce3:url4:infod6:lengthi1e4:name1:a6:pieces0:ee";
+        assert_eq!(::parse(s).shape(), "d(announce:s,info:d(length:i,name:s,pieces:s))");
+        assert_eq!(::parse(b"li1ei2e1:ali1eee").shape(), "l(i,s,l(i))");
+        assert_eq!(::parse(b"le").shape(), "l()");
+        let d = OrderedDict(vec!((b"b".to_vec(), Integer(1)), (b"a".to_vec(), Integer(2)),
+                                 (b"b".to_vec(), List(vec!()))));
+        assert_eq!(d.shape(), "d(a:i,b:l())");
+    }
+
+    #[test]
+    fn all_keys() {
+        let val = ::parse(b"d4:infod5:filesld6:lengthi1e4:pathl1:aeee6:lengthi1ee\
+                            4:listld1:xdeee5:emptydee");
+        let keys: Vec<&[u8]> = vec!(b"empty", b"files", b"info", b"length", b"list", b"path",
+                                    b"x");
+        assert_eq!(val.all_keys().iter().map(|k| &k[..]).collect::<Vec<_>>(), keys);
+        assert!(::parse(b"li1e1:ae").all_keys().is_empty());
+        assert_eq!(Raw(b"d1:ai1ee".to_vec()).all_keys().len(), 1);
+    }
+
+    #[test]
+    fn integers() {
+        use PathSegment::*;
+
+        let val = ::parse(b"d1:bli1e1:xi-2ee1:ai3ee");
+        assert_eq!(val.integers(), vec!(
+            (vec!(Key(b"a".to_vec())), 3),
+            (vec!(Key(b"b".to_vec()), Index(0)), 1),
+            (vec!(Key(b"b".to_vec()), Index(2)), -2),
+        ));
+        assert_eq!(Integer(1).integers(), vec!((vec!(), 1)));
+        assert!(::parse(b"l1:ae").integers().is_empty());
+    }
+
+    #[test]
+    fn with_truncated_strings() {
+        let val = ::parse(b"d4:infod6:pieces10:01234567894:name1:xe4:listl6:abcdefee");
+        let short = val.with_truncated_strings(4);
+        assert_eq!(short.get(b"info").unwrap().get(b"pieces"),
+                   Some(&Bytestring("0123…(10 bytes)".as_bytes().to_vec())));
+        assert_eq!(short.get(b"info").unwrap().get(b"name"), Some(&Bytestring(b"x".to_vec())));
+        assert_eq!(short.get(b"list"),
+                   Some(&List(vec!(Bytestring("abcd…(6 bytes)".as_bytes().to_vec())))));
+        // the original is untouched
+        assert_eq!(val.get(b"info").unwrap().get(b"pieces"),
+                   Some(&Bytestring(b"0123456789".to_vec())));
+        assert_eq!(val.with_truncated_strings(10), val);
+    }
+
+    #[test]
+    fn to_indented_bencode() {
+        let s = b"d8:announce3:url4:infod5:filesld6:lengthi1e4:pathl1:aeee\
+                  4:name2:\xff\\6:pieces40:0123456789012345678901234567890123456789\
+                  e5:emptyleee";
+        let expected = "\
+d
+  8:announce 3:url
+  5:empty le
+  4:info d
+    5:files l
+      d
+        6:length i1e
+        4:path l
+          1:a
+        e
+      e
+    e
+    4:name 2:\\xff\\x5c
+    6:pieces 40:01234567890123456789012345678901…
+  e
+e";
+        assert_eq!(::parse(s).to_indented_bencode(), expected);
+        assert_eq!(OrderedDict(vec!()).to_indented_bencode(), "de");
+        assert_eq!(Raw(b"i1".to_vec()).to_indented_bencode(), "\\x69\\x31");
+    }
+
+    #[test]
+    fn to_indented_bencode_with_options() {
+        use super::PrettyOptions;
+
+        let val = ::parse(b"d4:infod4:name6:hello!6:pieces5:\x00\xff\x02\x03\x04ee");
+        let opts = PrettyOptions { indent: 1, max_bytestring_display: 4, hex_non_utf8: true };
+        let expected = "\
+d
+ 4:info d
+  4:name 6:hell…
+  6:piec… 5:<00ff0203>…
+ e
+e";
+        assert_eq!(val.to_indented_bencode_with_options(&opts), expected);
+
+        let opts = PrettyOptions {
+            max_bytestring_display: usize::MAX,
+            ..Default::default()
+        };
+        let val = Bytestring(vec!(b'a'; 40));
+        assert_eq!(val.to_indented_bencode_with_options(&opts),
+                   format!("40:{}", "a".repeat(40)));
+        assert_eq!(val.to_indented_bencode_with_options(&PrettyOptions::default()),
+                   val.to_indented_bencode());
+    }
+
+    #[test]
+    fn to_stable_text() {
+        let s = b"d8:announce15:http://tracker/4:infod5:filesld6:lengthi1e4:pathl1:aeee\
+                  4:name6:caf\xc3\xa9\n6:pieces40:0123456789012345678901234567890123456789\
+                  4:salt2:\x00\xffe5:emptyle7:privatedee";
+        let expected = "\
+announce = \"http://tracker/\"
+empty = []
+info.files[0].length = 1
+info.files[0].path[0] = \"a\"
+info.name = \"caf\u{e9}\\x0a\"
+info.pieces = <40 bytes, hash 810c03bbda7711b9>
+info.salt = 00ff
+private = {}
+";
+        assert_eq!(::parse(s).to_stable_text(), expected);
+
+        let ordered = OrderedDict(vec!((b"b".to_vec(), Integer(2)),
+                                       (b"a".to_vec(), Integer(1))));
+        assert_eq!(ordered.to_stable_text(), "a = 1\nb = 2\n");
+        assert_eq!(Integer(-3).to_stable_text(), "-3\n");
+        assert_eq!(::parse(b"d3:a.b1:\"e").to_stable_text(), "a.b = \"\\x22\"\n");
+        assert_eq!(::parse(b"de").to_stable_text(), "{}\n");
+    }
+
+    #[test]
+    fn depth() {
+        assert_eq!(Integer(1).depth(), 1);
+        assert_eq!(::parse(b"li1ee").depth(), 2);
+        assert_eq!(::parse(b"le").depth(), 2);
+        assert_eq!(::parse(b"d1:ali1eee1:b0:e").depth(), 3);
+        assert_eq!(Raw(b"llee".to_vec()).depth(), 3);
+        for s in &[&b"3:foo"[..], b"le", b"d1:ad1:bleee", b"li1elleee"] {
+            let meta = ::decode_with_meta(s).unwrap().1;
+            assert_eq!(::parse(s).depth(), meta.max_depth + 1);
+        }
+    }
+
+    #[test]
+    fn children() {
+        let d = ::parse(b"d1:bi2e1:ai1e1:cli3eee");
+        assert_eq!(d.children(), vec!(&Integer(1), &Integer(2), &List(vec!(Integer(3)))));
+        assert_eq!(d.children()[2].children(), vec!(&Integer(3)));
+        assert!(Integer(1).children().is_empty());
+        assert_eq!(d.type_name(), "dict");
+        assert_eq!(OrderedDict(vec!()).type_name(), "dict");
+        assert_eq!(Bytestring(vec!()).type_name(), "bytestring");
+    }
+
+    #[test]
+    fn len() {
+        for s in &[&b"le"[..], b"de", b"0:"] {
+            assert_eq!(::parse(s).len(), Some(0));
+            assert!(::parse(s).is_empty());
+        }
+        assert_eq!(::parse(b"3:foo").len(), Some(3));
+        assert_eq!(::parse(b"d1:ai1e1:bi2ee").len(), Some(2));
+        let d = OrderedDict(vec!((b"a".to_vec(), Integer(1)), (b"a".to_vec(), Integer(2))));
+        assert_eq!(d.len(), Some(1));
+        assert_eq!(Raw(b"li1ee".to_vec()).len(), Some(1));
+        assert_eq!(Integer(0).len(), None);
+        assert!(!Integer(0).is_empty());
+    }
+
+    #[test]
+    fn canonical_refs() {
+        use super::canonical_refs;
+
+        let pairs = vec!((b"b".to_vec(), Integer(1)), (b"a".to_vec(), Integer(2)),
+                         (b"b".to_vec(), Integer(3)), (b"a".to_vec(), Integer(4)));
+        let (a, b) = (b"a".to_vec(), b"b".to_vec());
+        assert_eq!(canonical_refs(&pairs), vec!((&a, &Integer(4)), (&b, &Integer(3))));
+        assert_eq!(canonical_refs(&[]), vec!());
+    }
+
+    #[test]
+    fn sorted_keys() {
+        let d = ::parse(b"d1:bi1e1:ai2e1:ci3ee");
+        assert_eq!(d.sorted_keys(), Some(vec!(&b"a"[..], b"b", b"c")));
+        let d = OrderedDict(vec!((b"b".to_vec(), Integer(1)), (b"a".to_vec(), Integer(2)),
+                                 (b"b".to_vec(), Integer(3))));
+        assert_eq!(d.sorted_keys(), Some(vec!(&b"a"[..], b"b")));
+        assert_eq!(::parse(b"de").sorted_keys(), Some(vec!()));
+        assert_eq!(::parse(b"le").sorted_keys(), None);
+    }
+
+    #[test]
+    fn bytestrings() {
+        let v = b"foo".to_vec();
+        let ptr = v.as_ptr();
+        assert_eq!(Bytestring(v.clone()).as_bytes(), Some(&b"foo"[..]));
+        assert_eq!(Integer(1).as_bytes(), None);
+        // moved out, not copied
+        let v = Bytestring(v).into_bytestring().unwrap();
+        assert_eq!(v.as_ptr(), ptr);
+        assert_eq!(List(vec!()).into_bytestring(), None);
+    }
+
+    #[test]
+    fn as_u64() {
+        assert_eq!(Integer(42).as_u64(), Some(42));
+        assert_eq!(Integer(0).as_usize(), Some(0));
+        assert_eq!(Integer(-1).as_u64(), None);
+        assert_eq!(Integer(-1).as_usize(), None);
+        assert_eq!(Bytestring(b"42".to_vec()).as_u64(), None);
+    }
+
+    #[test]
+    fn as_int_list() {
+        let l = List(vec!(Integer(-1), Integer(0), Integer(255)));
+        assert_eq!(l.as_int_list(), Some(vec!(-1, 0, 255)));
+        assert_eq!(l.as_byte_list(), None);
+
+        let l = List(vec!(Integer(104), Integer(105)));
+        assert_eq!(l.as_byte_list(), Some(b"hi".to_vec()));
+        assert_eq!(List(vec!(Integer(256))).as_byte_list(), None);
+
+        let l = List(vec!(Integer(1), Bytestring(b"a".to_vec())));
+        assert_eq!(l.as_int_list(), None);
+        assert_eq!(Integer(1).as_int_list(), None);
+    }
+
+    #[test]
+    fn as_single() {
+        assert_eq!(super::parse(b"li1ee").as_single(), Some(&Integer(1)));
+        assert_eq!(super::parse(b"lli1eee").as_single(),
+                   Some(&List(vec!(Integer(1)))));
+        assert_eq!(super::parse(b"le").as_single(), None);
+        assert_eq!(super::parse(b"li1ei2ee").as_single(), None);
+        assert_eq!(super::parse(b"i1e").as_single(), None);
+
+        assert_eq!(super::parse(b"li1ei2ee").into_list_or_wrap(), vec!(Integer(1), Integer(2)));
+        assert_eq!(super::parse(b"i1e").into_list_or_wrap(), vec!(Integer(1)));
+        assert!(super::parse(b"le").into_list_or_wrap().is_empty());
+    }
+
+    #[test]
+    fn debug() {
+        assert_eq!(format!("{:?}", Integer(42)), "Integer(42)");
+        assert_eq!(format!("{:?}", Bytestring(b"hi\"".to_vec())),
+                   "Bytestring(\"hi\\\"\")");
+        assert_eq!(format!("{:?}", Bytestring(vec!(0, 0xff))),
+                   "Bytestring(<00ff>)");
+        assert_eq!(format!("{:?}", super::parse(b"d1:bi2e1:ali1eee")),
+                   "Dict({\"a\": List([Integer(1)]), \"b\": Integer(2)})");
+        let d = OrderedDict(vec!((b"b".to_vec(), Integer(2))));
+        assert_eq!(format!("{:?}", d), "OrderedDict([(\"b\", Integer(2))])");
+    }
+
+    #[test]
+    fn as_bool() {
+        assert_eq!(Integer(1).as_bool(), Some(true));
+        assert_eq!(Integer(0).as_bool(), Some(false));
+        assert_eq!(Integer(2).as_bool(), None);
+        assert_eq!(Bytestring(b"1".to_vec()).as_bool(), None);
+        assert_eq!(super::Bencoded::from(true), Integer(1));
+        assert_eq!(super::Bencoded::from(false), Integer(0));
+    }
+
+    #[test]
+    fn canonicalize() {
+        let mut d = OrderedDict(vec!((b"b".to_vec(), Integer(1)),
+                                     (b"a".to_vec(), Integer(2)),
+                                     (b"b".to_vec(), Integer(3))));
+        assert_eq!(d.to_bytes(), b"d1:bi1e1:ai2e1:bi3ee".to_vec());
+        assert_eq!(d.get(b"b"), Some(&Integer(3)));
+
+        d.canonicalize();
+        assert_eq!(d, OrderedDict(vec!((b"a".to_vec(), Integer(2)),
+                                       (b"b".to_vec(), Integer(3)))));
+        assert_eq!(d.to_bytes(), b"d1:ai2e1:bi3ee".to_vec());
+    }
+
+    #[test]
+    fn canonicalize_tree() {
+        let inner = OrderedDict(vec!((b"b".to_vec(), Integer(1)), (b"a".to_vec(), Integer(2))));
+        let mut d = OrderedDict(vec!((b"z".to_vec(), List(vec!(inner))),
+                                     (b"y".to_vec(), Integer(3)),
+                                     (b"y".to_vec(), Integer(4))));
+        d.canonicalize_tree();
+        assert_eq!(d.to_bytes(), b"d1:yi4e1:zld1:ai2e1:bi1eeee".to_vec());
+    }
+
+    #[test]
+    fn find() {
+        let d = ::parse(b"d1:bli5ei2000ee1:ai1001e1:cl3:fooee");
+        let big = |v: &::Bencoded| if let Integer(n) = *v { n > 1000 } else { false };
+        assert_eq!(d.find(big), Some(&Integer(1001)));
+        assert_eq!(d.find(|v| *v == "foo"), Some(&Bytestring(b"foo".to_vec())));
+        assert_eq!(d.find(|v| v.is_list()).map(|v| v.to_bytes()),
+                   Some(b"li5ei2000ee".to_vec()));
+        assert_eq!(d.find(|v| *v == 3), None);
+    }
+
+    #[test]
+    fn strip_key() {
+        let mut d = ::parse(b"d4:ed2k1:x5:filesld4:ed2k1:y6:lengthi1eeee");
+        assert_eq!(d.strip_key(b"ed2k"), 2);
+        assert_eq!(d, ::parse(b"d5:filesld6:lengthi1eeee"));
+
+        let mut d = OrderedDict(vec!((b"a".to_vec(), Integer(1)),
+                                     (b"b".to_vec(), ::parse(b"d1:ai2ee")),
+                                     (b"a".to_vec(), Integer(3))));
+        assert_eq!(d.strip_key(b"a"), 3);
+        assert_eq!(d.to_bytes(), b"d1:bdee".to_vec());
+        assert_eq!(Integer(1).strip_key(b"a"), 0);
+    }
+
+    #[test]
+    fn set_path() {
+        let mut d = ::parse(b"d8:announce3:urle");
+        d.set_path(&[b"info", b"private"], Integer(1)).unwrap();
+        assert_eq!(d, ::parse(b"d8:announce3:url4:infod7:privatei1eee"));
+        d.set_path(&[b"info", b"private"], Integer(0)).unwrap();
+        assert_eq!(d.get(b"info").unwrap().get(b"private"), Some(&Integer(0)));
+
+        let before = d.clone();
+        assert_eq!(d.set_path(&[b"announce", b"x"], Integer(1)),
+                   Err(super::AccessError::WrongType { expected: "dict" }));
+        assert_eq!(d, before);
+
+        let mut d = OrderedDict(vec!((b"a".to_vec(), Integer(1)), (b"a".to_vec(), Integer(2))));
+        d.set_path(&[b"a"], Integer(3)).unwrap();
+        assert_eq!(d.to_bytes(), b"d1:ai1e1:ai3ee".to_vec());
+
+        let mut n = Integer(1);
+        n.set_path(&[], Integer(2)).unwrap();
+        assert_eq!(n, Integer(2));
+    }
+
+    #[test]
+    fn as_fixed() {
+        let val = ::parse(b"l9:localhosti6881ee");
+        assert_eq!(val.as_pair(),
+                   Some((&Bytestring(b"localhost".to_vec()), &Integer(6881))));
+        let [ref host, ref port] = *val.as_fixed::<2>().unwrap();
+        assert_eq!(host.as_bytes(), Some(&b"localhost"[..]));
+        assert_eq!(port.as_integer(), Some(6881));
+        assert_eq!(val.as_fixed::<3>(), None);
+        assert_eq!(::parse(b"le").as_fixed::<0>(), Some(&[]));
+        assert_eq!(::parse(b"li1ee").as_pair(), None);
+        assert_eq!(Integer(1).as_pair(), None);
+    }
+
+    #[test]
+    fn rename_key() {
+        let mut d = ::parse(b"d8:Announce3:url4:infodee");
+        assert!(d.rename_key(b"Announce", b"announce"));
+        assert_eq!(d, ::parse(b"d8:announce3:url4:infodee"));
+        assert!(!d.rename_key(b"Announce", b"announce"));
+        assert!(!d.rename_key(b"announce", b"info"));
+        assert!(!d.rename_key(b"info", b"info"));
+        assert_eq!(d, ::parse(b"d8:announce3:url4:infodee"));
+
+        let mut d = OrderedDict(vec!((b"b".to_vec(), Integer(1)),
+                                     (b"a".to_vec(), Integer(2)),
+                                     (b"b".to_vec(), Integer(3))));
+        assert!(d.rename_key(b"b", b"c"));
+        assert_eq!(d.to_bytes(), b"d1:ci1e1:ai2e1:ci3ee".to_vec());
+        assert!(!d.rename_key(b"a", b"c"));
+        assert!(!Integer(1).rename_key(b"a", b"b"));
+    }
+
+    #[test]
+    fn take_path() {
+        let mut t = ::parse(b"d8:announce3:url4:infod4:name1:x6:pieces0:ee");
+        assert_eq!(t.take_path(&[b"info", b"name"]), Some(Bytestring(b"x".to_vec())));
+        assert_eq!(t.take_path(&[b"info"]), Some(::parse(b"d6:pieces0:e")));
+        assert_eq!(t, ::parse(b"d8:announce3:urle"));
+
+        assert_eq!(t.take_path(&[b"info"]), None);
+        assert_eq!(t.take_path(&[b"announce", b"x"]), None);
+        assert_eq!(t.take_path(&[]), None);
+        assert_eq!(t, ::parse(b"d8:announce3:urle"));
+
+        let mut d = OrderedDict(vec!((b"a".to_vec(), Integer(1)),
+                                     (b"b".to_vec(), Integer(2)),
+                                     (b"a".to_vec(), Integer(3))));
+        assert_eq!(d.take_path(&[b"a"]), Some(Integer(3)));
+        assert_eq!(d.to_bytes(), b"d1:bi2ee".to_vec());
+    }
+
+    #[test]
+    #[should_panic(expected = "keys are not sorted and unique")]
+    #[cfg(debug_assertions)]
+    fn from_pairs_unchecked_asserts_order() {
+        let pairs = vec!((b"b".to_vec(), Integer(1)), (b"a".to_vec(), Integer(2)));
+        super::Bencoded::from_pairs_unchecked(pairs);
+    }
+
+    #[test]
+    fn pairs() {
+        let pairs = vec!((b"b".to_vec(), Integer(1)), (b"a".to_vec(), Integer(2)));
+        let d = super::Bencoded::from_pairs(pairs).unwrap();
+        assert_eq!(d.to_bytes(), b"d1:ai2e1:bi1ee".to_vec());
+        assert_eq!(d.into_pairs(),
+                   Some(vec!((b"a".to_vec(), Integer(2)), (b"b".to_vec(), Integer(1)))));
+
+        let pairs = vec!((b"a".to_vec(), Integer(1)), (b"a".to_vec(), Integer(2)));
+        assert_eq!(super::Bencoded::from_pairs(pairs),
+                   Err(super::ParseError::DuplicateKey { pos: 1 }));
+
+        let pairs = vec!((b"a".to_vec(), Integer(2)), (b"b".to_vec(), Integer(1)));
+        let d = super::Bencoded::from_pairs_unchecked(pairs);
+        assert_eq!(d.to_bytes(), b"d1:ai2e1:bi1ee".to_vec());
+        assert_eq!(d.into_pairs(),
+                   Some(vec!((b"a".to_vec(), Integer(2)), (b"b".to_vec(), Integer(1)))));
+
+        let d = OrderedDict(vec!((b"b".to_vec(), Integer(1)),
+                                 (b"a".to_vec(), Integer(2)),
+                                 (b"b".to_vec(), Integer(3))));
+        assert_eq!(d.into_pairs(),
+                   Some(vec!((b"a".to_vec(), Integer(2)), (b"b".to_vec(), Integer(3)))));
+        assert_eq!(Integer(1).into_pairs(), None);
+    }
+
+    #[test]
+    fn into_sorted_entries() {
+        let d = ::parse(b"d1:bi1e1:ali2eee");
+        assert_eq!(d.into_sorted_entries(),
+                   Some(vec!((b"a".to_vec(), List(vec!(Integer(2)))),
+                             (b"b".to_vec(), Integer(1)))));
+        let d = OrderedDict(vec!((b"b".to_vec(), Integer(1)), (b"a".to_vec(), Integer(2))));
+        assert_eq!(d.into_sorted_entries(),
+                   Some(vec!((b"a".to_vec(), Integer(2)), (b"b".to_vec(), Integer(1)))));
+        assert_eq!(::parse(b"de").into_sorted_entries(), Some(vec!()));
+        assert_eq!(::parse(b"le").into_sorted_entries(), None);
+    }
+
+    #[test]
+    fn from_btree_map() {
+        use std::collections::BTreeMap;
+
+        let mut map = BTreeMap::new();
+        map.insert(b"b".to_vec(), Integer(1));
+        map.insert(b"a".to_vec(), Integer(2));
+        let d = super::Bencoded::from(map);
+        assert_eq!(d, ::parse(b"d1:ai2e1:bi1ee"));
+        assert_eq!(d.to_bytes(), b"d1:ai2e1:bi1ee".to_vec());
+        assert_eq!(super::Bencoded::from(BTreeMap::new()), ::parse(b"de"));
+    }
+
+    #[test]
+    fn into_string_keyed() {
+        use super::ParseError;
+
+        let map = ::parse(b"d3:fooi1e3:bar0:e").into_string_keyed().unwrap();
+        assert_eq!(map.len(), 2);
+        assert_eq!(map["foo"], Integer(1));
+        assert_eq!(::parse(b"d1:ai1e1:bi2e2:c\xffi3ee").into_string_keyed(),
+                   Err(ParseError::InvalidUtf8Key { pos: 2 }));
+        assert_eq!(Integer(1).into_string_keyed(), Err(ParseError::ExpectedDict));
+    }
+
+    #[test]
+    fn raw() {
+        use super::Bencoded;
+
+        let info = Bencoded::raw(b"d6:lengthi1ee".to_vec()).unwrap();
+        assert_eq!(info.to_escaped_string(), "d6:lengthi1ee");
+        assert_eq!(info.content_hash(), ::parse(b"d6:lengthi1ee").content_hash());
+
+        let mut map = HashMap::new();
+        map.insert(b"info".to_vec(), info);
+        let torrent = Dict(map);
+        assert_eq!(torrent.to_bytes(), b"d4:infod6:lengthi1eee".to_vec());
+        assert_eq!(torrent.to_string(), "d4:infod6:lengthi1eee");
+
+        assert_eq!(Bencoded::raw(b"i03e".to_vec()),
+                   Err(super::ParseError::UnexpectedByte { pos: 2, byte: b'3' }));
+        assert_eq!(Bencoded::raw(b"d1:bi1e1:ai2ee".to_vec()),
+                   Err(super::ParseError::NotCanonical { pos: 3 }));
+    }
+
+    #[test]
+    fn content_hash() {
+        let a = ::parse(b"d1:ai1e1:bl3:fooee");
+        let b = OrderedDict(vec!((b"b".to_vec(), ::parse(b"l3:fooe")),
+                                 (b"a".to_vec(), Integer(2)),
+                                 (b"a".to_vec(), Integer(1))));
+        assert_eq!(a.content_hash(), b.content_hash());
+        assert!(a.content_hash() != ::parse(b"d1:ai2e1:bl3:fooee").content_hash());
+        // FNV-1a of the encoding, so it never changes between runs
+        assert_eq!(Bytestring(vec!()).content_hash(), 0x07fc0e07b4bd112f);
+
+        // the text of non-canonical integers doesn't matter
+        let opts = super::DecodeOptions { preserve_integer_text: true, ..Default::default() };
+        let (c, _) = super::decode_lenient_with_options(b"d1:ai01e1:bl3:fooee", &opts).unwrap();
+        assert_eq!(c.get(b"a"), Some(&Raw(b"i01e".to_vec())));
+        assert_eq!(c.content_hash(), a.content_hash());
+        assert_eq!(Raw(b"i-0e".to_vec()).content_hash(), Integer(0).content_hash());
+    }
+
+    #[test]
+    fn encode_to_hasher() {
+        struct Collect(Vec<u8>);
+        impl ::std::hash::Hasher for Collect {
+            fn write(&mut self, bytes: &[u8]) {
+                self.0.extend_from_slice(bytes);
+            }
+            fn finish(&self) -> u64 {
+                0
+            }
+        }
+
+        for s in &[&b"d1:ai-12e1:bl3:fooi0eee"[..], b"le", b"0:"] {
+            let mut hasher = Collect(Vec::new());
+            ::parse(s).encode_to_hasher(&mut hasher);
+            assert_eq!(hasher.0, s.to_vec());
+        }
+
+        let mut val = OrderedDict(vec!((b"b".to_vec(), Integer(1)),
+                                       (b"a".to_vec(), Integer(2))));
+        let mut hasher = Collect(Vec::new());
+        val.encode_to_hasher(&mut hasher);
+        val.canonicalize_tree();
+        assert_eq!(hasher.0, val.to_bytes());
+
+        // a `Raw` is hashed in canonical form, unless it can't be decoded
+        let cases: &[(&[u8], &[u8])] = &[(b"i03e", b"i3e"),
+                                          (b"d1:bi1e1:ai-0ee", b"d1:ai0e1:bi1ee"),
+                                          (b"l1:ae", b"l1:ae"),
+                                          (b"i1", b"i1")];
+        for &(raw, canonical) in cases {
+            let mut hasher = Collect(Vec::new());
+            Raw(raw.to_vec()).encode_to_hasher(&mut hasher);
+            assert_eq!(hasher.0, canonical.to_vec());
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "fingerprint")]
+    fn fingerprint() {
+        let a = ::parse(b"d1:ai1e1:bl3:fooee");
+        let b = OrderedDict(vec!((b"b".to_vec(), ::parse(b"l3:fooe")),
+                                 (b"a".to_vec(), Integer(1))));
+        assert_eq!(a.fingerprint(), b.fingerprint());
+        assert!(a.fingerprint() != Integer(1).fingerprint());
+        assert_eq!(a.fingerprint(),
+                   ::xxhash_rust::xxh3::xxh3_128(b"d1:ai1e1:bl3:fooee"));
+    }
+
+    #[test]
+    fn to_escaped_string() {
+        let b = super::parse(b"d3:foo4:a\n\xffbe");
+        assert_eq!(b.to_escaped_string(), "d3:foo4:a\\x0a\\xffbe");
+        assert_eq!(Bytestring(b"a\\b".to_vec()).to_escaped_string(),
+                   "3:a\\x5cb");
+        assert_eq!(super::parse(b"li-3e2:hie").to_escaped_string(),
+                   "li-3e2:hie");
     }
 
 }
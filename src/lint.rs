@@ -0,0 +1,186 @@
+//! Reporting everything questionable about a value at once.
+
+use std::fmt;
+use std::str;
+
+use {decode_raw, is_canonical, Bencoded, PathSegment};
+use Bencoded::*;
+
+/// Dict keys whose values are meant to be text, in torrents and similar
+/// formats, and so should be valid UTF-8.
+const TEXT_KEYS: &[&[u8]] = &[b"announce", b"comment", b"created by", b"encoding",
+                              b"name", b"name.utf-8"];
+
+/// Dict keys whose values are lists of text, like `path` in a torrent.
+const TEXT_LIST_KEYS: &[&[u8]] = &[b"path", b"path.utf-8"];
+
+/// Something about a value that is allowed, but may be a mistake, as found by
+/// `Bencoded::lint`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Lint {
+    /// Where in the value the problem is.
+    pub path: Vec<PathSegment>,
+
+    /// What the problem is, e.g. `value of "name" is not UTF-8`. The wording
+    /// is not stable, and should only be shown to people.
+    pub message: String,
+}
+
+/// Shows the path like `SchemaError` does, e.g. `info.files[0].path: ...`,
+/// or just the message for the value itself.
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut path = String::new();
+        for segment in &self.path {
+            match *segment {
+                PathSegment::Key(ref key) => {
+                    if !path.is_empty() {
+                        path.push('.');
+                    }
+                    path.push_str(&String::from_utf8_lossy(key));
+                }
+                PathSegment::Index(i) => path.push_str(&format!("[{}]", i)),
+            }
+        }
+        if path.is_empty() {
+            f.write_str(&self.message)
+        } else {
+            write!(f, "{}: {}", path, self.message)
+        }
+    }
+}
+
+impl Bencoded {
+    /// Walks the whole value and reports everything that is allowed but
+    /// suspicious, rather than stopping at the first problem: dicts that are
+    /// empty or whose entries are out of order or repeated, `Raw` values that
+    /// aren't canonical, dict keys that aren't UTF-8, and values that should
+    /// be text, such as `name` and `path`, but aren't UTF-8.
+    ///
+    /// Dicts are visited in key order, except for an `OrderedDict`, whose
+    /// entries are visited as they are.
+    pub fn lint(&self) -> Vec<Lint> {
+        let mut lints = Vec::new();
+        self.push_lints(&mut Vec::new(), &mut lints);
+        lints
+    }
+
+    fn push_lints(&self, path: &mut Vec<PathSegment>, lints: &mut Vec<Lint>) {
+        match *self {
+            Integer(_) | Bytestring(_) => {}
+            List(ref v) => {
+                for (i, val) in v.iter().enumerate() {
+                    path.push(PathSegment::Index(i));
+                    val.push_lints(path, lints);
+                    path.pop();
+                }
+            }
+            Dict(ref map) => {
+                let mut pairs: Vec<_> = map.iter().collect();
+                pairs.sort_by(|&(a, _), &(b, _)| a.cmp(b));
+                push_pair_lints(&pairs, path, lints);
+            }
+            OrderedDict(ref pairs) => {
+                for (i, w) in pairs.windows(2).enumerate() {
+                    if pairs[..i + 1].iter().any(|&(ref k, _)| *k == w[1].0) {
+                        let key = String::from_utf8_lossy(&w[1].0);
+                        push_lint(lints, path, format!("key {:?} is repeated", key));
+                    } else if w[0].0 > w[1].0 {
+                        let key = String::from_utf8_lossy(&w[1].0);
+                        push_lint(lints, path, format!("key {:?} is out of order", key));
+                    }
+                }
+                let pairs: Vec<_> = pairs.iter().map(|&(ref k, ref v)| (k, v)).collect();
+                push_pair_lints(&pairs, path, lints);
+            }
+            Raw(ref v) => {
+                match decode_raw(v) {
+                    Ok(val) => {
+                        if !is_canonical(v) {
+                            let message = "raw value is not in canonical form".to_string();
+                            push_lint(lints, path, message);
+                        }
+                        val.push_lints(path, lints);
+                    }
+                    Err(err) => {
+                        push_lint(lints, path, format!("raw value does not decode: {}", err))
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn push_lint(lints: &mut Vec<Lint>, path: &[PathSegment], message: String) {
+    lints.push(Lint { path: path.to_vec(), message });
+}
+
+fn push_pair_lints(pairs: &[(&Vec<u8>, &Bencoded)],
+                   path: &mut Vec<PathSegment>,
+                   lints: &mut Vec<Lint>) {
+    if pairs.is_empty() {
+        push_lint(lints, path, "dict is empty".to_string());
+    }
+    for &(key, val) in pairs {
+        if str::from_utf8(key).is_err() {
+            let message = format!("key {:?} is not UTF-8", String::from_utf8_lossy(key));
+            push_lint(lints, path, message);
+        }
+        path.push(PathSegment::Key(key.clone()));
+
+        let is_text = |val: &Bencoded| match *val {
+            Bytestring(ref v) => str::from_utf8(v).is_ok(),
+            _ => true,
+        };
+        let key_name = String::from_utf8_lossy(key);
+        if TEXT_KEYS.contains(&&key[..]) && !is_text(val) {
+            push_lint(lints, path, format!("value of {:?} is not UTF-8", key_name));
+        }
+        if let (true, &List(ref v)) = (TEXT_LIST_KEYS.contains(&&key[..]), val) {
+            for (i, elem) in v.iter().enumerate() {
+                if !is_text(elem) {
+                    path.push(PathSegment::Index(i));
+                    push_lint(lints, path, format!("element of {:?} is not UTF-8", key_name));
+                    path.pop();
+                }
+            }
+        }
+
+        val.push_lints(path, lints);
+        path.pop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use PathSegment::*;
+    use Bencoded::*;
+
+    #[test]
+    fn lint() {
+        let val = ::parse(b"d4:infod5:filesld6:lengthi1e4:pathl1:a1:\xffeee\
+                            4:name2:\xfe\xffe5:otherdee");
+        let lints: Vec<String> = val.lint().iter().map(|l| l.to_string()).collect();
+        assert_eq!(lints, vec!(
+            "info.files[0].path[1]: element of \"path\" is not UTF-8",
+            "info.name: value of \"name\" is not UTF-8",
+            "other: dict is empty",
+        ));
+
+        let val = OrderedDict(vec!((b"b".to_vec(), Integer(1)),
+                                   (b"a".to_vec(), Raw(b"i03e".to_vec())),
+                                   (b"b".to_vec(), Integer(2)),
+                                   (vec!(0xff), Integer(3))));
+        let lints = val.lint();
+        assert_eq!(lints.iter().map(|l| &l.message[..]).collect::<Vec<_>>(), vec!(
+            "key \"a\" is out of order",
+            "key \"b\" is repeated",
+            "raw value is not in canonical form",
+            "key \"\u{fffd}\" is not UTF-8",
+        ));
+        assert_eq!(lints[2].path, vec!(Key(b"a".to_vec())));
+
+        assert!(::parse(b"d4:name3:fooe").lint().is_empty());
+        assert_eq!(::parse(b"de").lint()[0].to_string(), "dict is empty");
+    }
+}
@@ -0,0 +1,182 @@
+//! Checking that a value has an expected structure.
+
+use std::collections::HashMap;
+use std::error;
+use std::fmt;
+
+use {decode_raw, Bencoded};
+use Bencoded::*;
+
+/// The expected structure of a value, for `Bencoded::matches_schema`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Schema {
+    /// Any value at all.
+    Any,
+
+    /// An integer.
+    Int,
+
+    /// A bytestring.
+    Str,
+
+    /// A list whose elements all match the inner schema.
+    List(Box<Schema>),
+
+    /// A dict with the given fields, which are required unless their schema
+    /// is `Optional`. Keys not listed are allowed, so that messages can be
+    /// extended.
+    Dict(HashMap<Vec<u8>, Schema>),
+
+    /// A dict field that may be missing, but must match the inner schema if
+    /// it is present. Anywhere else this is the same as the inner schema.
+    Optional(Box<Schema>),
+}
+
+/// Why a value does not match a `Schema`. The `path` leads from the checked
+/// value to the offending one, e.g. `info.files[0].length`, and is empty for
+/// the checked value itself.
+#[derive(Debug, Eq, PartialEq)]
+pub enum SchemaError {
+    /// The value at `path` is a `found` rather than an `expected`, both named
+    /// as by `Bencoded::type_name`.
+    WrongType { path: String, expected: &'static str, found: &'static str },
+
+    /// A required dict field is missing.
+    MissingField { path: String },
+}
+
+impl fmt::Display for SchemaError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SchemaError::WrongType { ref path, expected, found } if path.is_empty() => {
+                write!(f, "expected {}, found {}", expected, found)
+            }
+            SchemaError::WrongType { ref path, expected, found } => {
+                write!(f, "expected {} at `{}`, found {}", expected, path, found)
+            }
+            SchemaError::MissingField { ref path } => {
+                write!(f, "missing field `{}`", path)
+            }
+        }
+    }
+}
+
+impl error::Error for SchemaError {}
+
+impl Bencoded {
+    /// Checks that this value has the structure described by `schema`, and
+    /// reports the first place where it doesn't. Dict fields are checked in
+    /// key order, so the same value always gives the same error.
+    ///
+    /// A `Raw` value is checked by what it decodes to.
+    pub fn matches_schema(&self, schema: &Schema) -> Result<(), SchemaError> {
+        self.match_at(schema, &mut String::new())
+    }
+
+    fn match_at(&self, schema: &Schema, path: &mut String) -> Result<(), SchemaError> {
+        if let Raw(ref v) = *self {
+            if let Ok(val) = decode_raw(v) {
+                return val.match_at(schema, path);
+            }
+        }
+
+        let expected = match *schema {
+            Schema::Any => return Ok(()),
+            Schema::Optional(ref inner) => return self.match_at(inner, path),
+            Schema::Int if self.is_integer() => return Ok(()),
+            Schema::Str if self.is_bytestring() => return Ok(()),
+            Schema::List(ref elem) => {
+                if let List(ref v) = *self {
+                    for (i, val) in v.iter().enumerate() {
+                        let len = path.len();
+                        path.push_str(&format!("[{}]", i));
+                        val.match_at(elem, path)?;
+                        path.truncate(len);
+                    }
+                    return Ok(());
+                }
+                "list"
+            }
+            Schema::Dict(ref fields) => {
+                if self.is_dict() {
+                    let mut fields: Vec<_> = fields.iter().collect();
+                    fields.sort_by(|&(a, _), &(b, _)| a.cmp(b));
+                    for (key, field) in fields {
+                        let len = path.len();
+                        if len > 0 {
+                            path.push('.');
+                        }
+                        path.push_str(&String::from_utf8_lossy(key));
+                        match (self.get(key), field) {
+                            (Some(val), _) => val.match_at(field, path)?,
+                            (None, &Schema::Optional(_)) => {}
+                            (None, _) => {
+                                return Err(SchemaError::MissingField { path: path.clone() })
+                            }
+                        }
+                        path.truncate(len);
+                    }
+                    return Ok(());
+                }
+                "dict"
+            }
+            Schema::Int => "integer",
+            Schema::Str => "bytestring",
+        };
+        Err(SchemaError::WrongType {
+            path: path.clone(),
+            expected,
+            found: self.type_name(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::{Schema, SchemaError};
+
+    fn torrent_schema() -> Schema {
+        let mut file = HashMap::new();
+        file.insert(b"length".to_vec(), Schema::Int);
+        file.insert(b"path".to_vec(), Schema::List(Box::new(Schema::Str)));
+
+        let mut info = HashMap::new();
+        info.insert(b"name".to_vec(), Schema::Str);
+        info.insert(b"files".to_vec(),
+                    Schema::Optional(Box::new(Schema::List(Box::new(Schema::Dict(file))))));
+
+        let mut torrent = HashMap::new();
+        torrent.insert(b"announce".to_vec(), Schema::Str);
+        torrent.insert(b"info".to_vec(), Schema::Dict(info));
+        Schema::Dict(torrent)
+    }
+
+    #[test]
+    fn matches_schema() {
+        let schema = torrent_schema();
+
+        let s = b"d8:announce3:url4:infod5:filesld6:lengthi1e4:pathl1:aeee4:name1:xee";
+        assert_eq!(::parse(s).matches_schema(&schema), Ok(()));
+        // `files` is optional, and unknown keys are allowed
+        let s = b"d8:announce3:url4:infod4:name1:x5:otheri1eee";
+        assert_eq!(::parse(s).matches_schema(&schema), Ok(()));
+
+        let s = b"d8:announce3:url4:infod5:filesld6:length1:1eee4:name1:xee";
+        assert_eq!(::parse(s).matches_schema(&schema), Err(SchemaError::WrongType {
+            path: "info.files[0].length".to_string(),
+            expected: "integer",
+            found: "bytestring",
+        }));
+
+        let s = b"d8:announce3:url4:infod5:filesld6:lengthi1eee4:name1:xee";
+        assert_eq!(::parse(s).matches_schema(&schema), Err(SchemaError::MissingField {
+            path: "info.files[0].path".to_string(),
+        }));
+
+        let err = ::parse(b"le").matches_schema(&schema).unwrap_err();
+        assert_eq!(err.to_string(), "expected dict, found list");
+        assert_eq!(::parse(b"le").matches_schema(&Schema::Any), Ok(()));
+    }
+}
@@ -0,0 +1,108 @@
+//! A decoded representation whose parts can be shared between threads.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use {decode, Bencoded, Int, ParseError};
+use Bencoded::*;
+
+/// Like `Bencoded`, but every list element, dict value and bytestring is
+/// behind an `Arc`, so that any part of the tree can be handed out, e.g. to
+/// many concurrent requests, by cloning a pointer instead of the data.
+///
+/// The tree can't be changed once built; convert it with `to_bencoded` to
+/// edit it.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ArcBencoded {
+    Integer(Int),
+    Bytestring(Arc<[u8]>),
+    List(Vec<Arc<ArcBencoded>>),
+    Dict(HashMap<Vec<u8>, Arc<ArcBencoded>>),
+    Raw(Arc<[u8]>),
+}
+
+impl ArcBencoded {
+    /// Looks up `key` in a dict, returning a shared handle to its value.
+    pub fn get(&self, key: &[u8]) -> Option<&Arc<ArcBencoded>> {
+        if let ArcBencoded::Dict(ref map) = *self {
+            map.get(key)
+        } else {
+            None
+        }
+    }
+
+    /// Copies the value back into a plain `Bencoded`.
+    pub fn to_bencoded(&self) -> Bencoded {
+        match *self {
+            ArcBencoded::Integer(n) => Integer(n),
+            ArcBencoded::Bytestring(ref v) => Bytestring(v.to_vec()),
+            ArcBencoded::List(ref v) => List(v.iter().map(|val| val.to_bencoded()).collect()),
+            ArcBencoded::Dict(ref map) => {
+                Dict(map.iter().map(|(k, v)| (k.clone(), v.to_bencoded())).collect())
+            }
+            ArcBencoded::Raw(ref v) => Raw(v.to_vec()),
+        }
+    }
+}
+
+impl Bencoded {
+    /// Converts the value into an `ArcBencoded`. The entries of an
+    /// `OrderedDict` end up in a plain dict, with later duplicates winning.
+    pub fn into_shared(self) -> ArcBencoded {
+        match self {
+            Integer(n) => ArcBencoded::Integer(n),
+            Bytestring(v) => ArcBencoded::Bytestring(Arc::from(v)),
+            List(v) => {
+                let v = v.into_iter().map(|val| Arc::new(val.into_shared()));
+                ArcBencoded::List(v.collect())
+            }
+            Dict(map) => {
+                let map = map.into_iter().map(|(k, v)| (k, Arc::new(v.into_shared())));
+                ArcBencoded::Dict(map.collect())
+            }
+            OrderedDict(pairs) => {
+                let map = pairs.into_iter().map(|(k, v)| (k, Arc::new(v.into_shared())));
+                ArcBencoded::Dict(map.collect())
+            }
+            Raw(v) => ArcBencoded::Raw(Arc::from(v)),
+        }
+    }
+}
+
+/// Like `decode`, but returns an `ArcBencoded`, whose parts can be shared
+/// between threads without copying them.
+pub fn decode_shared(s: &[u8]) -> Result<ArcBencoded, ParseError> {
+    decode(s).map(Bencoded::into_shared)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::thread;
+
+    use super::{decode_shared, ArcBencoded};
+
+    #[test]
+    fn shares_between_threads() {
+        let s = b"d4:infod6:lengthi1e4:name3:fooe5:otherl3:bari2eee";
+        let val = decode_shared(s).unwrap();
+        assert_eq!(val.to_bencoded(), ::parse(s));
+
+        let info = val.get(b"info").unwrap().clone();
+        let handle = thread::spawn(move || info.get(b"name").cloned());
+        let name = handle.join().unwrap().unwrap();
+        assert_eq!(*name, ArcBencoded::Bytestring(Arc::from(&b"foo"[..])));
+        // not a copy, but the one in `val`
+        assert!(Arc::ptr_eq(&name, val.get(b"info").unwrap().get(b"name").unwrap()));
+        assert_eq!(Arc::strong_count(&name), 2);
+
+        assert!(decode_shared(b"i1ei2e").is_err());
+    }
+
+    #[test]
+    fn into_shared() {
+        let val = ::Bencoded::OrderedDict(vec!((b"a".to_vec(), ::Bencoded::Integer(1)),
+                                               (b"a".to_vec(), ::Bencoded::Integer(2))));
+        assert_eq!(val.into_shared().to_bencoded(), ::parse(b"d1:ai2ee"));
+    }
+}
@@ -0,0 +1,27 @@
+//! Helpers for writing tests against this crate.
+
+use std::collections::HashMap;
+
+use Bencoded;
+
+/// Builds a dict from text keys, to cut down on `HashMap` boilerplate in
+/// tests. A repeated key keeps its last value.
+pub fn test_dict(pairs: &[(&str, Bencoded)]) -> Bencoded {
+    let mut map = HashMap::with_capacity(pairs.len());
+    for &(key, ref val) in pairs {
+        map.insert(key.as_bytes().to_vec(), val.clone());
+    }
+    Bencoded::Dict(map)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::test_dict;
+    use Bencoded::*;
+
+    #[test]
+    fn builds_dict() {
+        let d = test_dict(&[("b", Integer(1)), ("a", test_dict(&[]))]);
+        assert_eq!(d, ::parse(b"d1:ade1:bi1ee"));
+    }
+}
@@ -0,0 +1,1194 @@
+//! Helpers for BitTorrent metainfo files.
+
+use std::cmp;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddrV4, SocketAddrV6};
+use std::path::{Component, Path, PathBuf};
+use std::str;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use sha1::{Digest, Sha1};
+
+use {AccessError, Bencoded, Int};
+use Bencoded::*;
+
+/// A reason a value is not a valid torrent. Fields are named by their path,
+/// e.g. `info.piece length`.
+#[derive(Debug, Eq, PartialEq)]
+pub enum TorrentError {
+    /// A required field is missing.
+    Missing(&'static str),
+
+    /// A field has the wrong type or an invalid value.
+    Invalid(&'static str),
+
+    /// The info dict must contain exactly one of `length` (single-file) and
+    /// `files` (multi-file).
+    AmbiguousLayout,
+}
+
+impl fmt::Display for TorrentError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TorrentError::Missing(field) => write!(f, "missing field `{}`", field),
+            TorrentError::Invalid(field) => write!(f, "invalid field `{}`", field),
+            TorrentError::AmbiguousLayout => {
+                write!(f, "info must contain exactly one of `length` and `files`")
+            }
+        }
+    }
+}
+
+impl error::Error for TorrentError {}
+
+/// Where a file of a torrent lies in the concatenated data of all its files,
+/// as returned by `Bencoded::file_layout`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FileRecord {
+    /// The path of the file, starting with the torrent's `name`, with
+    /// components separated by `/`.
+    pub path: String,
+
+    /// The size of the file in bytes.
+    pub length: Int,
+
+    /// The byte offset at which the file starts.
+    pub offset: Int,
+}
+
+/// Which pieces of a torrent's data on disk have the right hash, as returned
+/// by `Bencoded::verify_files`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct VerifyReport {
+    /// Whether each piece matched its hash, in order.
+    pub pieces: Vec<bool>,
+}
+
+impl VerifyReport {
+    /// Returns the number of pieces that matched.
+    pub fn good_pieces(&self) -> usize {
+        self.pieces.iter().filter(|&&good| good).count()
+    }
+
+    /// Returns whether every piece matched, i.e. the download is complete.
+    pub fn is_complete(&self) -> bool {
+        self.pieces.iter().all(|&good| good)
+    }
+}
+
+/// Bytes read from disk at a time by `verify_files`.
+const VERIFY_BUF_LEN: usize = 64 * 1024;
+
+/// Where `Bencoded::add_trackers` puts new trackers in `announce-list`, whose
+/// tiers clients try in order.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TrackerTier {
+    /// In a tier of their own after the existing ones, to be tried only if
+    /// none of those work.
+    New,
+
+    /// In the first tier, to be tried alongside the trackers already there.
+    First,
+}
+
+impl Bencoded {
+    /// Checks that the required fields of a torrent exist and have the right
+    /// types: `announce` is a bytestring (or `announce-list` is present),
+    /// `info` is a dict with a bytestring `name`, a positive `piece length`
+    /// and a `pieces` bytestring whose length is a multiple of 20, and exactly
+    /// one of `length` and `files` is present.
+    pub fn validate_torrent(&self) -> Result<(), TorrentError> {
+        match (self.get(b"announce"), self.get(b"announce-list")) {
+            (Some(&Bytestring(_)), _) | (None, Some(_)) => {}
+            (Some(_), _) => return Err(TorrentError::Invalid("announce")),
+            (None, None) => return Err(TorrentError::Missing("announce")),
+        }
+
+        let info = match self.get(b"info") {
+            Some(info @ &Dict(_)) | Some(info @ &OrderedDict(_)) => info,
+            Some(_) => return Err(TorrentError::Invalid("info")),
+            None => return Err(TorrentError::Missing("info")),
+        };
+
+        match info.get(b"name") {
+            Some(&Bytestring(_)) => {}
+            Some(_) => return Err(TorrentError::Invalid("info.name")),
+            None => return Err(TorrentError::Missing("info.name")),
+        }
+
+        match info.get(b"piece length") {
+            Some(&Integer(n)) if n > 0 => {}
+            Some(_) => return Err(TorrentError::Invalid("info.piece length")),
+            None => return Err(TorrentError::Missing("info.piece length")),
+        }
+
+        match info.get(b"pieces") {
+            Some(&Bytestring(ref v)) if v.len() % 20 == 0 => {}
+            Some(_) => return Err(TorrentError::Invalid("info.pieces")),
+            None => return Err(TorrentError::Missing("info.pieces")),
+        }
+
+        match (info.get(b"length"), info.get(b"files")) {
+            (Some(_), None) | (None, Some(_)) => Ok(()),
+            _ => Err(TorrentError::AmbiguousLayout),
+        }
+    }
+
+    /// Returns the tracker URLs of a torrent: `announce` followed by every
+    /// tier of `announce-list`, without duplicates and in their original
+    /// order. Entries that aren't UTF-8 bytestrings are skipped.
+    pub fn trackers(&self) -> Vec<String> {
+        let mut res: Vec<String> = Vec::new();
+        {
+            let mut add = |b: &Bencoded| {
+                if let Bytestring(ref v) = *b {
+                    if let Ok(url) = str::from_utf8(v) {
+                        if !res.iter().any(|u| u == url) {
+                            res.push(url.to_string());
+                        }
+                    }
+                }
+            };
+
+            if let Some(announce) = self.get(b"announce") {
+                add(announce);
+            }
+            if let Some(&List(ref tiers)) = self.get(b"announce-list") {
+                for tier in tiers {
+                    if let List(ref urls) = *tier {
+                        for url in urls {
+                            add(url);
+                        }
+                    }
+                }
+            }
+        }
+        res
+    }
+
+    /// Adds the tracker URLs in `trackers` to `announce-list`, as a new tier or
+    /// in the first one, depending on `tier`. URLs already in `announce-list`
+    /// are skipped, and so are repeats within `trackers`.
+    ///
+    /// If there is no `announce-list`, one is created, with `announce` as its
+    /// first tier, since clients ignore `announce` when there is one. Fails
+    /// if this is not a dict, or `announce-list` is not a list of lists.
+    pub fn add_trackers(&mut self, trackers: &[&str], tier: TrackerTier)
+                        -> Result<(), AccessError> {
+        let announce = match self.get(b"announce") {
+            Some(&Bytestring(ref v)) => vec!(List(vec!(Bytestring(v.clone())))),
+            _ => Vec::new(),
+        };
+        let tiers = match *self.entry_or_insert_with(b"announce-list", || List(announce))? {
+            List(ref mut tiers) => tiers,
+            _ => return Err(AccessError::WrongType { expected: "list" }),
+        };
+
+        let mut known = Vec::new();
+        for t in tiers.iter() {
+            match *t {
+                List(ref urls) => known.extend(urls.iter().filter_map(Bencoded::as_bytes)),
+                _ => return Err(AccessError::WrongType { expected: "list" }),
+            }
+        }
+        let mut new: Vec<&[u8]> = Vec::new();
+        for url in trackers {
+            if !known.contains(&url.as_bytes()) && !new.contains(&url.as_bytes()) {
+                new.push(url.as_bytes());
+            }
+        }
+        if new.is_empty() {
+            return Ok(());
+        }
+
+        let new: Vec<Bencoded> = new.into_iter().map(|url| Bytestring(url.to_vec())).collect();
+        match (tier, tiers.first_mut()) {
+            (TrackerTier::First, Some(&mut List(ref mut urls))) => urls.extend(new),
+            _ => tiers.push(List(new)),
+        }
+        Ok(())
+    }
+
+    /// Returns the `creation date` of a torrent, which is in seconds since the
+    /// Unix epoch, or `None` if it is missing, not an integer, or negative.
+    pub fn creation_date(&self) -> Option<SystemTime> {
+        match self.get(b"creation date") {
+            Some(&Integer(n)) if n >= 0 => {
+                UNIX_EPOCH.checked_add(Duration::from_secs(n as u64))
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the `created by` field of a torrent, naming the program that
+    /// made it, or `None` if it is missing or not a UTF-8 bytestring.
+    pub fn created_by(&self) -> Option<&str> {
+        str::from_utf8(self.get(b"created by")?.as_bytes()?).ok()
+    }
+
+    /// Returns the name to show for a torrent, `info.name`, which is the file
+    /// name of a single-file torrent and the directory name of a multi-file
+    /// one. `info.name.utf-8` is preferred if present, as some clients write
+    /// it alongside a `name` in another encoding.
+    ///
+    /// Returns `None` if neither is present as a UTF-8 bytestring.
+    pub fn display_name(&self) -> Option<&str> {
+        let info = self.get(b"info")?;
+        let name = |key: &[u8]| str::from_utf8(info.get(key)?.as_bytes()?).ok();
+        name(b"name.utf-8").or_else(|| name(b"name"))
+    }
+
+    /// Returns the total size in bytes of the files in a torrent: `info.length`
+    /// for a single-file torrent, or the sum of `info.files[].length` for a
+    /// multi-file one.
+    ///
+    /// Returns `None` if the structure is malformed, a length is negative, or
+    /// the sum overflows.
+    pub fn total_length(&self) -> Option<Int> {
+        let info = self.get(b"info")?;
+        match (info.get(b"length"), info.get(b"files")) {
+            (Some(&Integer(n)), None) if n >= 0 => Some(n),
+            (None, Some(&List(ref files))) => {
+                let mut total: Int = 0;
+                for file in files {
+                    match file.get(b"length") {
+                        Some(&Integer(n)) if n >= 0 => total = total.checked_add(n)?,
+                        _ => return None,
+                    }
+                }
+                Some(total)
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the files of a torrent in order, with the offset at which each
+    /// one starts in the torrent's data, so that a downloaded piece can be
+    /// written to the right files. A single-file torrent gives one record.
+    ///
+    /// Returns `None` if the structure is malformed, a name or path component
+    /// isn't UTF-8, a length is negative, or the offsets overflow.
+    pub fn file_layout(&self) -> Option<Vec<FileRecord>> {
+        let info = self.get(b"info")?;
+        let name = match info.get(b"name") {
+            Some(&Bytestring(ref v)) => str::from_utf8(v).ok()?,
+            _ => return None,
+        };
+
+        match (info.get(b"length"), info.get(b"files")) {
+            (Some(&Integer(n)), None) if n >= 0 => {
+                Some(vec!(FileRecord { path: name.to_string(), length: n, offset: 0 }))
+            }
+            (None, Some(&List(ref files))) => {
+                let mut res = Vec::with_capacity(files.len());
+                let mut offset: Int = 0;
+                for file in files {
+                    let length = match file.get(b"length") {
+                        Some(&Integer(n)) if n >= 0 => n,
+                        _ => return None,
+                    };
+                    let mut path = name.to_string();
+                    match file.get(b"path") {
+                        Some(&List(ref components)) => {
+                            for component in components {
+                                match *component {
+                                    Bytestring(ref v) => {
+                                        path.push('/');
+                                        path.push_str(str::from_utf8(v).ok()?);
+                                    }
+                                    _ => return None,
+                                }
+                            }
+                        }
+                        _ => return None,
+                    }
+                    res.push(FileRecord { path, length, offset });
+                    offset = offset.checked_add(length)?;
+                }
+                Some(res)
+            }
+            _ => None,
+        }
+    }
+
+    /// Reads the files of a torrent from `base_dir`, where they are laid out
+    /// as by `file_layout`, and checks each piece against its hash in
+    /// `piece_hashes`. The files are read in small chunks, so this works for
+    /// torrents of any size.
+    ///
+    /// Pieces that overlap a missing or truncated file are reported as bad.
+    /// Fails with `io::ErrorKind::InvalidData` if the torrent is malformed,
+    /// its number of pieces doesn't fit its length, or a path component could
+    /// escape `base_dir`, such as `..`.
+    pub fn verify_files(&self, base_dir: &Path) -> io::Result<VerifyReport> {
+        let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+        let layout = self.file_layout().ok_or_else(|| invalid("invalid file layout"))?;
+        let hashes = self.piece_hashes().ok_or_else(|| invalid("invalid `info.pieces`"))?;
+        let piece_length = self.piece_length()
+                               .ok_or_else(|| invalid("invalid `info.piece length`"))?
+                               as u64;
+        let total = layout.last().map_or(0, |f| (f.offset + f.length) as u64);
+        if total.div_ceil(piece_length) != hashes.len() as u64 {
+            return Err(invalid("piece count does not match the total length"));
+        }
+
+        let mut pieces = Vec::with_capacity(hashes.len());
+        let mut hasher = Sha1::new();
+        // how much of the current piece has been read, and whether all of it
+        // was there
+        let mut in_piece: u64 = 0;
+        let mut complete = true;
+        let mut buf = vec!(0; VERIFY_BUF_LEN);
+
+        for record in layout {
+            let mut path = PathBuf::from(base_dir);
+            for component in record.path.split('/') {
+                let mut components = Path::new(component).components();
+                match (components.next(), components.next()) {
+                    (Some(Component::Normal(c)), None) => path.push(c),
+                    _ => return Err(invalid("unsafe path component")),
+                }
+            }
+            let mut file = match File::open(&path) {
+                Ok(file) => Some(file),
+                Err(ref e) if e.kind() == io::ErrorKind::NotFound => None,
+                Err(e) => return Err(e),
+            };
+
+            let mut remaining = record.length as u64;
+            while remaining > 0 {
+                let want = cmp::min(cmp::min(remaining, piece_length - in_piece),
+                                    buf.len() as u64) as usize;
+                let got = match file {
+                    Some(ref mut file) => read_up_to(file, &mut buf[..want])?,
+                    None => 0,
+                };
+                hasher.update(&buf[..got]);
+                if got < want {
+                    // the rest of the file is missing
+                    complete = false;
+                    file = None;
+                }
+
+                in_piece += want as u64;
+                remaining -= want as u64;
+                if in_piece == piece_length {
+                    let hash = hasher.finalize_reset();
+                    pieces.push(complete && hash[..] == hashes[pieces.len()][..]);
+                    in_piece = 0;
+                    complete = true;
+                }
+            }
+        }
+        if in_piece > 0 {
+            let hash = hasher.finalize_reset();
+            pieces.push(complete && hash[..] == hashes[pieces.len()][..]);
+        }
+        Ok(VerifyReport { pieces })
+    }
+
+    /// Returns `info.piece length`, or `None` if it is missing or not a
+    /// positive integer.
+    pub fn piece_length(&self) -> Option<Int> {
+        match self.get(b"info")?.get(b"piece length") {
+            Some(&Integer(n)) if n > 0 => Some(n),
+            _ => None,
+        }
+    }
+
+    /// Returns whether `info.piece length` is a power of two, as many clients
+    /// require, or `None` if it is missing or not a positive integer.
+    pub fn piece_length_is_power_of_two(&self) -> Option<bool> {
+        self.piece_length().map(|n| (n as u64).is_power_of_two())
+    }
+
+    /// Returns `info.meta version`, which is 2 for BitTorrent v2 and hybrid
+    /// torrents, or `None` if it is missing or not an integer, as in v1
+    /// torrents.
+    pub fn meta_version(&self) -> Option<Int> {
+        self.get(b"info")?.get(b"meta version")?.as_integer()
+    }
+
+    /// Returns whether this is a private torrent, i.e. `info.private` is 1,
+    /// whose peers must only come from its trackers, not from DHT or peer
+    /// exchange. A missing `private`, or any value other than 1, means the
+    /// torrent is public.
+    pub fn is_private(&self) -> bool {
+        match self.get(b"info") {
+            Some(info) => info.get(b"private").and_then(Bencoded::as_bool) == Some(true),
+            None => false,
+        }
+    }
+
+    /// Returns whether this is a hybrid torrent, whose info dict has both the
+    /// v1 `pieces` and the v2 `file tree`.
+    pub fn is_hybrid(&self) -> bool {
+        match self.get(b"info") {
+            Some(info) => info.get(b"pieces").is_some() && info.get(b"file tree").is_some(),
+            None => false,
+        }
+    }
+
+    /// Returns the number of pieces, i.e. of 20-byte hashes in `info.pieces`.
+    ///
+    /// Returns `None` under the same conditions as `piece_hashes`.
+    pub fn piece_count(&self) -> Option<usize> {
+        match self.get(b"info")?.get(b"pieces") {
+            Some(&Bytestring(ref v)) if v.len() % 20 == 0 => Some(v.len() / 20),
+            _ => None,
+        }
+    }
+
+    /// Returns whether the number of pieces is what the total length and the
+    /// piece length call for, i.e. the total length divided by the piece
+    /// length, rounded up. A mismatch means the torrent is corrupt, which this
+    /// finds without hashing anything.
+    ///
+    /// Returns `None` if any of `total_length`, `piece_length` and
+    /// `piece_count` does.
+    pub fn pieces_consistent(&self) -> Option<bool> {
+        let total = self.total_length()? as u64;
+        let piece_length = self.piece_length()? as u64;
+        let count = self.piece_count()? as u64;
+        Some(total.div_ceil(piece_length) == count)
+    }
+
+    /// Returns the offset in the torrent's data at which each piece starts,
+    /// from `total_length` and `piece_length`. Every piece but the last is
+    /// `piece_length` bytes long; the last one ends at the total length, and
+    /// may be shorter. Together with `file_layout`, this tells which files a
+    /// piece covers.
+    ///
+    /// Returns `None` if `pieces_consistent` does, or if the number of pieces
+    /// isn't what the lengths call for, so that a huge declared length can't
+    /// make this allocate more than `info.pieces` holds.
+    pub fn piece_boundaries(&self) -> Option<Vec<u64>> {
+        if !self.pieces_consistent()? {
+            return None;
+        }
+        let total = self.total_length()? as u64;
+        let step = usize::try_from(self.piece_length()?).ok()?;
+        Some((0..total).step_by(step).collect())
+    }
+
+    /// Looks up the value at `path`, a list of dict keys starting from this
+    /// value, and returns the SHA-1 hash of its encoding. With a `path` of
+    /// `[b"info"]` this is the info hash of a torrent.
+    ///
+    /// The value is encoded as by `encode_to`, straight into the hash state,
+    /// so even a large info dict is never held in memory as bytes. Unlike
+    /// `encode_to_hasher`, this does not put the value in canonical form
+    /// first: an info hash is that of the info dict's bytes as they appear
+    /// in the torrent, so an `OrderedDict` or `Raw` kept from decoding with
+    /// `preserve_order` or `preserve_integer_text` is hashed as it is, and
+    /// matches the hash of `field_bytes(s, b"info")`. Returns `None` if
+    /// there is no value at `path`.
+    pub fn hash_subtree_sha1(&self, path: &[&[u8]]) -> Option<[u8; 20]> {
+        let mut val = self;
+        for key in path {
+            val = val.get(key)?;
+        }
+        let mut hasher = Sha1::new();
+        val.encode_to(&mut hasher).expect("hashing cannot fail");
+        Some(hasher.finalize().into())
+    }
+
+    /// Returns the info hash of a torrent, the SHA-1 hash of its `info` dict,
+    /// or `None` if there is no `info`. See `hash_subtree_sha1`.
+    pub fn info_hash(&self) -> Option<[u8; 20]> {
+        self.hash_subtree_sha1(&[b"info"])
+    }
+
+    /// Returns whether this and `other` are torrents of the same content, i.e.
+    /// have the same info hash, whatever their trackers, comments or creation
+    /// dates. Returns `false` if either has no `info` dict.
+    pub fn same_content(&self, other: &Bencoded) -> bool {
+        let info_hash = |t: &Bencoded| match t.get(b"info") {
+            Some(info) if info.is_dict() => t.info_hash(),
+            _ => None,
+        };
+        match (info_hash(self), info_hash(other)) {
+            (Some(a), Some(b)) => a == b,
+            _ => false,
+        }
+    }
+
+    /// Returns the SHA-1 piece hashes stored in `info.pieces`, split into
+    /// 20-byte chunks.
+    ///
+    /// Returns `None` if the field is missing, isn't a bytestring, or its
+    /// length isn't a multiple of 20.
+    pub fn piece_hashes(&self) -> Option<Vec<[u8; 20]>> {
+        let info = self.get(b"info");
+        let pieces = match info.and_then(|info| info.get(b"pieces")) {
+            Some(&Bytestring(ref v)) => v,
+            _ => return None,
+        };
+
+        if pieces.len() % 20 != 0 {
+            return None;
+        }
+
+        let mut res = Vec::with_capacity(pieces.len() / 20);
+        for chunk in pieces.chunks(20) {
+            let mut hash = [0; 20];
+            hash.copy_from_slice(chunk);
+            res.push(hash);
+        }
+        Some(res)
+    }
+
+    /// Returns whether the SHA-1 hash of `data` is the `index`th hash in
+    /// `info.pieces`, to check each piece as it is downloaded. Only that hash
+    /// is looked at, so this doesn't split up the whole of `info.pieces` as
+    /// `piece_hashes` does.
+    ///
+    /// Returns `None` if `index` is out of range, or under the same conditions
+    /// as `piece_hashes`.
+    pub fn verify_piece(&self, index: usize, data: &[u8]) -> Option<bool> {
+        let pieces = match self.get(b"info")?.get(b"pieces") {
+            Some(&Bytestring(ref v)) if v.len() % 20 == 0 => v,
+            _ => return None,
+        };
+        let expected = pieces.chunks(20).nth(index)?;
+        Some(Sha1::digest(data)[..] == expected[..])
+    }
+
+    /// Removes `info.pieces` from a torrent and returns the rest of it along
+    /// with the pieces, e.g. to store the large and incompressible pieces
+    /// apart from the metadata. `attach_pieces` puts them back.
+    ///
+    /// Returns `None` if `info.pieces` is missing or isn't a bytestring.
+    pub fn split_pieces(mut self) -> Option<(Bencoded, Vec<u8>)> {
+        match self.get(b"info").and_then(|info| info.get(b"pieces")) {
+            Some(&Bytestring(_)) => {}
+            _ => return None,
+        }
+        let pieces = self.take_path(&[b"info", b"pieces"])?.into_bytestring()?;
+        Some((self, pieces))
+    }
+
+    /// Sets `info.pieces` to `pieces`, undoing `split_pieces`, so that the
+    /// torrent has the same info hash as before it was split.
+    ///
+    /// Fails with `AccessError::WrongType` if the torrent or its `info` is
+    /// not a dict, in which case nothing is changed.
+    pub fn attach_pieces(&mut self, pieces: Vec<u8>) -> Result<(), AccessError> {
+        self.set_path(&[b"info", b"pieces"], Bytestring(pieces))
+    }
+
+    /// Reads a bytestring of IPv4 peers in the compact format used by
+    /// trackers and the DHT: 6 bytes each, a 4-byte address followed by a
+    /// 2-byte port, both in network byte order.
+    ///
+    /// Returns `None` if this is not a bytestring or its length isn't a
+    /// multiple of 6.
+    pub fn compact_peers(&self) -> Option<Vec<SocketAddrV4>> {
+        let v = match *self {
+            Bytestring(ref v) if v.len() % 6 == 0 => v,
+            _ => return None,
+        };
+        let peers = v.chunks(6).map(|c| {
+            let ip = Ipv4Addr::new(c[0], c[1], c[2], c[3]);
+            SocketAddrV4::new(ip, u16::from_be_bytes([c[4], c[5]]))
+        });
+        Some(peers.collect())
+    }
+
+    /// Encodes `peers` in the compact format read by `compact_peers`.
+    pub fn from_compact_peers(peers: &[SocketAddrV4]) -> Bencoded {
+        let mut v = Vec::with_capacity(peers.len() * 6);
+        for peer in peers {
+            v.push_all(&peer.ip().octets());
+            v.push_all(&peer.port().to_be_bytes());
+        }
+        Bytestring(v)
+    }
+
+    /// Like `compact_peers`, but for IPv6 peers, as in the `values6` and
+    /// `nodes6` of the DHT: 18 bytes each, a 16-byte address followed by a
+    /// 2-byte port.
+    ///
+    /// Returns `None` if this is not a bytestring or its length isn't a
+    /// multiple of 18.
+    pub fn compact_peers6(&self) -> Option<Vec<SocketAddrV6>> {
+        let v = match *self {
+            Bytestring(ref v) if v.len() % 18 == 0 => v,
+            _ => return None,
+        };
+        let peers = v.chunks(18).map(|c| {
+            let mut octets = [0; 16];
+            octets.copy_from_slice(&c[..16]);
+            let port = u16::from_be_bytes([c[16], c[17]]);
+            SocketAddrV6::new(Ipv6Addr::from(octets), port, 0, 0)
+        });
+        Some(peers.collect())
+    }
+
+    /// Encodes `peers` in the compact format read by `compact_peers6`. Flow
+    /// info and scope IDs are not part of the format, and are dropped.
+    pub fn from_compact_peers6(peers: &[SocketAddrV6]) -> Bencoded {
+        let mut v = Vec::with_capacity(peers.len() * 18);
+        for peer in peers {
+            v.push_all(&peer.ip().octets());
+            v.push_all(&peer.port().to_be_bytes());
+        }
+        Bytestring(v)
+    }
+}
+
+/// Creates a torrent from its files' data, hashing the pieces as the data is
+/// fed in, so that nothing but the hashes is kept in memory.
+///
+/// Files added with `add_file` make a multi-file torrent, and the data fed
+/// in must be their contents one after another. With no files added, the
+/// torrent holds a single file called `name` with all the data fed in. Data
+/// can also be written through `io::Write`, e.g. with `io::copy`.
+pub struct TorrentBuilder {
+    name: String,
+    piece_length: usize,
+    announce: Option<String>,
+    files: Vec<(Vec<String>, Int)>,
+    pieces: Vec<u8>,
+    hasher: Sha1,
+    // bytes fed into the current piece, and in total
+    in_piece: usize,
+    total: u64,
+}
+
+impl TorrentBuilder {
+    pub fn new(name: &str, piece_length: usize) -> TorrentBuilder {
+        TorrentBuilder {
+            name: name.to_string(),
+            piece_length,
+            announce: None,
+            files: Vec::new(),
+            pieces: Vec::new(),
+            hasher: Sha1::new(),
+            in_piece: 0,
+            total: 0,
+        }
+    }
+
+    /// Sets the tracker URL stored as `announce`.
+    pub fn announce(&mut self, url: &str) -> &mut TorrentBuilder {
+        self.announce = Some(url.to_string());
+        self
+    }
+
+    /// Adds a file of `length` bytes at `path`, relative to the directory
+    /// called `name`.
+    pub fn add_file(&mut self, path: &[&str], length: Int) -> &mut TorrentBuilder {
+        self.files.push((path.iter().map(|c| c.to_string()).collect(), length));
+        self
+    }
+
+    /// Hashes `data` as the next part of the torrent's contents.
+    ///
+    /// With a piece length of 0 nothing is hashed, as there are no pieces to
+    /// hash, and `build` fails.
+    pub fn feed(&mut self, mut data: &[u8]) {
+        self.total += data.len() as u64;
+        if self.piece_length == 0 {
+            return;
+        }
+        while !data.is_empty() {
+            let n = cmp::min(data.len(), self.piece_length - self.in_piece);
+            self.hasher.update(&data[..n]);
+            self.in_piece += n;
+            data = &data[n..];
+            if self.in_piece == self.piece_length {
+                self.finish_piece();
+            }
+        }
+    }
+
+    fn finish_piece(&mut self) {
+        self.pieces.push_all(&self.hasher.finalize_reset());
+        self.in_piece = 0;
+    }
+
+    /// Returns the torrent along with its info hash.
+    ///
+    /// Fails with `TorrentError::Invalid` if the piece length is 0, a file
+    /// length is negative, or the data fed in doesn't add up to the lengths
+    /// of the files.
+    pub fn build(mut self) -> Result<(Bencoded, [u8; 20]), TorrentError> {
+        if self.piece_length == 0 {
+            return Err(TorrentError::Invalid("info.piece length"));
+        }
+        if self.in_piece > 0 {
+            self.finish_piece();
+        }
+
+        let mut info = HashMap::new();
+        info.insert(b"name".to_vec(), Bytestring(self.name.into_bytes()));
+        info.insert(b"piece length".to_vec(), Integer(self.piece_length as Int));
+        info.insert(b"pieces".to_vec(), Bytestring(self.pieces));
+        if self.files.is_empty() {
+            info.insert(b"length".to_vec(), Integer(self.total as Int));
+        } else {
+            let mut expected: u64 = 0;
+            let mut files = Vec::with_capacity(self.files.len());
+            for (path, length) in self.files {
+                if length < 0 {
+                    return Err(TorrentError::Invalid("info.files"));
+                }
+                expected += length as u64;
+                let path = path.into_iter().map(|c| Bytestring(c.into_bytes())).collect();
+                let mut file = HashMap::new();
+                file.insert(b"length".to_vec(), Integer(length));
+                file.insert(b"path".to_vec(), List(path));
+                files.push(Dict(file));
+            }
+            if expected != self.total {
+                return Err(TorrentError::Invalid("info.files"));
+            }
+            info.insert(b"files".to_vec(), List(files));
+        }
+
+        let mut torrent = HashMap::new();
+        if let Some(url) = self.announce {
+            torrent.insert(b"announce".to_vec(), Bytestring(url.into_bytes()));
+        }
+        torrent.insert(b"info".to_vec(), Dict(info));
+        let torrent = Dict(torrent);
+        let hash = torrent.info_hash().expect("the torrent has an info dict");
+        Ok((torrent, hash))
+    }
+}
+
+impl Write for TorrentBuilder {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.feed(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Fills `buf` from `r` as far as it goes, and returns how much was read,
+/// which is less than `buf.len()` only at the end of `r`.
+fn read_up_to<R: Read>(r: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut len = 0;
+    while len < buf.len() {
+        match r.read(&mut buf[len..]) {
+            Ok(0) => break,
+            Ok(n) => len += n,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(len)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use sha1::{Digest, Sha1};
+
+    use Bencoded::*;
+
+    fn torrent_with_pieces(pieces: Vec<u8>) -> ::Bencoded {
+        let mut info = HashMap::new();
+        info.insert(b"pieces".to_vec(), Bytestring(pieces));
+        let mut m = HashMap::new();
+        m.insert(b"info".to_vec(), Dict(info));
+        Dict(m)
+    }
+
+    /// A single-file torrent of `length` bytes with `pieces` piece hashes.
+    fn torrent_with_lengths(length: ::Int, piece_length: ::Int, pieces: usize) -> ::Bencoded {
+        let mut t = torrent_with_pieces(vec!(0; 20 * pieces));
+        t.set_path(&[b"info", b"length"], Integer(length)).unwrap();
+        t.set_path(&[b"info", b"piece length"], Integer(piece_length)).unwrap();
+        t
+    }
+
+    #[test]
+    fn validate_torrent() {
+        use super::TorrentError::*;
+
+        let valid = b"d8:announce3:url4:infod6:lengthi1e4:name1:a\
+                      12:piece lengthi16384e6:pieces0:ee";
+        assert_eq!(::parse(valid).validate_torrent(), Ok(()));
+
+        let s = b"d4:infod6:lengthi1e4:name1:a12:piece lengthi1e6:pieces0:ee";
+        assert_eq!(::parse(s).validate_torrent(), Err(Missing("announce")));
+
+        let s = b"d8:announcei1e4:infodee";
+        assert_eq!(::parse(s).validate_torrent(), Err(Invalid("announce")));
+
+        let s = b"d8:announce3:url4:infod4:name1:a12:piece lengthi0e6:pieces0:ee";
+        assert_eq!(::parse(s).validate_torrent(), Err(Invalid("info.piece length")));
+
+        let s = b"d8:announce3:url4:infod4:name1:a12:piece lengthi1e6:pieces1:xee";
+        assert_eq!(::parse(s).validate_torrent(), Err(Invalid("info.pieces")));
+
+        let s = b"d8:announce3:url4:infod4:name1:a12:piece lengthi1e6:pieces0:ee";
+        assert_eq!(::parse(s).validate_torrent(), Err(AmbiguousLayout));
+    }
+
+    #[test]
+    fn trackers() {
+        let s = b"d8:announce1:a13:announce-listll1:a1:bel1:c2:\xff\xffi1eel1:beee";
+        assert_eq!(::parse(s).trackers(), vec!("a", "b", "c"));
+        assert_eq!(::parse(b"de").trackers(), Vec::<String>::new());
+    }
+
+    #[test]
+    fn display_name() {
+        let t = ::parse(b"d4:infod6:lengthi1e4:name5:a.txtee");
+        assert_eq!(t.display_name(), Some("a.txt"));
+        let t = ::parse(b"d4:infod5:filesle4:name3:dir10:name.utf-85:dir\xc3\xa9ee");
+        assert_eq!(t.display_name(), Some("dir\u{e9}"));
+        let t = ::parse(b"d4:infod4:name1:\xff10:name.utf-81:\xffee");
+        assert_eq!(t.display_name(), None);
+        assert_eq!(::parse(b"d4:infod4:name1:\xff10:name.utf-81:aee").display_name(), Some("a"));
+        assert_eq!(::parse(b"d4:infodee").display_name(), None);
+    }
+
+    #[test]
+    fn creation_date_and_created_by() {
+        use std::time::{Duration, UNIX_EPOCH};
+
+        let t = ::parse(b"d10:created by9:mktorrent13:creation datei1700000000ee");
+        assert_eq!(t.creation_date(), Some(UNIX_EPOCH + Duration::from_secs(1700000000)));
+        assert_eq!(t.created_by(), Some("mktorrent"));
+
+        let t = ::parse(b"d10:created by1:\xff13:creation datei-1ee");
+        assert_eq!(t.creation_date(), None);
+        assert_eq!(t.created_by(), None);
+        assert_eq!(::parse(b"de").creation_date(), None);
+        assert_eq!(::parse(b"d13:creation date1:1e").creation_date(), None);
+    }
+
+    #[test]
+    fn add_trackers() {
+        use super::TrackerTier;
+
+        let mut t = ::parse(b"d8:announce1:ae");
+        t.add_trackers(&["b", "a", "b"], TrackerTier::New).unwrap();
+        assert_eq!(t, ::parse(b"d8:announce1:a13:announce-listll1:ael1:beee"));
+        t.add_trackers(&["c"], TrackerTier::First).unwrap();
+        t.add_trackers(&["c", "b"], TrackerTier::New).unwrap();
+        assert_eq!(t, ::parse(b"d8:announce1:a13:announce-listll1:a1:cel1:beee"));
+        assert_eq!(t.trackers(), vec!("a", "c", "b"));
+
+        let mut t = ::parse(b"de");
+        t.add_trackers(&["a"], TrackerTier::First).unwrap();
+        assert_eq!(t, ::parse(b"d13:announce-listll1:aeee"));
+
+        let mut t = ::parse(b"d13:announce-listl1:aee");
+        assert!(t.add_trackers(&["b"], TrackerTier::New).is_err());
+        assert!(Integer(1).add_trackers(&["b"], TrackerTier::New).is_err());
+    }
+
+    #[test]
+    fn total_length() {
+        let s = b"d4:infod6:lengthi42eee";
+        assert_eq!(::parse(s).total_length(), Some(42));
+        let s = b"d4:infod5:filesld6:lengthi1eed6:lengthi2eeeee";
+        assert_eq!(::parse(s).total_length(), Some(3));
+        let s = b"d4:infod5:filesld6:lengthi1eed6:lengthi-2eeeee";
+        assert_eq!(::parse(s).total_length(), None);
+        let s = b"d4:infod6:lengthi1e5:filesleee";
+        assert_eq!(::parse(s).total_length(), None);
+        assert_eq!(::parse(b"d4:infodee").total_length(), None);
+    }
+
+    #[test]
+    fn file_layout() {
+        use super::FileRecord;
+
+        let s = b"d4:infod6:lengthi42e4:name5:a.isoee";
+        assert_eq!(::parse(s).file_layout(),
+                   Some(vec!(FileRecord { path: "a.iso".to_string(), length: 42, offset: 0 })));
+
+        let s = b"d4:infod5:filesld6:lengthi10e4:pathl1:a1:beed6:lengthi0e4:pathl1:ceed\
+                  6:lengthi5e4:pathl1:deee4:name3:diree";
+        assert_eq!(::parse(s).file_layout(), Some(vec!(
+            FileRecord { path: "dir/a/b".to_string(), length: 10, offset: 0 },
+            FileRecord { path: "dir/c".to_string(), length: 0, offset: 10 },
+            FileRecord { path: "dir/d".to_string(), length: 5, offset: 10 },
+        )));
+
+        let s = b"d4:infod5:filesld6:lengthi1e4:pathl1:\xffeee4:name3:diree";
+        assert_eq!(::parse(s).file_layout(), None);
+        let s = b"d4:infod5:filesld6:lengthi-1e4:pathl1:aeee4:name3:diree";
+        assert_eq!(::parse(s).file_layout(), None);
+        assert_eq!(::parse(b"d4:infod6:lengthi1eee").file_layout(), None);
+    }
+
+    #[test]
+    fn verify_files() {
+        use std::env;
+        use std::fs;
+        use std::process;
+
+        // two files of 5 and 7 bytes in pieces of 4, so pieces span files
+        let data = b"helloworld!!";
+        let mut pieces = Vec::new();
+        for chunk in data.chunks(4) {
+            pieces.extend_from_slice(&Sha1::digest(chunk));
+        }
+        let mut t = ::parse(b"d4:infod5:filesld6:lengthi5e4:pathl1:aeed6:lengthi7e\
+                              4:pathl3:sub1:beee4:name3:dir12:piece lengthi4eee");
+        t.set_path(&[b"info", b"pieces"], Bytestring(pieces)).unwrap();
+
+        let base = env::temp_dir().join(format!("bencode-verify-{}", process::id()));
+        fs::create_dir_all(base.join("dir/sub")).unwrap();
+        fs::write(base.join("dir/a"), b"hello").unwrap();
+        fs::write(base.join("dir/sub/b"), b"world!!").unwrap();
+        assert_eq!(t.verify_files(&base).unwrap().pieces, vec!(true, true, true));
+        assert!(t.verify_files(&base).unwrap().is_complete());
+
+        // a corrupt byte in the middle piece, and a truncated last file
+        fs::write(base.join("dir/sub/b"), b"wXrld").unwrap();
+        let report = t.verify_files(&base).unwrap();
+        assert_eq!(report.pieces, vec!(true, false, false));
+        assert_eq!(report.good_pieces(), 1);
+
+        fs::remove_file(base.join("dir/a")).unwrap();
+        assert_eq!(t.verify_files(&base).unwrap().pieces, vec!(false, false, false));
+        fs::remove_dir_all(&base).unwrap();
+
+        let mut evil = t.clone();
+        evil.set_path(&[b"info", b"name"], Bytestring(b"..".to_vec())).unwrap();
+        assert_eq!(evil.verify_files(&base).unwrap_err().kind(),
+                   ::std::io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn torrent_builder() {
+        use super::{FileRecord, TorrentBuilder};
+
+        let mut b = TorrentBuilder::new("a.txt", 4);
+        b.announce("http://tracker/announce");
+        b.feed(b"hello");
+        b.feed(b"world!");
+        let (t, hash) = b.build().unwrap();
+        assert_eq!(t.validate_torrent(), Ok(()));
+        assert_eq!(t.info_hash(), Some(hash));
+        assert_eq!(t.total_length(), Some(11));
+        let pieces: Vec<[u8; 20]> = [&b"hell"[..], b"owor", b"ld!"].iter()
+                                        .map(|p| Sha1::digest(p).into())
+                                        .collect();
+        assert_eq!(t.piece_hashes(), Some(pieces));
+
+        let mut b = TorrentBuilder::new("dir", 4);
+        b.add_file(&["a"], 5).add_file(&["sub", "b"], 6);
+        ::std::io::copy(&mut &b"helloworld!!"[..11], &mut b).unwrap();
+        let (t, _) = b.build().unwrap();
+        assert_eq!(t.file_layout(), Some(vec!(
+            FileRecord { path: "dir/a".to_string(), length: 5, offset: 0 },
+            FileRecord { path: "dir/sub/b".to_string(), length: 6, offset: 5 },
+        )));
+        assert_eq!(t.piece_count(), Some(3));
+        assert_eq!(t.validate_torrent(), Err(super::TorrentError::Missing("announce")));
+
+        let mut b = TorrentBuilder::new("dir", 4);
+        b.add_file(&["a"], 5);
+        b.feed(b"hi");
+        assert_eq!(b.build().unwrap_err(), super::TorrentError::Invalid("info.files"));
+        assert!(TorrentBuilder::new("a", 0).build().is_err());
+
+        // a zero piece length must not hang feeding data in
+        let mut b = TorrentBuilder::new("a", 0);
+        b.feed(b"x");
+        ::std::io::Write::write_all(&mut b, b"yz").unwrap();
+        assert_eq!(b.build().unwrap_err(), super::TorrentError::Invalid("info.piece length"));
+    }
+
+    #[test]
+    fn split_pieces() {
+        let t = torrent_with_pieces(vec!(1; 40));
+        let hash = t.info_hash();
+        let (mut meta, pieces) = t.clone().split_pieces().unwrap();
+        assert_eq!(pieces, vec!(1; 40));
+        assert_eq!(meta, ::parse(b"d4:infodee"));
+
+        meta.attach_pieces(pieces).unwrap();
+        assert_eq!(meta, t);
+        assert_eq!(meta.info_hash(), hash);
+
+        assert_eq!(::parse(b"d4:infod6:piecesi1eee").split_pieces(), None);
+        assert_eq!(::parse(b"d4:infoi1ee").attach_pieces(vec!()),
+                   Err(::AccessError::WrongType { expected: "dict" }));
+    }
+
+    #[test]
+    fn piece_hashes() {
+        let mut pieces = vec!(1; 20);
+        pieces.extend(vec!(2; 20));
+        let t = torrent_with_pieces(pieces);
+        assert_eq!(t.piece_hashes(), Some(vec!([1; 20], [2; 20])));
+
+        assert_eq!(torrent_with_pieces(vec!()).piece_hashes(), Some(vec!()));
+        assert_eq!(torrent_with_pieces(vec!(0; 21)).piece_hashes(), None);
+        assert_eq!(Integer(1).piece_hashes(), None);
+    }
+
+    #[test]
+    fn verify_piece() {
+        let mut pieces = Sha1::digest(b"abcd").to_vec();
+        pieces.extend(Sha1::digest(b"ef"));
+        let t = torrent_with_pieces(pieces);
+        assert_eq!(t.verify_piece(0, b"abcd"), Some(true));
+        assert_eq!(t.verify_piece(1, b"ef"), Some(true));
+        assert_eq!(t.verify_piece(1, b"eg"), Some(false));
+        assert_eq!(t.verify_piece(0, b"ef"), Some(false));
+        assert_eq!(t.verify_piece(2, b""), None);
+        assert_eq!(torrent_with_pieces(vec!(0; 21)).verify_piece(0, b""), None);
+    }
+
+    #[test]
+    fn hash_subtree_sha1() {
+        let t = ::parse(b"d4:infod6:lengthi1e4:name1:aee");
+        // sha1sum of `d6:lengthi1e4:name1:ae`
+        let hash = t.hash_subtree_sha1(&[b"info"]).unwrap();
+        assert_eq!(hash, *b"\x8a\xa9\xd3\xc6\x5b\x01\x64\xd2\x22\xd9\
+                            \xb2\x52\x7a\x70\xf1\x25\x66\x85\x75\xef");
+        assert_eq!(t.hash_subtree_sha1(&[]), Some(Sha1::digest(t.to_bytes()).into()));
+        assert_eq!(t.hash_subtree_sha1(&[b"info", b"missing"]), None);
+        assert_eq!(t.info_hash(), Some(hash));
+        assert_eq!(::parse(b"de").info_hash(), None);
+
+        // a non-canonical info dict is hashed as it appears in the torrent
+        let s = b"d4:infod4:name1:a6:lengthi1eee";
+        let opts = ::DecodeOptions { preserve_order: true, ..Default::default() };
+        let (t, _) = ::decode_lenient_with_options(s, &opts).unwrap();
+        assert!(matches!(t.get(b"info"), Some(&OrderedDict(_))));
+        let info = ::field_bytes(s, b"info").unwrap().unwrap();
+        assert_eq!(t.info_hash(), Some(Sha1::digest(info).into()));
+        assert!(t.info_hash() != ::parse(b"d4:infod6:lengthi1e4:name1:aee").info_hash());
+    }
+
+    #[test]
+    fn same_content() {
+        let a = ::parse(b"d8:announce1:a4:infod6:lengthi1e4:name1:aee");
+        let b = ::parse(b"d8:announce1:b7:comment1:c4:infod6:lengthi1e4:name1:aee");
+        let c = ::parse(b"d8:announce1:a4:infod6:lengthi2e4:name1:aee");
+        assert!(a.same_content(&b));
+        assert!(!a.same_content(&c));
+        assert!(!::parse(b"d4:infoi1ee").same_content(&::parse(b"d4:infoi1ee")));
+        assert!(!::parse(b"de").same_content(&::parse(b"de")));
+    }
+
+    #[test]
+    fn compact_peers() {
+        use std::net::{Ipv4Addr, SocketAddrV4};
+
+        let v = b"\x0a\x00\x00\x01\x1a\xe1\xc0\xa8\x01\x02\x00\x50".to_vec();
+        let peers = vec!(SocketAddrV4::new(Ipv4Addr::new(10, 0, 0, 1), 6881),
+                         SocketAddrV4::new(Ipv4Addr::new(192, 168, 1, 2), 80));
+        assert_eq!(Bytestring(v.clone()).compact_peers(), Some(peers.clone()));
+        assert_eq!(::Bencoded::from_compact_peers(&peers), Bytestring(v));
+
+        assert_eq!(Bytestring(vec!()).compact_peers(), Some(vec!()));
+        assert_eq!(Bytestring(vec!(0; 7)).compact_peers(), None);
+        assert_eq!(Integer(1).compact_peers(), None);
+    }
+
+    #[test]
+    fn compact_peers6() {
+        use std::net::{Ipv6Addr, SocketAddrV6};
+
+        let mut v = vec!(0x20, 0x01, 0x0d, 0xb8);
+        v.extend(vec!(0; 11));
+        v.extend(vec!(1, 0x1a, 0xe1));
+        v.extend(vec!(0; 15));
+        v.extend(vec!(1, 0, 0x50));
+        let peers = vec!(SocketAddrV6::new(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1),
+                                           6881, 0, 0),
+                         SocketAddrV6::new(Ipv6Addr::LOCALHOST, 80, 0, 0));
+        assert_eq!(Bytestring(v.clone()).compact_peers6(), Some(peers.clone()));
+        assert_eq!(::Bencoded::from_compact_peers6(&peers), Bytestring(v));
+
+        assert_eq!(Bytestring(vec!()).compact_peers6(), Some(vec!()));
+        assert_eq!(Bytestring(vec!(0; 6)).compact_peers6(), None);
+        assert_eq!(Integer(1).compact_peers6(), None);
+    }
+
+    #[test]
+    fn piece_length_and_count() {
+        let s = b"d4:infod12:piece lengthi16384e6:pieces40:\
+                  0123456789012345678901234567890123456789ee";
+        assert_eq!(::parse(s).piece_length(), Some(16384));
+        assert_eq!(::parse(s).piece_count(), Some(2));
+
+        let s = b"d4:infod12:piece lengthi0e6:pieces1:xee";
+        assert_eq!(::parse(s).piece_length(), None);
+        assert_eq!(::parse(s).piece_count(), None);
+        assert_eq!(torrent_with_pieces(vec!()).piece_count(), Some(0));
+    }
+
+    #[test]
+    fn pieces_consistent() {
+        let torrent = |length, pieces| torrent_with_lengths(length, 4, pieces);
+        assert_eq!(torrent(8, 2).pieces_consistent(), Some(true));
+        assert_eq!(torrent(9, 3).pieces_consistent(), Some(true));
+        assert_eq!(torrent(0, 0).pieces_consistent(), Some(true));
+        assert_eq!(torrent(9, 2).pieces_consistent(), Some(false));
+        assert_eq!(torrent(8, 3).pieces_consistent(), Some(false));
+        assert_eq!(torrent_with_pieces(vec!()).pieces_consistent(), None);
+    }
+
+    #[test]
+    fn piece_boundaries() {
+        assert_eq!(torrent_with_lengths(10, 4, 3).piece_boundaries(), Some(vec!(0, 4, 8)));
+        assert_eq!(torrent_with_lengths(8, 4, 2).piece_boundaries(), Some(vec!(0, 4)));
+        assert_eq!(torrent_with_lengths(0, 4, 0).piece_boundaries(), Some(vec!()));
+        assert_eq!(torrent_with_lengths(8, 0, 2).piece_boundaries(), None);
+        assert_eq!(torrent_with_lengths(8, 4, 3).piece_boundaries(), None);
+        // a huge length doesn't fit the pieces, and isn't allocated for
+        assert_eq!(torrent_with_lengths(::Int::MAX, 1, 1).piece_boundaries(), None);
+
+        let s = b"d4:infod5:filesld6:lengthi3eed6:lengthi2eee12:piece lengthi4e\
+                  6:pieces40:0123456789012345678901234567890123456789ee";
+        assert_eq!(::parse(s).piece_boundaries(), Some(vec!(0, 4)));
+    }
+
+    #[test]
+    fn is_private() {
+        assert!(::parse(b"d4:infod7:privatei1eee").is_private());
+        assert!(!::parse(b"d4:infod7:privatei0eee").is_private());
+        assert!(!::parse(b"d4:infod7:privatei2eee").is_private());
+        assert!(!::parse(b"d4:infod7:private1:1ee").is_private());
+        assert!(!::parse(b"d4:infodee").is_private());
+        assert!(!::parse(b"d7:privatei1ee").is_private());
+    }
+
+    #[test]
+    fn meta_version() {
+        let s = b"d4:infod9:file treede12:meta versioni2e6:pieces0:ee";
+        assert_eq!(::parse(s).meta_version(), Some(2));
+        assert!(::parse(s).is_hybrid());
+
+        let s = b"d4:infod9:file treede12:meta versioni2eee";
+        assert!(!::parse(s).is_hybrid());
+        let s = b"d4:infod6:pieces0:ee";
+        assert_eq!(::parse(s).meta_version(), None);
+        assert!(!::parse(s).is_hybrid());
+        assert!(!Integer(1).is_hybrid());
+    }
+
+    #[test]
+    fn piece_length_is_power_of_two() {
+        let s = b"d4:infod12:piece lengthi16384eee";
+        assert_eq!(::parse(s).piece_length_is_power_of_two(), Some(true));
+        let s = b"d4:infod12:piece lengthi1000eee";
+        assert_eq!(::parse(s).piece_length_is_power_of_two(), Some(false));
+        let s = b"d4:infod12:piece length1:xee";
+        assert_eq!(::parse(s).piece_length_is_power_of_two(), None);
+        assert_eq!(::parse(b"d4:infodee").piece_length_is_power_of_two(), None);
+    }
+}
@@ -0,0 +1,14 @@
+//! An entry point for decoding from JavaScript with `wasm-bindgen`.
+
+use js_sys::JSON;
+use wasm_bindgen::prelude::*;
+
+use decode;
+
+/// Decodes `bytes` and returns the value as a JavaScript object, converted as
+/// by `Bencoded::to_json`. Fails with the error message as a string.
+#[wasm_bindgen]
+pub fn decode_to_json(bytes: &[u8]) -> Result<JsValue, JsValue> {
+    let val = decode(bytes).map_err(|e| JsValue::from_str(&e.to_string()))?;
+    JSON::parse(&val.to_json().to_string())
+}
@@ -0,0 +1,82 @@
+#![cfg(feature = "derive")]
+
+extern crate bencode;
+
+use bencode::{ConvertError, FromBencoded};
+
+#[derive(FromBencoded, Debug, PartialEq)]
+struct Info {
+    name: String,
+    #[bencode(rename = "piece length")]
+    piece_length: u64,
+    pieces: Vec<u8>,
+    private: Option<bool>,
+}
+
+#[derive(FromBencoded, Debug, PartialEq)]
+struct Torrent {
+    announce: String,
+    #[bencode(rename = "announce-list")]
+    announce_list: Option<Vec<Vec<String>>>,
+    info: Info,
+}
+
+#[derive(FromBencoded, Debug, PartialEq)]
+struct FileEntry {
+    length: u64,
+    path: Vec<String>,
+}
+
+#[derive(FromBencoded, Debug, PartialEq)]
+struct MultiFileInfo {
+    files: Vec<FileEntry>,
+    name: String,
+}
+
+#[test]
+fn derive_nested() {
+    let s = b"d8:announce3:foo4:infod4:name3:bar12:piece lengthi16384e6:pieces2:xyee";
+    let t = Torrent::from_bencoded(&bencode::parse(s)).unwrap();
+    assert_eq!(t, Torrent {
+        announce: "foo".to_string(),
+        announce_list: None,
+        info: Info {
+            name: "bar".to_string(),
+            piece_length: 16384,
+            pieces: b"xy".to_vec(),
+            private: None,
+        },
+    });
+}
+
+#[test]
+fn derive_errors() {
+    let s = b"d4:name3:bar6:pieces0:e";
+    assert_eq!(Info::from_bencoded(&bencode::parse(s)),
+               Err(ConvertError::MissingKey(b"piece length".to_vec())));
+
+    let s = b"d4:namei1e12:piece lengthi1e6:pieces0:e";
+    assert_eq!(Info::from_bencoded(&bencode::parse(s)),
+               Err(ConvertError::WrongType { expected: "bytestring" }));
+
+    assert_eq!(Info::from_bencoded(&bencode::parse(b"le")),
+               Err(ConvertError::WrongType { expected: "dict" }));
+}
+
+#[test]
+fn derive_list_of_dicts() {
+    let s = b"d5:filesld6:lengthi1024e4:pathl6:READMEeed6:lengthi7e4:pathl3:src7:main.rseee\
+              4:name7:projecte";
+    let info = MultiFileInfo::from_bencoded(&bencode::parse(s)).unwrap();
+    assert_eq!(info, MultiFileInfo {
+        files: vec!(
+            FileEntry { length: 1024, path: vec!("README".to_string()) },
+            FileEntry { length: 7, path: vec!("src".to_string(), "main.rs".to_string()) },
+        ),
+        name: "project".to_string(),
+    });
+
+    let s = b"d5:filesld6:lengthi1eei1ee4:name1:ae";
+    assert_eq!(MultiFileInfo::from_bencoded(&bencode::parse(s)),
+               Err(ConvertError::MissingKey(b"path".to_vec())));
+}
@@ -1,4 +1,6 @@
 extern crate bencode;
+#[cfg(feature = "torrent")]
+extern crate sha1;
 
 use std::collections::HashMap;
 use bencode::Bencoded::*;
@@ -33,3 +35,700 @@ fn get_dict() {
     let parsed = bencode::parse(s);
     assert_eq!(parsed.get(b"info").unwrap().to_string(), "d3:fooi42ee".to_string());
 }
+
+#[test]
+fn decode_errors() {
+    use bencode::ParseError::*;
+
+    assert_eq!(bencode::decode(b"i42e"), Ok(Integer(42)));
+    assert_eq!(bencode::decode(b""), Err(Incomplete { needed: None }));
+    assert_eq!(bencode::decode(b"i42"), Err(Incomplete { needed: None }));
+    assert_eq!(bencode::decode(b"5:hel"), Err(Incomplete { needed: Some(2) }));
+    assert_eq!(bencode::decode(b"l5:hel"), Err(Incomplete { needed: Some(2) }));
+    assert_eq!(bencode::decode(b"l5:helloe").map(|_| ()), Ok(()));
+    assert_eq!(bencode::decode(b"i4xe"), Err(UnexpectedByte { pos: 2, byte: b'x' }));
+    assert_eq!(bencode::decode(b"ie"), Err(UnexpectedByte { pos: 1, byte: b'e' }));
+    assert_eq!(bencode::decode(b"i42ei1e"), Err(TrailingData { pos: 4 }));
+    assert_eq!(bencode::decode(b"di1ei2ee"), Err(NonBytestringKey { pos: 1 }));
+    assert_eq!(bencode::decode(b"d1:ai1eli1eei2ee"), Err(NonBytestringKey { pos: 7 }));
+    assert_eq!(bencode::decode_borrowed(b"dd1:ai1eei2ee"), Err(NonBytestringKey { pos: 1 }));
+    assert_eq!(bencode::skip_value(b"di1ei2ee", 0), Err(NonBytestringKey { pos: 1 }));
+}
+
+#[test]
+fn incremental_decoder() {
+    let mut decoder = bencode::IncrementalDecoder::new();
+    assert_eq!(decoder.feed(b"d3:fo"), Ok(None));
+    assert_eq!(decoder.feed(b"oli4"), Ok(None));
+    assert_eq!(decoder.feed(b"2eee"), Ok(Some(bencode::parse(b"d3:fooli42eee"))));
+    assert!(decoder.buffered().is_empty());
+
+    assert_eq!(decoder.feed(b"i1ei2"), Ok(Some(Integer(1))));
+    assert_eq!(decoder.buffered(), b"i2");
+    assert_eq!(decoder.feed(b"e"), Ok(Some(Integer(2))));
+    assert_eq!(decoder.feed(b""), Ok(None));
+
+    assert!(decoder.feed(b"i1xe").is_err());
+    assert!(decoder.buffered().is_empty());
+}
+
+#[test]
+fn incremental_decoder_byte_by_byte() {
+    let s = b"d1:ad0:0:1:bli-12e0:le3:xyzee1:cle1:di0ee";
+    let mut decoder = bencode::IncrementalDecoder::new();
+    for &c in &s[..s.len() - 1] {
+        assert_eq!(decoder.feed(&[c]), Ok(None));
+    }
+    assert_eq!(decoder.feed(&s[s.len() - 1..]), Ok(Some(bencode::parse(s))));
+
+    // errors are found as soon as the bad byte arrives
+    for bad in &[&b"l1:ai1x"[..], b"di", b"d1:ae", b"e", b"99999999999999999999"] {
+        let mut decoder = bencode::IncrementalDecoder::new();
+        let (last, init) = bad.split_last().unwrap();
+        for &c in init {
+            assert_eq!(decoder.feed(&[c]), Ok(None));
+        }
+        assert!(decoder.feed(&[*last]).is_err());
+    }
+
+    let opts = bencode::DecodeOptions { allow_whitespace: true, ..Default::default() };
+    let mut decoder = bencode::IncrementalDecoder::with_options(opts);
+    assert_eq!(decoder.feed(b" l i1e\n"), Ok(None));
+    assert_eq!(decoder.feed(b" e "), Ok(Some(List(vec!(Integer(1))))));
+
+    // other limits are still checked before the value is complete
+    let opts = bencode::DecodeOptions { max_elements: Some(10), ..Default::default() };
+    let mut decoder = bencode::IncrementalDecoder::with_options(opts);
+    assert_eq!(decoder.feed(b"l"), Ok(None));
+    let mut res = Ok(None);
+    for _ in 0..100 {
+        res = decoder.feed(b"i1e");
+        if res.is_err() {
+            break;
+        }
+    }
+    assert!(res.is_err());
+}
+
+#[test]
+fn decode_chunks() {
+    let chunks: Vec<&[u8]> = vec!(b"d3:fo", b"oli4", b"2eeei", b"1ei2e");
+    let mut chunks = chunks.into_iter();
+    let (val, rest) = bencode::decode_chunks(chunks.by_ref()).unwrap();
+    assert_eq!(val, bencode::parse(b"d3:fooli42eee"));
+    assert_eq!(rest, b"i");
+    assert_eq!(chunks.next(), Some(&b"1ei2e"[..]));
+
+    let chunks: Vec<&[u8]> = vec!(b"l3:", b"fo");
+    assert_eq!(bencode::decode_chunks(chunks),
+               Err(bencode::ParseError::Incomplete { needed: Some(1) }));
+    assert_eq!(bencode::decode_chunks(vec!()),
+               Err(bencode::ParseError::Incomplete { needed: None }));
+    let chunks: Vec<&[u8]> = vec!(b"i1", b"xe");
+    assert!(bencode::decode_chunks(chunks).is_err());
+
+    // one byte at a time, with errors at their offset in the whole input
+    let s = b"d3:fooli42ee3:bar4:spame";
+    assert_eq!(bencode::decode_chunks(s.chunks(1)), Ok((bencode::parse(s), vec!())));
+    let s = b"d3:fooli42ee3:bar4:spx";
+    assert_eq!(bencode::decode_chunks(s.chunks(1)),
+               Err(bencode::ParseError::Incomplete { needed: Some(1) }));
+    let s = b"d3:fooli42ee3:barxe";
+    assert_eq!(bencode::decode_chunks(s.chunks(3)),
+               Err(bencode::ParseError::UnexpectedByte { pos: 17, byte: b'x' }));
+}
+
+#[test]
+fn max_bytestring_len() {
+    let opts = bencode::DecodeOptions {
+        max_bytestring_len: Some(4),
+        ..Default::default()
+    };
+    assert!(bencode::decode_with_options(b"l4:spame", &opts).is_ok());
+    assert_eq!(bencode::decode_with_options(b"l5:spame", &opts),
+               Err(bencode::ParseError::BytestringTooLong { pos: 1, len: 5 }));
+
+    // rejected as soon as the length is known, without waiting for the rest
+    let mut decoder = bencode::IncrementalDecoder::with_options(opts);
+    assert_eq!(decoder.feed(b"d3:foo"), Ok(None));
+    assert_eq!(decoder.feed(b"67108864:"),
+               Err(bencode::ParseError::BytestringTooLong { pos: 6, len: 67108864 }));
+}
+
+#[test]
+fn preserve_order() {
+    let opts = bencode::DecodeOptions { preserve_order: true, ..Default::default() };
+    let s = b"d1:ai1e1:ai2ee";
+    let val = bencode::decode_with_options(s, &opts).unwrap();
+    assert_eq!(val, OrderedDict(vec!((b"a".to_vec(), Integer(1)), (b"a".to_vec(), Integer(2)))));
+    assert_eq!(val.to_bytes(), s.to_vec());
+    assert_eq!(bencode::decode(s).unwrap().to_bytes(), b"d1:ai2ee".to_vec());
+
+    let s = b"d1:bld1:zi1e1:yi2eee1:ai3ee";
+    let val = bencode::decode_with_options(s, &opts).unwrap();
+    let mut v = Vec::new();
+    val.encode_to(&mut v).unwrap();
+    assert_eq!(v, s.to_vec());
+
+    let (_, warnings) = bencode::decode_lenient_with_options(b"d1:ai1e1:ai2ee", &opts).unwrap();
+    assert_eq!(warnings, vec!(bencode::Warning::DuplicateKey { pos: 7 }));
+}
+
+#[test]
+fn max_elements() {
+    let opts = bencode::DecodeOptions {
+        max_elements: Some(3),
+        ..Default::default()
+    };
+    assert!(bencode::decode_with_options(b"ld1:ai1eei2ee", &opts).is_ok());
+    assert_eq!(bencode::decode_with_options(b"ld1:ai1eei2ei3ee", &opts),
+               Err(bencode::ParseError::TooManyElements { pos: 12 }));
+    // counted across nested containers
+    assert_eq!(bencode::decode_with_options(b"llllleeeee", &opts),
+               Err(bencode::ParseError::TooManyElements { pos: 4 }));
+
+    let opts = bencode::DecodeOptions { max_elements: None, ..Default::default() };
+    assert!(bencode::decode_with_options(b"li1ei2ei3ei4ee", &opts).is_ok());
+    assert_eq!(bencode::DecodeOptions::default().max_elements,
+               Some(bencode::DEFAULT_MAX_ELEMENTS));
+}
+
+#[test]
+fn max_total_allocated() {
+    let opts = bencode::DecodeOptions {
+        max_total_allocated: Some(5),
+        ..Default::default()
+    };
+    assert!(bencode::decode_with_options(b"5:hello", &opts).is_ok());
+    assert_eq!(bencode::decode_with_options(b"6:hello!", &opts),
+               Err(bencode::ParseError::MemoryLimitExceeded { pos: 0 }));
+
+    // each element also counts
+    let opts = bencode::DecodeOptions {
+        max_total_allocated: Some(2 * bencode::ELEMENT_OVERHEAD + 3),
+        ..Default::default()
+    };
+    assert!(bencode::decode_with_options(b"l3:abci1ee", &opts).is_ok());
+    assert_eq!(bencode::decode_with_options(b"l3:abc3:abce", &opts),
+               Err(bencode::ParseError::MemoryLimitExceeded { pos: 6 }));
+    // and so do dict keys
+    assert_eq!(bencode::decode_with_options(b"d3:abc3:abc3:abdi1ee", &opts),
+               Err(bencode::ParseError::MemoryLimitExceeded { pos: 11 }));
+}
+
+#[test]
+fn max_work_units() {
+    let opts = bencode::DecodeOptions {
+        max_work_units: Some(8),
+        ..Default::default()
+    };
+    // two elements and six bytes
+    assert!(bencode::decode_with_options(b"l3:abc3:abce", &opts).is_ok());
+    assert_eq!(bencode::decode_with_options(b"l3:abc3:abci1ee", &opts),
+               Err(bencode::ParseError::WorkBudgetExceeded { pos: 11 }));
+    // deep nesting costs the same as wide lists
+    assert_eq!(bencode::decode_with_options(b"lllllllllleeeeeeeeee", &opts),
+               Err(bencode::ParseError::WorkBudgetExceeded { pos: 9 }));
+    assert_eq!(bencode::decode_with_options(b"9:abcdefghi", &opts),
+               Err(bencode::ParseError::WorkBudgetExceeded { pos: 0 }));
+    assert_eq!(bencode::validate_with_options(b"d3:abc6:abcdefe", &opts),
+               Err(bencode::ParseError::WorkBudgetExceeded { pos: 6 }));
+}
+
+#[test]
+fn lowercase_keys() {
+    let opts = bencode::DecodeOptions {
+        lowercase_keys: true,
+        ..Default::default()
+    };
+    let s = b"d8:Announce3:url4:INFOd4:Namei1eee";
+    let val = bencode::decode_with_options(s, &opts).unwrap();
+    assert_eq!(val.get(b"announce"), Some(&Bytestring(b"url".to_vec())));
+    assert_eq!(val.get(b"info").and_then(|info| info.get(b"name")), Some(&Integer(1)));
+
+    // keys that only differ in case collide
+    let s = b"d1:Ai1e1:ai2ee";
+    let (val, warnings) = bencode::decode_lenient_with_options(s, &opts).unwrap();
+    assert_eq!(val, bencode::parse(b"d1:ai2ee"));
+    assert_eq!(warnings, vec!(bencode::Warning::DuplicateKey { pos: 7 }));
+
+    let val = bencode::decode(b"d8:Announce3:urle").unwrap();
+    assert_eq!(val.get(b"announce"), None);
+}
+
+#[test]
+fn duplicate_keys_collect() {
+    let opts = bencode::DecodeOptions {
+        duplicate_keys: bencode::DuplicateKeyPolicy::Collect,
+        ..Default::default()
+    };
+    let s = b"d1:ai1e1:bi2e1:ai3e1:ali4eee";
+    let val = bencode::decode_with_options(s, &opts).unwrap();
+    assert_eq!(val, bencode::parse(b"d1:ali1ei3eli4eee1:bi2ee"));
+
+    let (val, warnings) = bencode::decode_lenient_with_options(s, &opts).unwrap();
+    assert_eq!(val.get(b"b"), Some(&Integer(2)));
+    assert_eq!(warnings, vec!(bencode::Warning::UnsortedKey { pos: 13 },
+                              bencode::Warning::DuplicateKey { pos: 13 },
+                              bencode::Warning::DuplicateKey { pos: 19 }));
+
+    // the default keeps the last value
+    let val = bencode::decode(b"d1:ai1e1:ai2ee").unwrap();
+    assert_eq!(val.get(b"a"), Some(&Integer(2)));
+}
+
+#[test]
+fn integer_range() {
+    let opts = bencode::DecodeOptions {
+        integer_range: Some((0, 65535)),
+        ..Default::default()
+    };
+    assert!(bencode::decode_with_options(b"li0ei65535ee", &opts).is_ok());
+    assert_eq!(bencode::decode_with_options(b"d4:porti65536ee", &opts),
+               Err(bencode::ParseError::IntegerOutOfRange { pos: 8, value: 65536 }));
+    assert_eq!(bencode::decode_with_options(b"li-1ee", &opts),
+               Err(bencode::ParseError::IntegerOutOfRange { pos: 3, value: -1 }));
+    assert!(bencode::decode(b"i-1e").is_ok());
+}
+
+#[test]
+fn decode_with_len() {
+    // keys out of order, so a re-encoding would differ from the input
+    let s = b"d1:bi1e1:ai2eetrailing";
+    let (val, len) = bencode::decode_with_len(s).unwrap();
+    assert_eq!(&s[..len], b"d1:bi1e1:ai2ee");
+    assert!(val.to_bytes() != s[..len]);
+}
+
+#[test]
+fn decode_with_trailer() {
+    let s = b"d1:ai1eeSIG!";
+    assert_eq!(bencode::decode_with_trailer(s, 4),
+               Ok((bencode::parse(b"d1:ai1ee"), &b"SIG!"[..])));
+    assert_eq!(bencode::decode_with_trailer(b"i1e", 0), Ok((Integer(1), &b""[..])));
+    assert_eq!(bencode::decode_with_trailer(s, 6),
+               Err(bencode::ParseError::Incomplete { needed: Some(2) }));
+    assert_eq!(bencode::decode_with_trailer(s, 3),
+               Err(bencode::ParseError::TrailingData { pos: 11 }));
+    assert_eq!(bencode::decode_with_trailer(b"d1:ai1", 0),
+               Err(bencode::ParseError::Incomplete { needed: None }));
+}
+
+#[test]
+fn error_context() {
+    let s = b"d1:aixee";
+    let err = bencode::decode(s).unwrap_err();
+    assert_eq!(err.context(s),
+               "unexpected byte 0x78 at offset 5\n\
+                00000000  64 31 3a 61 69 78 65 65                          |d1:aixee|\n\
+                \x20                        ^^");
+
+    let s = b"d8:announce3:url4:infod6:lengthi1e4:name1:a_e";
+    let err = bencode::decode(s).unwrap_err();
+    assert_eq!(err.context(s),
+               "unexpected byte 0x5f at offset 43\n\
+                00000023  3a 6e 61 6d 65 31 3a 61 5f 65                    |:name1:a_e|\n\
+                \x20                                 ^^");
+
+    let s = b"l1:a";
+    assert_eq!(bencode::decode(s).unwrap_err().context(s),
+               "unexpected end of input\n\
+                00000000  6c 31 3a 61                                      |l1:a|\n\
+                \x20                     ^^");
+
+    // an error from a longer buffer marks the end of the one given
+    let err = bencode::ParseError::UnexpectedByte { pos: 100, byte: b'x' };
+    assert_eq!(err.context(s),
+               "unexpected byte 0x78 at offset 100\n\
+                00000000  6c 31 3a 61                                      |l1:a|\n\
+                \x20                     ^^");
+    assert!(err.context(b"").starts_with("unexpected byte 0x78 at offset 100\n00000000"));
+}
+
+#[test]
+fn decode_dict() {
+    assert_eq!(bencode::decode_dict(b"d1:ai1ee"), Ok(bencode::parse(b"d1:ai1ee")));
+    assert_eq!(bencode::decode_dict(b"li1ee"), Err(bencode::ParseError::ExpectedDict));
+    assert_eq!(bencode::decode_dict(b"d1:a"), Err(bencode::ParseError::Incomplete { needed: None }));
+}
+
+#[test]
+fn trim_leading_junk() {
+    let s = b"\xef\xbb\xbf\r\nd1:ai1ee";
+    assert!(bencode::decode(s).is_err());
+
+    let opts = bencode::DecodeOptions { trim_leading_junk: true, ..Default::default() };
+    assert_eq!(bencode::decode_with_options(s, &opts), Ok(bencode::parse(b"d1:ai1ee")));
+    assert_eq!(bencode::decode_with_options(b" i1e", &opts), Ok(Integer(1)));
+    // only before the value
+    assert!(bencode::decode_with_options(b"d 1:ai1ee", &opts).is_err());
+    assert!(bencode::decode_with_options(b"i1e\n", &opts).is_err());
+}
+
+#[test]
+fn allow_trailing_newline() {
+    let opts = bencode::DecodeOptions { allow_trailing_newline: true, ..Default::default() };
+    assert!(bencode::decode(b"d1:ai1ee\n").is_err());
+    assert_eq!(bencode::decode_with_options(b"d1:ai1ee\n", &opts), Ok(bencode::parse(b"d1:ai1ee")));
+    assert_eq!(bencode::decode_with_options(b"i1e\r\n \n", &opts), Ok(Integer(1)));
+    assert_eq!(bencode::decode_with_options(b"i1e\nx", &opts),
+               Err(bencode::ParseError::TrailingData { pos: 4 }));
+    // only after the value
+    assert!(bencode::decode_with_options(b"\ni1e", &opts).is_err());
+    assert!(bencode::decode_with_options(b"l\ni1ee", &opts).is_err());
+}
+
+#[test]
+fn decode_str() {
+    assert_eq!(bencode::decode_str("l3:fooi1ee"), Ok(bencode::parse(b"l3:fooi1ee")));
+    assert_eq!(bencode::decode_str("i1"), Err(bencode::ParseError::Incomplete { needed: None }));
+}
+
+#[test]
+fn skip_value() {
+    let s = b"d3:fooli1ei2ee3:bar3:bazei42e";
+    assert_eq!(bencode::skip_value(s, 0), Ok(25));
+    assert_eq!(bencode::skip_value(s, 25), Ok(s.len()));
+    assert_eq!(bencode::skip_value(s, 5), Err(bencode::ParseError::UnexpectedByte {
+        pos: 5,
+        byte: b'o',
+    }));
+    assert_eq!(bencode::skip_value(b"l1:a", 0), Err(bencode::ParseError::Incomplete { needed: None }));
+}
+
+#[test]
+fn validate() {
+    assert_eq!(bencode::validate(b"d3:fooli1ei2ee3:bar3:baze"), Ok(25));
+    assert_eq!(bencode::validate(b"i1ei2e"), Err(bencode::ParseError::TrailingData { pos: 3 }));
+    assert_eq!(bencode::validate(b"l1:a"), Err(bencode::ParseError::Incomplete { needed: None }));
+
+    let opts = bencode::DecodeOptions {
+        max_elements: Some(2),
+        trim_leading_junk: true,
+        allow_trailing_newline: true,
+        ..Default::default()
+    };
+    assert_eq!(bencode::validate_with_options(b" li1ei2ee\n", &opts), Ok(8));
+    assert_eq!(bencode::validate_with_options(b"li1ei2ei3ee", &opts),
+               bencode::decode_with_options(b"li1ei2ei3ee", &opts).map(|_| 0));
+    let opts = bencode::DecodeOptions { max_bytestring_len: Some(2), ..Default::default() };
+    assert_eq!(bencode::validate_with_options(b"d1:a3:fooe", &opts),
+               Err(bencode::ParseError::BytestringTooLong { pos: 4, len: 3 }));
+}
+
+#[test]
+fn key_occurrences() {
+    let counts = bencode::key_occurrences(b"d1:ai1e1:bd1:ai1ee1:ai2ee").unwrap();
+    assert_eq!(counts.len(), 2);
+    assert_eq!(counts[&b"a".to_vec()], 2);
+    assert_eq!(counts[&b"b".to_vec()], 1);
+
+    assert_eq!(bencode::key_occurrences(b"li1ee"),
+               Err(bencode::ParseError::UnexpectedByte { pos: 0, byte: b'l' }));
+    assert_eq!(bencode::key_occurrences(b"dei1e"),
+               Err(bencode::ParseError::TrailingData { pos: 2 }));
+}
+
+#[test]
+fn decode_with_callback() {
+    let mut nodes = Vec::new();
+    let val = bencode::decode_with_callback(b"d1:ali1e2:bce1:bi2ee", |val, pos| {
+        nodes.push((val.to_bytes(), pos));
+    }).unwrap();
+    assert_eq!(val, bencode::parse(b"d1:ali1e2:bce1:bi2ee"));
+    let expected: Vec<(&[u8], usize)> = vec!((b"i1e", 5), (b"2:bc", 8), (b"li1e2:bce", 4),
+                                              (b"i2e", 16), (b"d1:ali1e2:bce1:bi2ee", 0));
+    let expected: Vec<_> = expected.into_iter().map(|(v, pos)| (v.to_vec(), pos)).collect();
+    assert_eq!(nodes, expected);
+
+    assert!(bencode::decode_with_callback(b"i1ei2e", |_, _| {}).is_err());
+}
+
+#[test]
+fn decode_map_bytestrings() {
+    let upper = |v: &[u8]| v.to_ascii_uppercase();
+    let s = b"d1:al3:fooi1ee1:b3:bare";
+    let val = bencode::decode_map_bytestrings(s, upper).unwrap();
+    assert_eq!(val, bencode::parse(b"d1:Al3:FOOi1ee1:B3:BARe"));
+    let val = bencode::decode_map_values(s, upper).unwrap();
+    assert_eq!(val, bencode::parse(b"d1:al3:FOOi1ee1:b3:BARe"));
+
+    let mut seen = Vec::new();
+    bencode::decode_map_bytestrings(b"ld1:k1:xe1:ye", |v| {
+        seen.push(v.to_vec());
+        Vec::new()
+    }).unwrap();
+    assert_eq!(seen, vec!(b"k".to_vec(), b"x".to_vec(), b"y".to_vec()));
+    let mut seen = Vec::new();
+    bencode::decode_map_values(b"ld1:k1:xe1:ye", |v| {
+        seen.push(v.to_vec());
+        Vec::new()
+    }).unwrap();
+    assert_eq!(seen, vec!(b"x".to_vec(), b"y".to_vec()));
+    assert!(bencode::decode_map_bytestrings(b"l3:foe", upper).is_err());
+}
+
+#[test]
+fn scalar_fast_path_matches_general_path() {
+    // `decode_with_callback` never takes the fast path for scalars in lists
+    // and dicts, so the two must agree on every input, valid or not
+    let samples: &[&[u8]] = &[b"d1:ad2:id20:abcdefghij0123456789e1:q4:ping1:t2:aa1:y1:qe",
+                              b"li1ei-2e3:fooli3eed1:xi0eee", b"d1:ai01e1:b0:e", b"l3:foe"];
+    let mut seed: u64 = 1;
+    for sample in samples {
+        for _ in 0..200 {
+            let mut s = sample.to_vec();
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            let n = (seed >> 33) as usize;
+            s[n % sample.len()] = b"ilde0123456789:-x"[n / sample.len() % 17];
+            assert_eq!(bencode::decode(&s), bencode::decode_with_callback(&s, |_, _| {}),
+                       "{:?}", String::from_utf8_lossy(&s));
+        }
+    }
+}
+
+#[test]
+fn decode_length_prefixed() {
+    let s = b"\x00\x00\x00\x08d1:ai1eenext";
+    assert_eq!(bencode::decode_length_prefixed(s), Ok((bencode::parse(b"d1:ai1ee"), 12)));
+
+    assert_eq!(bencode::decode_length_prefixed(b"\x00\x00"),
+               Err(bencode::ParseError::Incomplete { needed: Some(2) }));
+    assert_eq!(bencode::decode_length_prefixed(b"\x00\x00\x00\x08d1:a"),
+               Err(bencode::ParseError::Incomplete { needed: Some(4) }));
+    assert_eq!(bencode::decode_length_prefixed(b"\x00\x00\x00\x06i1ei2e"),
+               Err(bencode::ParseError::TrailingData { pos: 7 }));
+    // a value running past the frame is cut short by it
+    assert_eq!(bencode::decode_length_prefixed(b"\x00\x00\x00\x02i1e"),
+               Err(bencode::ParseError::Incomplete { needed: None }));
+}
+
+#[test]
+fn decode_with_meta() {
+    let (val, meta) = bencode::decode_with_meta(b"d1:ali1eee1:btrailing").unwrap();
+    assert_eq!(val, bencode::parse(b"d1:ali1eee"));
+    assert_eq!(meta, bencode::DecodeMeta {
+        was_canonical: true,
+        bytes_consumed: 10,
+        max_depth: 2,
+    });
+
+    let inputs: Vec<&[u8]> = vec!(b"i-0e", b"d1:bi1e1:ai2ee", b"d1:ai1e1:ai2ee", b"li1ed1:ai-0eee");
+    for s in inputs {
+        let (_, meta) = bencode::decode_with_meta(s).unwrap();
+        assert!(!meta.was_canonical, "{:?}", s);
+        assert_eq!(meta.was_canonical, bencode::is_canonical(s));
+    }
+    assert_eq!(bencode::decode_with_meta(b"3:foo").unwrap().1.max_depth, 0);
+}
+
+#[test]
+fn top_level_entry_bytes() {
+    // the original bytes, even where a re-encoding would sort the keys
+    let s = b"d4:infod4:name1:x6:lengthi1ee1:ai1ee";
+    let entries = bencode::top_level_entry_bytes(s).unwrap();
+    assert_eq!(entries, vec!((b"info".to_vec(), b"d4:name1:x6:lengthi1ee".to_vec()),
+                             (b"a".to_vec(), b"i1e".to_vec())));
+    assert_eq!(bencode::top_level_entry_bytes(b"de"), Ok(vec!()));
+    assert_eq!(bencode::top_level_entry_bytes(b"i1e"),
+               Err(bencode::ParseError::UnexpectedByte { pos: 0, byte: b'i' }));
+}
+
+#[test]
+fn field_bytes() {
+    let s = b"d8:announce3:url4:infod4:name1:x6:lengthi1eee";
+    assert_eq!(bencode::field_bytes(s, b"info"), Ok(Some(&b"d4:name1:x6:lengthi1ee"[..])));
+    assert_eq!(bencode::field_bytes(s, b"announce"), Ok(Some(&b"3:url"[..])));
+    assert_eq!(bencode::field_bytes(s, b"comment"), Ok(None));
+    assert_eq!(bencode::field_bytes(b"d1:ai1e1:ai2ee", b"a"), Ok(Some(&b"i2e"[..])));
+    assert_eq!(bencode::field_bytes(b"d1:ai1eex", b"a"),
+               Err(bencode::ParseError::TrailingData { pos: 8 }));
+    assert_eq!(bencode::field_bytes(b"le", b"a"),
+               Err(bencode::ParseError::UnexpectedByte { pos: 0, byte: b'l' }));
+}
+
+#[test]
+fn decode_selected() {
+    let s = b"d8:announce3:url4:infod6:lengthi1e4:name1:x6:pieces3:abce5:otherli1eee";
+    let found = bencode::decode_selected(s, &[&[b"info", b"name"], &[b"announce"],
+                                              &[b"info", b"missing"], &[b"other", b"x"]])
+                    .unwrap();
+    assert_eq!(found.len(), 2);
+    assert_eq!(found[&vec!(b"info".to_vec(), b"name".to_vec())], Bytestring(b"x".to_vec()));
+    assert_eq!(found[&vec!(b"announce".to_vec())], Bytestring(b"url".to_vec()));
+
+    // Paths may lead into one another, and the empty path is the whole value
+    let found = bencode::decode_selected(s, &[&[b"info"], &[b"info", b"length"], &[]]).unwrap();
+    assert_eq!(found[&vec!(b"info".to_vec(), b"length".to_vec())], Integer(1));
+    assert_eq!(found[&Vec::new()], bencode::parse(s));
+
+    // A repeated key keeps its last value, as `decode` does
+    let found = bencode::decode_selected(b"d1:ai1e1:ai2e1:bi3ee", &[&[b"a"], &[b"b"]]).unwrap();
+    assert_eq!(found[&vec!(b"a".to_vec())], Integer(2));
+    assert_eq!(found[&vec!(b"b".to_vec())], Integer(3));
+    let s = b"d4:infod4:name1:xe4:infod4:name1:yee";
+    let found = bencode::decode_selected(s, &[&[b"info"], &[b"info", b"name"]]).unwrap();
+    assert_eq!(found[&vec!(b"info".to_vec())], bencode::parse(s).get(b"info").unwrap().clone());
+    assert_eq!(found[&vec!(b"info".to_vec(), b"name".to_vec())], Bytestring(b"y".to_vec()));
+    let s = b"d4:infod4:name1:xe4:infoi1ee";
+    let found = bencode::decode_selected(s, &[&[b"info", b"name"]]).unwrap();
+    assert!(found.is_empty());
+
+    // Everything is checked, even after every path has been found
+    assert_eq!(bencode::decode_selected(b"d1:ai1e1:bxxx", &[&[b"a"]]),
+               Err(bencode::ParseError::UnexpectedByte { pos: 10, byte: b'x' }));
+    assert_eq!(bencode::decode_selected(b"d1:ai1e1:bxxx", &[&[b"c"]]),
+               Err(bencode::ParseError::UnexpectedByte { pos: 10, byte: b'x' }));
+    assert_eq!(bencode::decode_selected(b"d1:ai1eex", &[&[b"c"]]),
+               Err(bencode::ParseError::TrailingData { pos: 8 }));
+}
+
+#[test]
+fn decode_from() {
+    let s = b"HDR\x00d1:ai1eetrailing";
+    assert_eq!(bencode::decode_from(s, 4), Ok((bencode::parse(b"d1:ai1ee"), 12)));
+    assert_eq!(bencode::decode_from(s, 5), Ok((Bytestring(b"a".to_vec()), 8)));
+    assert_eq!(bencode::decode_from(s, 3),
+               Err(bencode::ParseError::UnexpectedByte { pos: 3, byte: 0 }));
+    assert_eq!(bencode::decode_from(s, s.len()), Err(bencode::ParseError::Incomplete { needed: None }));
+    assert_eq!(bencode::decode_from(s, 100), Err(bencode::ParseError::Incomplete { needed: None }));
+}
+
+#[test]
+fn decode_allow_whitespace() {
+    let s = b"d\n  3:bar l i1e i2e e\r\n  3:foo i42e\ne\n";
+    assert!(bencode::decode(s).is_err());
+
+    let opts = bencode::DecodeOptions { allow_whitespace: true, ..Default::default() };
+    assert_eq!(bencode::decode_with_options(s, &opts).unwrap(),
+               bencode::parse(b"d3:barli1ei2ee3:fooi42ee"));
+
+    // whitespace is only skipped between tokens, not inside them
+    assert!(bencode::decode_with_options(b"i4 2e", &opts).is_err());
+    assert!(bencode::decode_with_options(b"3 :foo", &opts).is_err());
+}
+
+fn dict(pairs: Vec<(&str, bencode::Bencoded)>) -> bencode::Bencoded {
+    let mut m = HashMap::new();
+    for (key, val) in pairs {
+        m.insert(key.as_bytes().to_vec(), val);
+    }
+    Dict(m)
+}
+
+fn bytes(s: &str) -> bencode::Bencoded {
+    Bytestring(s.as_bytes().to_vec())
+}
+
+fn multi_file_torrent() -> bencode::Bencoded {
+    let files = List(vec!(
+        dict(vec!(("length", Integer(1024)),
+                  ("path", List(vec!(bytes("README")))))),
+        dict(vec!(("length", Integer(70000)),
+                  ("path", List(vec!(bytes("src"), bytes("main.rs")))))),
+        dict(vec!(("length", Integer(0)),
+                  ("path", List(vec!(bytes("src"), bytes("lib"), bytes("mod.rs")))))),
+    ));
+    let info = dict(vec!(
+        ("files", files),
+        ("name", bytes("project")),
+        ("piece length", Integer(32768)),
+        ("pieces", Bytestring((0..60).collect())),
+    ));
+    dict(vec!(
+        ("announce", bytes("http://tracker.example/announce")),
+        ("announce-list", List(vec!(List(vec!(bytes("http://tracker.example/announce"))),
+                                    List(vec!(bytes("udp://backup.example:80")))))),
+        ("creation date", Integer(1262304000)),
+        ("info", info),
+    ))
+}
+
+#[test]
+fn nested_torrent_round_trip() {
+    let torrent = multi_file_torrent();
+    let encoded = torrent.to_bytes();
+    let decoded = bencode::decode(&encoded).unwrap();
+    assert_eq!(decoded, torrent);
+    assert_eq!(decoded.to_bytes(), encoded);
+
+    // the info hash is computed over the encoded info dict, so it must be
+    // unchanged by the round trip
+    let info = decoded.get(b"info").unwrap();
+    assert_eq!(info.to_bytes(), torrent.get(b"info").unwrap().to_bytes());
+    #[cfg(feature = "torrent")]
+    {
+        use sha1::{Digest, Sha1};
+
+        let info_bytes = bencode::field_bytes(&encoded, b"info").unwrap().unwrap();
+        assert_eq!(decoded.info_hash(), torrent.info_hash());
+        assert_eq!(decoded.info_hash(), Some(Sha1::digest(info_bytes).into()));
+    }
+
+    let files = info.get(b"files").unwrap();
+    assert_eq!(files.to_string(),
+               "ld6:lengthi1024e4:pathl6:READMEeed6:lengthi70000e4:pathl3:src7:main.rseed6:lengthi0e4:pathl3:src3:lib6:mod.rseee");
+}
+
+#[test]
+fn canonical_corpus() {
+    use bencode::ParseError::NotCanonical;
+
+    let torrent = multi_file_torrent().to_bytes();
+    let corpus: Vec<&[u8]> = vec!(
+        &torrent,
+        b"d8:announce3:url4:infod6:lengthi10e4:name1:a12:piece lengthi16384e6:pieces0:ee",
+        b"i0e",
+        b"i-1e",
+        b"0:",
+        b"le",
+        b"de",
+        // not canonical
+        b"i03e",
+        b"i-0e",
+        b"03:abc",
+        b"d1:bi1e1:ai2ee",
+        b"d1:ai1e1:ai2ee",
+        b"d8:announce3:url4:infod4:name1:a6:lengthi10eee",
+        b"i1ei2e",
+    );
+
+    for s in corpus {
+        assert_eq!(bencode::is_canonical(s), bencode::assert_roundtrip(s).is_ok(),
+                   "disagreement on {:?}", String::from_utf8_lossy(s));
+        if let Ok(same) = bencode::would_reencode_identically(s) {
+            assert_eq!(bencode::is_canonical(s), same,
+                       "disagreement on {:?}", String::from_utf8_lossy(s));
+        }
+    }
+
+    assert!(bencode::is_canonical(&torrent));
+    assert_eq!(bencode::assert_roundtrip(b"i-0e"), Err(NotCanonical { pos: 1 }));
+    assert_eq!(bencode::assert_roundtrip(b"d1:bi1e1:ai2ee"), Err(NotCanonical { pos: 3 }));
+}
+
+#[test]
+fn reencode_difference() {
+    assert_eq!(bencode::would_reencode_identically(b"d1:ai1e1:bi2ee"), Ok(true));
+    assert_eq!(bencode::would_reencode_identically(b"d1:bi1e1:ai2ee"), Ok(false));
+    assert_eq!(bencode::reencode_difference(b"d1:ai1e1:bi2ee"), Ok(None));
+    assert_eq!(bencode::reencode_difference(b"d1:bi1e1:ai2ee"), Ok(Some(3)));
+    assert_eq!(bencode::reencode_difference(b"i-0e"), Ok(Some(1)));
+    // the repeated `a` is dropped
+    assert_eq!(bencode::reencode_difference(b"d1:ai1e1:ai1ee"), Ok(Some(7)));
+    assert_eq!(bencode::would_reencode_identically(b"i03e"),
+               Err(bencode::ParseError::UnexpectedByte { pos: 2, byte: b'3' }));
+}
+
+#[test]
+fn compare_with_rust_types() {
+    let b = bencode::parse(b"d6:lengthi1024e4:name3:fooe");
+    assert_eq!(b.get(b"length").unwrap(), &1024i64);
+    assert!(*b.get(b"length").unwrap() != 1023);
+    assert_eq!(*b.get(b"name").unwrap(), "foo");
+    assert_eq!(*b.get(b"name").unwrap(), b"foo"[..]);
+
+    // the variant has to match too
+    assert!(Bytestring(b"1024".to_vec()) != 1024);
+    assert!(Integer(1) != "1");
+}